@@ -1,6 +1,8 @@
 use std::fmt::Display;
 
-use crate::{reduction::Evaluate, typecheck::TypeCheck, typing::Type};
+use crate::constants::RuntimeError;
+use crate::span::Span;
+use crate::typing::{IntType, Type};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 
@@ -16,11 +18,10 @@ impl Display for Reference {
     }
 }
 
-pub trait AST: Evaluate + TypeCheck {}
-
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Value {
-    NumericLiteral(i64),
+    NumericLiteral(i128, Option<IntType>), // None without a suffix, then i64 or the other operand's type
+    Bool(bool),
     Reference(Reference),
     Epsilon,
     Undefined,
@@ -29,7 +30,8 @@ pub enum Value {
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Value::NumericLiteral(n) => write!(f, "{}", n),
+            Value::NumericLiteral(n, _) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
             Value::Reference(r) => write!(f, "ref {}", r.location),
             Value::Epsilon => write!(f, "Epsilon"),
             Value::Undefined => write!(f, "Undefined"),
@@ -37,14 +39,49 @@ impl Display for Value {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl BinOp {
+    pub fn is_comparison(&self) -> bool {
+        !matches!(self, BinOp::Add | BinOp::Sub)
+    }
+}
+
+impl Display for BinOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BinOp::Add => write!(f, "+"),
+            BinOp::Sub => write!(f, "-"),
+            BinOp::Eq => write!(f, "=="),
+            BinOp::Ne => write!(f, "!="),
+            BinOp::Lt => write!(f, "<"),
+            BinOp::Gt => write!(f, ">"),
+            BinOp::Le => write!(f, "<="),
+            BinOp::Ge => write!(f, ">="),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum LVal {
     Variable {
         name: String,
         copyable: Option<bool>,
+        span: Span,
     },
     Deref {
         var: Box<LVal>,
+        span: Span,
     },
 }
 
@@ -52,70 +89,384 @@ impl LVal {
     pub fn get_name(&self) -> String {
         match self {
             LVal::Variable { name, .. } => name.clone(),
-            LVal::Deref { var } => var.get_name().clone(),
+            LVal::Deref { var, .. } => var.get_name().clone(),
         }
     }
-    pub fn is_copyable(&self) -> Result<bool, String> {
+    pub fn is_copyable(&self) -> Result<bool, RuntimeError> {
         match self {
-            LVal::Variable { copyable, .. } => match copyable {
+            LVal::Variable { name, copyable, .. } => match copyable {
                 Some(c) => Ok(*c),
-                None => Err(
-                    "Tried to access copyable field of variable, however it was  before it was set"
-                        .to_string(),
-                ),
+                None => Err(RuntimeError::NotTypeChecked(name.clone())),
             },
-            LVal::Deref { var } => var.is_copyable(),
+            LVal::Deref { var, .. } => var.is_copyable(),
+        }
+    }
+    pub fn span(&self) -> Span {
+        match self {
+            LVal::Variable { span, .. } | LVal::Deref { span, .. } => *span,
+        }
+    }
+    // whether both name the same place, wherever they were written
+    pub fn same_place(&self, other: &LVal) -> bool {
+        match (self, other) {
+            (LVal::Variable { name: a, .. }, LVal::Variable { name: b, .. }) => a == b,
+            (LVal::Deref { var: a, .. }, LVal::Deref { var: b, .. }) => a.same_place(b),
+            _ => false,
+        }
+    }
+    pub fn with_span(mut self, span: Span) -> LVal {
+        match &mut self {
+            LVal::Variable { span: s, .. } | LVal::Deref { span: s, .. } => *s = span,
         }
+        return self;
     }
     pub fn set_copyable(&mut self, copyable: bool) {
         match self {
             LVal::Variable { copyable: c, .. } => *c = Some(copyable),
-            LVal::Deref { var } => var.set_copyable(copyable),
+            LVal::Deref { var, .. } => var.set_copyable(copyable),
+        }
+    }
+    fn clear_spans(&mut self) {
+        match self {
+            LVal::Variable { span, .. } => *span = Span::default(),
+            LVal::Deref { var, span } => {
+                var.clear_spans();
+                *span = Span::default();
+            }
+        }
+    }
+}
+
+impl Display for LVal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LVal::Variable { name, .. } => write!(f, "{}", name),
+            LVal::Deref { var, .. } => write!(f, "*{}", var),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Program {
     pub terms: Vec<Term>,
 }
 
-#[derive(Debug, Clone)]
+impl Program {
+    /// The program with every span cleared, to compare two parses of source laid
+    /// out differently.
+    pub fn without_spans(&self) -> Program {
+        let mut program = self.clone();
+        for term in &mut program.terms {
+            term.clear_spans();
+        }
+        return program;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Term {
     Variable(LVal),
-    Value(Value),
+    Value(Value, Option<Span>), // no span for the result of reducing a term
     Box {
         term: Box<Term>,
+        span: Span,
+    },
+    BinaryOp {
+        op: BinOp,
+        lhs: Box<Term>,
+        rhs: Box<Term>,
+        span: Span,
     },
     Ref {
         mutable: bool,
         var: LVal,
+        span: Span,
     },
     Let {
         mutable: bool,
         variable: LVal,
         term: Box<Term>,
+        span: Span,
     },
     Assign {
         variable: LVal,
         term: Box<Term>,
+        span: Span,
     },
     FunctionCall {
         name: String,
         params: Vec<Term>,
+        span: Span,
     },
     FunctionDeclaration {
         name: String,
         args: Vec<Argument>,
         body: Vec<Term>,
         ty: Option<Type>,
+        span: Span,
+    },
+    TraitDeclaration {
+        name: String,
+        methods: Vec<MethodSignature>,
+        span: Span,
+    },
+    Impl {
+        trait_name: Option<String>, // None for inherent impls
+        ty: Type,
+        methods: Vec<Term>,
+        span: Span,
+    },
+    MethodCall {
+        receiver: LVal,
+        method: String,
+        params: Vec<Term>,
+        function: Option<String>, // resolved from the receiver type during type checking
+        span: Span,
+    },
+    Assert {
+        cond: Box<Term>,
+        span: Span,
+    },
+    Print {
+        term: Box<Term>,
+        span: Span,
     },
 }
 
-#[derive(Debug, Clone)]
+impl Term {
+    fn clear_spans(&mut self) {
+        let clear_args = |args: &mut Vec<Argument>| {
+            for arg in args {
+                arg.span = Span::default();
+            }
+        };
+        match self {
+            Term::Variable(var) => var.clear_spans(),
+            Term::Value(_, span) => *span = None,
+            Term::Box { term, span }
+            | Term::Assert { cond: term, span }
+            | Term::Print { term, span } => {
+                term.clear_spans();
+                *span = Span::default();
+            }
+            Term::BinaryOp { lhs, rhs, span, .. } => {
+                lhs.clear_spans();
+                rhs.clear_spans();
+                *span = Span::default();
+            }
+            Term::Ref { var, span, .. } => {
+                var.clear_spans();
+                *span = Span::default();
+            }
+            Term::Let {
+                variable,
+                term,
+                span,
+                ..
+            }
+            | Term::Assign {
+                variable,
+                term,
+                span,
+            } => {
+                variable.clear_spans();
+                term.clear_spans();
+                *span = Span::default();
+            }
+            Term::FunctionCall { params, span, .. } => {
+                params.iter_mut().for_each(Term::clear_spans);
+                *span = Span::default();
+            }
+            Term::FunctionDeclaration {
+                args, body, span, ..
+            } => {
+                clear_args(args);
+                body.iter_mut().for_each(Term::clear_spans);
+                *span = Span::default();
+            }
+            Term::TraitDeclaration { methods, span, .. } => {
+                for method in methods {
+                    clear_args(&mut method.args);
+                }
+                *span = Span::default();
+            }
+            Term::Impl { methods, span, .. } => {
+                methods.iter_mut().for_each(Term::clear_spans);
+                *span = Span::default();
+            }
+            Term::MethodCall {
+                receiver,
+                params,
+                span,
+                ..
+            } => {
+                receiver.clear_spans();
+                params.iter_mut().for_each(Term::clear_spans);
+                *span = Span::default();
+            }
+        }
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            Term::Variable(var) => Some(var.span()),
+            Term::Value(_, span) => *span,
+            Term::Box { span, .. }
+            | Term::BinaryOp { span, .. }
+            | Term::Ref { span, .. }
+            | Term::Let { span, .. }
+            | Term::Assign { span, .. }
+            | Term::FunctionCall { span, .. }
+            | Term::FunctionDeclaration { span, .. }
+            | Term::TraitDeclaration { span, .. }
+            | Term::Impl { span, .. }
+            | Term::MethodCall { span, .. }
+            | Term::Assert { span, .. }
+            | Term::Print { span, .. } => Some(*span),
+        }
+    }
+}
+
+// renders terms back in source syntax, used to point at expressions in errors
+impl Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::Variable(var) => write!(f, "{}", var),
+            Term::Value(Value::NumericLiteral(n, Some(ty)), _) => write!(f, "{}{}", n, ty),
+            Term::Value(v, _) => write!(f, "{}", v),
+            Term::Box { term, .. } => write!(f, "box {}", term),
+            Term::BinaryOp { op, lhs, rhs, .. } => write!(f, "{} {} {}", lhs, op, rhs),
+            Term::Ref { mutable, var, .. } => {
+                write!(f, "{}ref {}", if *mutable { "mut " } else { "" }, var)
+            }
+            Term::Let {
+                mutable,
+                variable,
+                term,
+                ..
+            } => write!(
+                f,
+                "let {}{} = {}",
+                if *mutable { "mut " } else { "" },
+                variable,
+                term
+            ),
+            Term::Assign { variable, term, .. } => write!(f, "{} = {}", variable, term),
+            Term::FunctionCall { name, params, .. } => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}({})", name, params.join(", "))
+            }
+            Term::FunctionDeclaration {
+                name,
+                args,
+                body,
+                ty,
+                ..
+            } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                write!(f, "fn {}({})", name, args.join(", "))?;
+                if let Some(ty) = ty {
+                    write!(f, " : {}", ty.syntax())?;
+                }
+                writeln!(f, " {{")?;
+                for term in body {
+                    for line in term.to_string().lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Term::TraitDeclaration { name, methods, .. } => {
+                writeln!(f, "trait {} {{", name)?;
+                for method in methods {
+                    writeln!(f, "    {}", method)?;
+                }
+                write!(f, "}}")
+            }
+            Term::Impl {
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => {
+                match trait_name {
+                    Some(trait_name) => writeln!(f, "impl {} for {} {{", trait_name, ty.syntax())?,
+                    None => writeln!(f, "impl {} {{", ty.syntax())?,
+                }
+                for method in methods {
+                    for line in method.to_string().lines() {
+                        writeln!(f, "    {}", line)?;
+                    }
+                }
+                write!(f, "}}")
+            }
+            Term::MethodCall {
+                receiver,
+                method,
+                params,
+                ..
+            } => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "{}.{}({})", receiver, method, params.join(", "))
+            }
+            Term::Assert { cond, .. } => write!(f, "assert({})", cond),
+            Term::Print { term, .. } => write!(f, "print({})", term),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Argument {
     pub name: String,
     pub ty: Type,
     pub mutable: bool,
     pub reference: bool,
+    pub span: Span,
+}
+
+impl Display for Argument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            if self.mutable { "mut " } else { "" },
+            if self.reference { "ref " } else { "" },
+            self.name,
+        )?;
+        match self.ty {
+            Type::SelfAlias => Ok(()),
+            _ => write!(f, " : {}", self.ty.syntax()),
+        }
+    }
+}
+
+impl Argument {
+    // arguments agree when they are passed the same way, names do not matter
+    pub fn same_kind(&self, other: &Argument) -> bool {
+        self.ty == other.ty && self.mutable == other.mutable && self.reference == other.reference
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSignature {
+    pub name: String,
+    pub args: Vec<Argument>,
+    pub ty: Option<Type>,
+}
+
+impl MethodSignature {
+    pub fn receiver(&self) -> Option<&Argument> {
+        self.args.first().filter(|arg| arg.name == "self")
+    }
+}
+
+impl Display for MethodSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args: Vec<String> = self.args.iter().map(|a| a.to_string()).collect();
+        write!(f, "fn {}({})", self.name, args.join(", "))?;
+        match &self.ty {
+            Some(ty) => write!(f, " : {}", ty.syntax()),
+            None => Ok(()),
+        }
+    }
 }
@@ -0,0 +1,16 @@
+#![allow(clippy::needless_return)]
+
+// the debug adapter, speaking DAP over stdin and stdout
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    match lightweight_muse::dap::serve(&mut input, &mut output) {
+        Ok(()) => return ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("muse-dap: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+}
@@ -0,0 +1,16 @@
+#![allow(clippy::needless_return)]
+
+// the language server, speaking LSP over stdin and stdout
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let mut input = std::io::stdin().lock();
+    let mut output = std::io::stdout().lock();
+    match lightweight_muse::lsp::serve(&mut input, &mut output) {
+        Ok(code) => return ExitCode::from(code),
+        Err(e) => {
+            eprintln!("muse-lsp: {}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+}
@@ -1,4 +1,12 @@
-use crate::{ast::Term, typing::Type};
+use std::fmt::Display;
+
+use crate::{
+    ast::{BinOp, Term, Value},
+    span::Span,
+    token::TokenKind,
+    typing::{IntType, Type},
+};
+#[derive(Debug, Clone, PartialEq)]
 pub enum TypeError {
     FunctionCallIncompatableArgumentCount(usize, usize),
     FunctionCallIncompatableArgumentType(Type, Type),
@@ -11,32 +19,428 @@ pub enum TypeError {
     MutrefImmut(String),
     RefAlreadyBorrowedMut(String),
     LetAlreadyDefined(String),
-    LetExprNoReturn(Term),
+    LetExprNoReturn(Box<Term>),
     AssignBorrowed(String),
     IncompatibleTypes(Type, Type),
     NotWithinScope(String),
     TypeMoved(Type),
+    LiteralOutOfRange(i128, IntType),
+    TraitAlreadyDefined(String),
+    TraitNotDefined(String),
+    TraitMethodMissing(String, String),
+    TraitMethodMismatch(String, String),
+    MethodNotInTrait(String, String),
+    MethodMissingSelf(String),
+    MethodNotDefined(Type, String),
+    MethodAmbiguous(Type, String),
+    MethodAlreadyDefined(Type, String),
+    ImplAlreadyDefined(String, Type),
+    SelfOutsideImpl(String),
+    ExplicitBorrowRequired(String, bool),
+    VariableNotDefined(String),
+    NotAReference(String),
+    WriteThroughImmutableRef(String),
+    NotMutable(String),
+    MoveOutOfReference(String),
 }
 
 impl TypeError {
-    pub fn to_string(&self) -> String {
+    // the message without the "Type error" prefix, which diagnostics render with the code
+    pub fn description(&self) -> String {
+        return match self {
+            TypeError::FunctionCallIncompatableArgumentCount(a1, a2) => {
+                format!("Incompatible argument count: expected {}, got {}", a1, a2)
+            }
+            TypeError::FunctionCallIncompatableArgumentType(t1, t2) => {
+                format!("Incompatible argument type: expected {}, got {}", t1, t2)
+            }
+            TypeError::FunctionNotDefined(s) => format!("Function not defined: {}()", s),
+            TypeError::FunctionDeclDupArg(s) => {
+                format!("Duplicate argument in function declaration: {}", s)
+            }
+            TypeError::FunctionUnexpectedReturn(t1, t2) => {
+                format!("Unexpected return type: expected {}, got {}", t1, t2)
+            }
+            TypeError::CopyNotReadable(s) => {
+                format!("Cannot copy variable that is mutually borrowed: {}", s)
+            }
+            TypeError::MoveNotWritable(s) => format!("Cannot move variable that is borrowd: {}", s),
+            TypeError::MutrefAlreadyBorrowedImmut(s) => format!(
+                "Cannot create a mutable reference to {} as it's already borrowed immutably",
+                s
+            ),
+            TypeError::MutrefImmut(s) => format!(
+                "Mutable reference cannot be created from immutable reference: {}",
+                s
+            ),
+            TypeError::RefAlreadyBorrowedMut(s) => {
+                format!("Immutable reference already borrowed mutably: {}", s)
+            }
+            TypeError::LetAlreadyDefined(s) => format!("Variable already defined: {}", s),
+            TypeError::LetExprNoReturn(s) => {
+                format!("Let expression does not return a value: {}", s)
+            }
+            TypeError::IncompatibleTypes(t1, t2) => {
+                format!("Incompatible types: {} and {}", t1, t2)
+            }
+            TypeError::NotWithinScope(s) => format!("Type is not within scope: {}", s),
+            TypeError::AssignBorrowed(s) => format!("Cannot assign to borrowed reference: {}", s),
+            TypeError::TypeMoved(t1) => {
+                format!("Type of {} is undefined, indicating that it was moved", t1)
+            }
+            TypeError::LiteralOutOfRange(n, k) => format!(
+                "Literal {} is out of range for {} ({}..={})",
+                n,
+                k,
+                k.min(),
+                k.max()
+            ),
+            TypeError::TraitAlreadyDefined(s) => format!("Trait already defined: {}", s),
+            TypeError::TraitNotDefined(s) => format!("Trait not defined: {}", s),
+            TypeError::TraitMethodMissing(t, m) => {
+                format!("Missing method {}() from trait {}", m, t)
+            }
+            TypeError::TraitMethodMismatch(t, m) => {
+                format!("Method {}() does not match its signature in trait {}", m, t)
+            }
+            TypeError::MethodNotInTrait(t, m) => {
+                format!("Method {}() is not a member of trait {}", m, t)
+            }
+            TypeError::MethodMissingSelf(m) => {
+                format!("Method {}() must take self as its first argument", m)
+            }
+            TypeError::MethodNotDefined(t, m) => format!("No method {}() for type {}", m, t),
+            TypeError::MethodAmbiguous(t, m) => format!("Multiple methods {}() for type {}", m, t),
+            TypeError::MethodAlreadyDefined(t, m) => {
+                format!("Method {}() is already defined for type {}", m, t)
+            }
+            TypeError::ImplAlreadyDefined(s, t) => {
+                format!("Trait {} is already implemented for {}", s, t)
+            }
+            TypeError::SelfOutsideImpl(s) => format!("Self used outside of an impl block: {}()", s),
+            TypeError::ExplicitBorrowRequired(s, m) => format!(
+                "Argument {} must be borrowed at the call site with `{}ref`",
+                s,
+                if *m { "mut " } else { "" }
+            ),
+            TypeError::VariableNotDefined(s) => format!("Variable not defined: {}", s),
+            TypeError::NotAReference(s) => {
+                format!("Cannot dereference {} as it is not a reference", s)
+            }
+            TypeError::WriteThroughImmutableRef(s) => {
+                format!("Cannot assign through immutable reference to {}", s)
+            }
+            TypeError::NotMutable(s) => format!("Cannot change {} as it is not declared mut", s),
+            TypeError::MoveOutOfReference(s) => {
+                format!("Cannot move {} out of a reference, it is only borrowed", s)
+            }
+        };
+    }
+
+    // a stable code for each kind of error, `explain <code>` prints its explanation
+    pub fn code(&self) -> &'static str {
+        return match self {
+            TypeError::MoveNotWritable(_) => "M0001",
+            TypeError::CopyNotReadable(_) => "M0002",
+            TypeError::MutrefAlreadyBorrowedImmut(_) => "M0003",
+            TypeError::RefAlreadyBorrowedMut(_) => "M0004",
+            TypeError::MutrefImmut(_) => "M0005",
+            TypeError::TypeMoved(_) => "M0006",
+            TypeError::AssignBorrowed(_) => "M0007",
+            TypeError::NotWithinScope(_) => "M0008",
+            TypeError::WriteThroughImmutableRef(_) => "M0009",
+            TypeError::ExplicitBorrowRequired(..) => "M0010",
+            TypeError::IncompatibleTypes(..) => "M0011",
+            TypeError::LiteralOutOfRange(..) => "M0012",
+            TypeError::LetAlreadyDefined(_) => "M0013",
+            TypeError::LetExprNoReturn(_) => "M0014",
+            TypeError::VariableNotDefined(_) => "M0015",
+            TypeError::NotAReference(_) => "M0016",
+            TypeError::FunctionNotDefined(_) => "M0017",
+            TypeError::FunctionDeclDupArg(_) => "M0018",
+            TypeError::FunctionCallIncompatableArgumentCount(..) => "M0019",
+            TypeError::FunctionCallIncompatableArgumentType(..) => "M0020",
+            TypeError::FunctionUnexpectedReturn(..) => "M0021",
+            TypeError::TraitAlreadyDefined(_) => "M0022",
+            TypeError::TraitNotDefined(_) => "M0023",
+            TypeError::TraitMethodMissing(..) => "M0024",
+            TypeError::TraitMethodMismatch(..) => "M0025",
+            TypeError::MethodNotInTrait(..) => "M0026",
+            TypeError::MethodMissingSelf(_) => "M0027",
+            TypeError::MethodNotDefined(..) => "M0028",
+            TypeError::MethodAmbiguous(..) => "M0029",
+            TypeError::MethodAlreadyDefined(..) => "M0030",
+            TypeError::ImplAlreadyDefined(..) => "M0031",
+            TypeError::SelfOutsideImpl(_) => "M0032",
+            TypeError::NotMutable(_) => "M0034",
+            TypeError::MoveOutOfReference(_) => "M0035",
+        };
+    }
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Type error: {}", self.description())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    AssertionFailed(Box<Term>),
+    IntegerOverflow(i128, BinOp, i128, IntType),
+    InvalidOperands(BinOp, Value, Value),
+    FunctionNotDefined(String),
+    VariableNotFound(String),
+    NotAReference(String),
+    DanglingReference(String),
+    UnresolvedMethod(String),
+    NotTypeChecked(String),
+    InvariantViolated(String), // a progress or preservation check failed
+    Stopped,                   // the debugger running the program went away
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            RuntimeError::AssertionFailed(t) => format!("Runtime error: Assertion failed: {}", t),
+            RuntimeError::IntegerOverflow(n1, op, n2, k) => format!(
+                "Runtime error: Integer overflow: {} {} {} does not fit in {}",
+                n1, op, n2, k
+            ),
+            RuntimeError::InvalidOperands(op, v1, v2) => {
+                format!("Runtime error: Cannot apply {} to {} and {}", op, v1, v2)
+            }
+            RuntimeError::FunctionNotDefined(s) => {
+                format!("Runtime error: Function not found: {}()", s)
+            }
+            RuntimeError::VariableNotFound(s) => {
+                format!("Runtime error: Variable not found: {}", s)
+            }
+            RuntimeError::NotAReference(s) => format!(
+                "Runtime error: Cannot dereference {} as it is not a reference",
+                s
+            ),
+            RuntimeError::DanglingReference(l) => {
+                format!("Runtime error: Location {} is not in the store", l)
+            }
+            RuntimeError::UnresolvedMethod(s) => format!(
+                "Runtime error: Method call on {} was not resolved by the type checker",
+                s
+            ),
+            RuntimeError::NotTypeChecked(s) => format!(
+                "Runtime error: Variable {} was not type checked before it was used",
+                s
+            ),
+            RuntimeError::InvariantViolated(s) => format!("Runtime error: {}", s),
+            RuntimeError::Stopped => "Runtime error: Stopped by the debugger".to_string(),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnexpectedCharacter(char, Span),
+    InvalidLiteral(String, Span),
+    UnterminatedComment(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnexpectedCharacter(_, span)
+            | LexError::InvalidLiteral(_, span)
+            | LexError::UnterminatedComment(span) => *span,
+        }
+    }
+}
+
+impl Display for LexError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            LexError::UnexpectedCharacter(c, _) => {
+                format!("Lex error: Unexpected character `{}`", c)
+            }
+            LexError::InvalidLiteral(s, _) => format!("Lex error: Invalid numeric literal `{}`", s),
+            LexError::UnterminatedComment(_) => "Lex error: Unterminated block comment".to_string(),
+        };
+        write!(f, "{}", message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub expected: String,
+    pub found: TokenKind, // Eof once the input has run out
+    pub span: Span,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Parse error: Expected {}, found {}",
+            self.expected, self.found
+        )
+    }
+}
+
+// an error from checking or running a program, with the location it was raised at
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub span: Option<Span>,
+    pub labels: Vec<Label>, // secondary locations that explain the error
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    Lex(LexError),
+    Parse(ParseError),
+    Type(TypeError),
+    Runtime(RuntimeError),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+// a fix for an error, as edits to the source that `--fix` can apply
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub message: String,
+    pub edits: Vec<Edit>,
+}
+
+// replace the source covered by `span`, an empty span inserts
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub span: Span,
+    pub replacement: String,
+}
+
+impl Error {
+    pub fn with_label(mut self, span: Span, message: impl Display) -> Error {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        return self;
+    }
+
+    pub fn with_suggestion(mut self, message: impl Display, edits: Vec<Edit>) -> Error {
+        self.suggestions.push(Suggestion {
+            message: message.to_string(),
+            edits,
+        });
+        return self;
+    }
+
+    // errors keep the innermost location they were raised at
+    pub fn or_at(self, span: Option<Span>) -> Error {
+        Error {
+            span: self.span.or(span),
+            ..self
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TypeError::FunctionCallIncompatableArgumentCount(a1, a2) => format!("Type error: Incompatible argument count: expected {}, got {}", a1, a2),
-            TypeError::FunctionCallIncompatableArgumentType(t1, t2) => format!("Type error: Incompatible argument type: expected {}, got {}", t1.to_string(), t2.to_string()),
-            TypeError::FunctionNotDefined(s) => format!("Type error: Function not defined: {}()", s),
-            TypeError::FunctionDeclDupArg(s) => format!("Type error: Duplicate argument in function declaration: {}", s),
-            TypeError::FunctionUnexpectedReturn(t1, t2) => format!("Type error: Unexpected return type: expected {}, got {}", t1.to_string(), t2.to_string()),
-            TypeError::CopyNotReadable(s) => format!("Type error: Cannot copy variable that is mutually borrowed: {}", s),
-            TypeError::MoveNotWritable(s) => format!("Type error: Cannot move variable that is borrowd: {}", s),
-            TypeError::MutrefAlreadyBorrowedImmut(s) => format!("Type error: Cannot create a mutable reference to {} as it's already borrowed immutably", s),
-            TypeError::MutrefImmut(s) => format!("Type error: Mutable reference cannot be created from immutable reference: {}", s),
-            TypeError::RefAlreadyBorrowedMut(s) => format!("Type error: Immutable reference already borrowed mutably: {}", s),
-            TypeError::LetAlreadyDefined(s) => format!("Type error: Variable already defined: {}", s),
-            TypeError::LetExprNoReturn(s) => format!("Type error: Let expression does not return a value: {:?}", s),
-            TypeError::IncompatibleTypes(t1, t2) => format!("Type error: Incompatible types: {} and {}", t1.to_string(), t2.to_string()),
-            TypeError::NotWithinScope(s) => format!("Type error: Type is not within scope: {}", s),
-            TypeError::AssignBorrowed(s) => format!("Type error: Cannot assign to borrowed reference: {}", s),
-            TypeError::TypeMoved(t1) => format!("Type error: Type of {} is undefined, indicating that it was moved", t1.to_string())
+            ErrorKind::Lex(e) => write!(f, "{}", e),
+            ErrorKind::Parse(e) => write!(f, "{}", e),
+            ErrorKind::Type(e) => write!(f, "{}", e),
+            ErrorKind::Runtime(e) => write!(f, "{}", e),
         }
     }
 }
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for LexError {}
+impl std::error::Error for ParseError {}
+impl std::error::Error for TypeError {}
+impl std::error::Error for RuntimeError {}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Lex(e) => Some(e),
+            ErrorKind::Parse(e) => Some(e),
+            ErrorKind::Type(e) => Some(e),
+            ErrorKind::Runtime(e) => Some(e),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        let span = match &kind {
+            ErrorKind::Lex(e) => Some(e.span()),
+            ErrorKind::Parse(e) => Some(e.span),
+            _ => None,
+        };
+        Error {
+            kind,
+            span,
+            labels: vec![],
+            suggestions: vec![],
+        }
+    }
+}
+
+impl From<LexError> for ErrorKind {
+    fn from(e: LexError) -> ErrorKind {
+        ErrorKind::Lex(e)
+    }
+}
+
+impl From<ParseError> for ErrorKind {
+    fn from(e: ParseError) -> ErrorKind {
+        ErrorKind::Parse(e)
+    }
+}
+
+impl From<TypeError> for ErrorKind {
+    fn from(e: TypeError) -> ErrorKind {
+        ErrorKind::Type(e)
+    }
+}
+
+impl From<RuntimeError> for ErrorKind {
+    fn from(e: RuntimeError) -> ErrorKind {
+        ErrorKind::Runtime(e)
+    }
+}
+
+impl From<LexError> for Error {
+    fn from(e: LexError) -> Error {
+        Error::from(ErrorKind::from(e))
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(e: ParseError) -> Error {
+        Error::from(ErrorKind::from(e))
+    }
+}
+
+impl From<TypeError> for Error {
+    fn from(e: TypeError) -> Error {
+        Error::from(ErrorKind::from(e))
+    }
+}
+
+impl From<RuntimeError> for Error {
+    fn from(e: RuntimeError) -> Error {
+        Error::from(ErrorKind::from(e))
+    }
+}
@@ -0,0 +1,436 @@
+//! A debug adapter for muse, spoken over stdio by the `muse-dap` binary.
+//!
+//! Sessions are driven by the step debugger, so stepping, breakpoints and the stack
+//! behave as they do in `muse debug`. Stack traces come from the frames at the pause,
+//! each frame's scope lists its variables as the store holds them, and a reference
+//! expands into the cell it points at. The program runs on a single thread.
+
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    ast::Value,
+    debugger::{self, Breakpoint, Debugger},
+    diagnostic,
+    json::{self, object, Json},
+    typing::TypeEnviroment,
+};
+
+const THREAD: usize = 1;
+const EXIT_RUNTIME: usize = 4; // the exit code the cli gives a runtime error
+const NOT_RUNNING: &str = "The program is not running";
+
+// what a variablesReference stands for, valid until the program moves on
+#[derive(Debug)]
+enum Handle {
+    Frame(usize), // the variables of a frame, innermost first
+    Store,
+    Cell(String), // the cell a reference points at
+}
+
+#[derive(Default)]
+pub struct Adapter {
+    seq: usize,
+    session: Option<Debugger>,
+    source: String,
+    path: String,
+    stop_on_entry: bool,
+    configured: bool, // the client has sent its breakpoints
+    breakpoints: Vec<Breakpoint>,
+    handles: Vec<Handle>,
+    disconnected: bool,
+}
+
+impl Adapter {
+    pub fn new() -> Adapter {
+        return Adapter::default();
+    }
+
+    /// Handle one request, returning its response and any events it causes.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let command = message.get("command").as_str().unwrap_or_default();
+        let arguments = message.get("arguments");
+        let mut events = vec![];
+        let body = match command {
+            "initialize" => {
+                events.push(("initialized", Json::Null));
+                Ok(object([
+                    ("supportsConfigurationDoneRequest", true.into()),
+                    ("supportsFunctionBreakpoints", true.into()),
+                ]))
+            }
+            "launch" => self.launch(arguments).map(|started| {
+                events = started;
+                Json::Null
+            }),
+            "setBreakpoints" => {
+                let lines: Vec<usize> = arguments
+                    .get("breakpoints")
+                    .as_array()
+                    .iter()
+                    .filter_map(|b| b.get("line").as_u64())
+                    .map(|line| line as usize)
+                    .collect();
+                self.set_breakpoints(
+                    |b| !matches!(b, Breakpoint::Line(_)),
+                    lines.iter().map(|line| Breakpoint::Line(*line)).collect(),
+                );
+                let verified = lines
+                    .iter()
+                    .map(|line| object([("verified", true.into()), ("line", (*line).into())]));
+                Ok(object([(
+                    "breakpoints",
+                    verified.collect::<Vec<_>>().into(),
+                )]))
+            }
+            "setFunctionBreakpoints" => {
+                let names: Vec<&str> = arguments
+                    .get("breakpoints")
+                    .as_array()
+                    .iter()
+                    .filter_map(|b| b.get("name").as_str())
+                    .collect();
+                self.set_breakpoints(
+                    |b| !matches!(b, Breakpoint::Function(_)),
+                    names
+                        .iter()
+                        .map(|name| Breakpoint::Function(name.to_string()))
+                        .collect(),
+                );
+                let verified = names.iter().map(|_| object([("verified", true.into())]));
+                Ok(object([(
+                    "breakpoints",
+                    verified.collect::<Vec<_>>().into(),
+                )]))
+            }
+            "configurationDone" => {
+                self.configured = true;
+                events = self.start();
+                Ok(Json::Null)
+            }
+            "threads" => Ok(object([(
+                "threads",
+                vec![object([("id", THREAD.into()), ("name", "main".into())])].into(),
+            )])),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => self.scopes(arguments),
+            "variables" => self.variables(arguments),
+            "continue" => self.run(Debugger::resume, "breakpoint").map(|stopped| {
+                events = stopped;
+                object([("allThreadsContinued", true.into())])
+            }),
+            "next" | "stepIn" | "stepOut" => {
+                let step = match command {
+                    "next" => Debugger::step_over,
+                    "stepIn" => Debugger::step_in,
+                    _ => Debugger::step_out,
+                };
+                self.run(step, "step").map(|stopped| {
+                    events = stopped;
+                    Json::Null
+                })
+            }
+            "disconnect" | "terminate" => {
+                self.disconnected = true;
+                Ok(Json::Null)
+            }
+            command => Err(format!("Unknown request {}", command)),
+        };
+
+        let mut response = vec![
+            ("type", "response".into()),
+            ("request_seq", message.get("seq").clone()),
+            ("command", command.into()),
+        ];
+        match body {
+            Ok(Json::Null) => response.push(("success", true.into())),
+            Ok(body) => {
+                response.push(("success", true.into()));
+                response.push(("body", body));
+            }
+            Err(message) => {
+                response.push(("success", false.into()));
+                response.push(("message", message.into()));
+            }
+        }
+        let mut replies = vec![self.number(response)];
+        for (event, body) in events {
+            let mut message = vec![("type", "event".into()), ("event", event.into())];
+            if !body.is_null() {
+                message.push(("body", body));
+            }
+            replies.push(self.number(message));
+        }
+        return replies;
+    }
+
+    /// Whether the client has ended the session.
+    pub fn is_disconnected(&self) -> bool {
+        return self.disconnected;
+    }
+
+    // give an outgoing message the next sequence number
+    fn number(&mut self, members: Vec<(&str, Json)>) -> Json {
+        self.seq += 1;
+        let mut message = vec![("seq".to_string(), self.seq.into())];
+        message.extend(members.into_iter().map(|(k, v)| (k.to_string(), v)));
+        return Json::Object(message);
+    }
+
+    fn launch(&mut self, arguments: &Json) -> Result<Vec<(&'static str, Json)>, String> {
+        let path = arguments
+            .get("program")
+            .as_str()
+            .ok_or("Launch needs a program")?;
+        let source =
+            std::fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", path, e))?;
+        let mut gamma = TypeEnviroment::new();
+        if arguments.get("explicitBorrows").as_bool() == Some(true) {
+            gamma.require_explicit_borrows();
+        }
+        // a program that does not parse or check cannot be run, so say why instead
+        let mut session = Debugger::new(&source, path, gamma).map_err(|errors| {
+            let rendered: Vec<String> = errors
+                .iter()
+                .map(|e| diagnostic::render(e, &source, path, false))
+                .collect();
+            return rendered.concat();
+        })?;
+        for breakpoint in &self.breakpoints {
+            session.add_breakpoint(breakpoint.clone());
+        }
+        self.session = Some(session);
+        self.source = source;
+        self.path = path.to_string();
+        self.stop_on_entry = arguments.get("stopOnEntry").as_bool() == Some(true);
+        return Ok(self.start());
+    }
+
+    // replace the breakpoints that `keep` rejects with `new`
+    fn set_breakpoints(&mut self, keep: impl Fn(&Breakpoint) -> bool, new: Vec<Breakpoint>) {
+        let old = std::mem::take(&mut self.breakpoints);
+        if let Some(session) = &mut self.session {
+            for breakpoint in &old {
+                session.remove_breakpoint(breakpoint);
+            }
+        }
+        self.breakpoints = old.into_iter().filter(|b| keep(b)).chain(new).collect();
+        if let Some(session) = &mut self.session {
+            for breakpoint in &self.breakpoints {
+                session.add_breakpoint(breakpoint.clone());
+            }
+        }
+    }
+
+    // the program starts once it is launched and the client is configured, stopping
+    // on its first statement if asked to or if that has a breakpoint
+    fn start(&mut self) -> Vec<(&'static str, Json)> {
+        let Some(session) = &mut self.session else {
+            return vec![];
+        };
+        if !self.configured {
+            return vec![];
+        }
+        if self.stop_on_entry {
+            return self.stopped(0, "entry");
+        }
+        if !session.at_breakpoint() {
+            session.resume();
+        }
+        return self.stopped(0, "breakpoint");
+    }
+
+    fn run(
+        &mut self,
+        step: fn(&mut Debugger),
+        reason: &'static str,
+    ) -> Result<Vec<(&'static str, Json)>, String> {
+        let session = self.session.as_mut().ok_or(NOT_RUNNING)?;
+        if session.pause().is_none() {
+            return Err(NOT_RUNNING.to_string());
+        }
+        let printed = session.printed();
+        step(session);
+        return Ok(self.stopped(printed, reason));
+    }
+
+    // the events for where the program stopped, after the lines it printed on the way
+    fn stopped(&mut self, printed: usize, reason: &'static str) -> Vec<(&'static str, Json)> {
+        self.handles.clear();
+        let Some(session) = &self.session else {
+            return vec![];
+        };
+        let mut events: Vec<(&'static str, Json)> = session.output()
+            [printed.min(session.printed())..session.printed()]
+            .iter()
+            .map(|line| {
+                let output = object([
+                    ("category", "stdout".into()),
+                    ("output", format!("{}\n", line).into()),
+                ]);
+                return ("output", output);
+            })
+            .collect();
+
+        if session.pause().is_some() {
+            let reason = if reason != "entry" && session.at_breakpoint() {
+                "breakpoint"
+            } else {
+                reason
+            };
+            events.push((
+                "stopped",
+                object([
+                    ("reason", reason.into()),
+                    ("threadId", THREAD.into()),
+                    ("allThreadsStopped", true.into()),
+                ]),
+            ));
+            return events;
+        }
+        let mut exit_code = 0;
+        if let Some(error) = session.error() {
+            let rendered = diagnostic::render(error, &self.source, &self.path, false);
+            events.push((
+                "output",
+                object([("category", "stderr".into()), ("output", rendered.into())]),
+            ));
+            exit_code = EXIT_RUNTIME;
+        }
+        events.push(("exited", object([("exitCode", exit_code.into())])));
+        events.push(("terminated", Json::Null));
+        return events;
+    }
+
+    fn stack_trace(&self) -> Result<Json, String> {
+        let session = self.session.as_ref().ok_or(NOT_RUNNING)?;
+        let name = self.path.rsplit('/').next().unwrap_or_default();
+        let frames: Vec<Json> = session
+            .backtrace()
+            .iter()
+            .enumerate()
+            .map(|(id, (function, span))| {
+                let before = &self.source[..span.start.min(self.source.len())];
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                return object([
+                    ("id", id.into()),
+                    ("name", function.as_deref().unwrap_or("<main>").into()),
+                    (
+                        "source",
+                        object([("name", name.into()), ("path", self.path.as_str().into())]),
+                    ),
+                    ("line", span.line.into()),
+                    ("column", (before[line_start..].chars().count() + 1).into()),
+                ]);
+            })
+            .collect();
+        return Ok(object([
+            ("totalFrames", frames.len().into()),
+            ("stackFrames", frames.into()),
+        ]));
+    }
+
+    fn scopes(&mut self, arguments: &Json) -> Result<Json, String> {
+        let session = self.session.as_ref().ok_or(NOT_RUNNING)?;
+        let frame = arguments.get("frameId").as_u64().unwrap_or(0) as usize;
+        if frame >= session.backtrace().len() {
+            return Err(format!("No frame {}", frame));
+        }
+        let locals = handle(&mut self.handles, Handle::Frame(frame));
+        let store = handle(&mut self.handles, Handle::Store);
+        let scope = |name: &str, reference: usize| {
+            return object([
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ]);
+        };
+        return Ok(object([(
+            "scopes",
+            vec![scope("Locals", locals), scope("Store", store)].into(),
+        )]));
+    }
+
+    fn variables(&mut self, arguments: &Json) -> Result<Json, String> {
+        let pause = self
+            .session
+            .as_ref()
+            .and_then(|s| s.pause())
+            .ok_or(NOT_RUNNING)?;
+        let state = &pause.state;
+        let reference = arguments.get("variablesReference").as_u64().unwrap_or(0) as usize;
+        let mut variables = vec![];
+        match self.handles.get(reference.wrapping_sub(1)) {
+            Some(Handle::Frame(frame)) => {
+                let locations = state
+                    .frames()
+                    .iter()
+                    .rev()
+                    .nth(*frame)
+                    .map(|f| f.locations().clone())
+                    .unwrap_or_default();
+                for (name, reference) in locations {
+                    let value = state.store().read(reference).unwrap_or(Value::Undefined);
+                    variables.push((name, debugger::describe(&value), value));
+                }
+            }
+            Some(Handle::Store) => {
+                for (location, slot) in state.store().cells() {
+                    let shown = format!(
+                        "{}, lifetime {}",
+                        debugger::describe(&slot.value),
+                        slot.lifetime
+                    );
+                    variables.push((location.clone(), shown, slot.value.clone()));
+                }
+            }
+            Some(Handle::Cell(location)) => {
+                if let Some(slot) = state.store().cells().get(location) {
+                    let shown = debugger::describe(&slot.value);
+                    variables.push((location.clone(), shown, slot.value.clone()));
+                }
+            }
+            None => return Err(format!("No variables for {}", reference)),
+        }
+
+        // references expand into the cell they point at
+        let variables: Vec<Json> = variables
+            .into_iter()
+            .map(|(name, shown, value)| {
+                let expand = match value {
+                    Value::Reference(r) => handle(&mut self.handles, Handle::Cell(r.location)),
+                    _ => 0,
+                };
+                return object([
+                    ("name", name.into()),
+                    ("value", shown.into()),
+                    ("variablesReference", expand.into()),
+                ]);
+            })
+            .collect();
+        return Ok(object([("variables", variables.into())]));
+    }
+}
+
+// the variablesReference for `handle`, which are numbered from 1
+fn handle(handles: &mut Vec<Handle>, handle: Handle) -> usize {
+    handles.push(handle);
+    return handles.len();
+}
+
+/// Answer requests from `input` until the client disconnects or the input closes.
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    let mut adapter = Adapter::new();
+    while let Some(body) = json::read_frame(input)? {
+        // a message that is not JSON has no seq to answer, so it is dropped
+        let Ok(message) = Json::parse(&body) else {
+            continue;
+        };
+        for reply in adapter.handle(&message) {
+            json::write_frame(output, &reply)?;
+        }
+        if adapter.is_disconnected() {
+            break;
+        }
+    }
+    return Ok(());
+}
@@ -0,0 +1,401 @@
+//! Steps through a program one statement at a time, inside function bodies too.
+//!
+//! The interpreter runs on its own thread and waits before every statement it
+//! evaluates until the debugger tells it to go on. Only the statement it waits at
+//! is kept, so a program that never finishes can still be stepped and stopped at
+//! breakpoints, and stepping over a call is going on until the call returns.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::{
+    ast::{Program, Value},
+    diagnostic,
+    interpreter::Interpreter,
+    span::Span,
+    state::{State, Trace},
+    typecheck,
+    typing::{Type, TypeEnviroment},
+    Error,
+};
+
+// as much as the main thread gets, since evaluation recurses into calls
+const STACK_SIZE: usize = 8 * 1024 * 1024;
+
+pub const HELP: &str = "\
+Commands:
+        step, s              Run to the next statement, stepping into calls
+        next, n              Run to the next statement, stepping over calls
+        finish, f            Run until the current function returns
+        continue, c          Run to the next breakpoint
+        break, b <target>    Break at a line number or a function
+        delete, d <target>   Remove a breakpoint
+        where, w             Show the stack, store and types again
+        help, h              Show this message
+        quit, q              Leave";
+
+/// A point the program stopped at, before evaluating the statement at `span`.
+#[derive(Debug, Clone)]
+pub struct Pause {
+    pub span: Span,
+    pub depth: usize,             // calls in progress, 0 at the top level
+    pub function: Option<String>, // the function being run, None at the top level
+    pub state: State,
+}
+
+// what the program's thread tells the debugger, in the order it happens
+#[derive(Debug)]
+pub(crate) enum Step {
+    Printed(String),
+    Paused(Pause),
+    Finished(Result<Value, Error>, State), // with the program's value or error, and its state
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Breakpoint {
+    Line(usize),
+    Function(String),
+}
+
+impl Breakpoint {
+    /// A line number, or else the name of a function.
+    pub fn parse(target: &str) -> Breakpoint {
+        match target.parse() {
+            Ok(line) => Breakpoint::Line(line),
+            Err(_) => Breakpoint::Function(target.to_string()),
+        }
+    }
+}
+
+pub struct Debugger {
+    source: String,
+    file: String,
+    program: Program,
+    environment: TypeEnviroment, // the one the program was checked in
+    steps: Receiver<Step>,
+    resume: Sender<()>,
+    pause: Option<Pause>,               // None once the program finished
+    entered: bool,                      // whether the pause is the first statement of a call
+    calls: Vec<(Option<String>, Span)>, // the last pause at each depth, outermost first
+    output: Vec<String>,
+    error: Option<Error>, // the runtime error the program ended with
+    value: Option<Value>, // the value of its last statement, if it ended without one
+    end: Option<State>,   // the state it ended in
+    breakpoints: Vec<Breakpoint>,
+    color: bool,
+}
+
+impl Debugger {
+    /// Parse, check and start running `source`, paused before its first statement.
+    pub fn new(
+        source: &str,
+        file: &str,
+        environment: TypeEnviroment,
+    ) -> Result<Debugger, Vec<Error>> {
+        let program = crate::parse(source)?;
+        let checked = crate::check_with(&program, environment.clone()).map_err(|mut errors| {
+            crate::verify_suggestions(&mut errors, source, 0, &environment);
+            return errors;
+        })?;
+
+        let (steps, receiver) = mpsc::channel();
+        let (resume, resumed) = mpsc::channel();
+        let explicit_borrows = environment.explicit_borrows();
+        thread::Builder::new()
+            .name("program".to_string())
+            .stack_size(STACK_SIZE)
+            .spawn(move || {
+                let mut interpreter = Interpreter::new();
+                if explicit_borrows {
+                    interpreter.require_explicit_borrows();
+                }
+                interpreter.attach(Trace::new(steps.clone(), resumed));
+                let result = interpreter.run(checked.program);
+                let state = interpreter.state().untraced();
+                // nobody is listening if the debugger went away
+                let _ = steps.send(Step::Finished(result, state));
+            })
+            .expect("could not start the program's thread");
+
+        let mut debugger = Debugger {
+            source: source.to_string(),
+            file: file.to_string(),
+            program,
+            environment,
+            steps: receiver,
+            resume,
+            pause: None,
+            entered: false,
+            calls: vec![],
+            output: vec![],
+            error: None,
+            value: None,
+            end: None,
+            breakpoints: vec![],
+            color: false,
+        };
+        debugger.wait();
+        return Ok(debugger);
+    }
+
+    // take what the program sends until it pauses or finishes
+    fn wait(&mut self) {
+        loop {
+            match self.steps.recv() {
+                Ok(Step::Printed(line)) => self.output.push(line),
+                Ok(Step::Paused(pause)) => {
+                    self.entered = pause.depth >= self.calls.len() && !self.calls.is_empty();
+                    self.calls.truncate(pause.depth);
+                    self.calls.push((pause.function.clone(), pause.span));
+                    self.pause = Some(pause);
+                    return;
+                }
+                Ok(Step::Finished(result, state)) => {
+                    match result {
+                        Ok(value) => self.value = Some(value),
+                        Err(error) => self.error = Some(error),
+                    }
+                    self.end = Some(state);
+                    return;
+                }
+                // the program's thread panicked
+                Err(_) => return,
+            }
+        }
+    }
+
+    // let the program run to its next pause
+    fn advance(&mut self) {
+        self.pause = None;
+        if self.resume.send(()).is_ok() {
+            self.wait();
+        }
+    }
+
+    /// Render errors in color.
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        let found = self.breakpoints.contains(breakpoint);
+        self.breakpoints.retain(|b| b != breakpoint);
+        return found;
+    }
+
+    /// Where the program is paused, or None once it finished.
+    pub fn pause(&self) -> Option<&Pause> {
+        return self.pause.as_ref();
+    }
+
+    /// The runtime error the program stops with, if any.
+    pub fn error(&self) -> Option<&Error> {
+        return self.error.as_ref();
+    }
+
+    /// The value of the program's last statement, once it finished without an error.
+    pub fn value(&self) -> Option<&Value> {
+        return self.value.as_ref();
+    }
+
+    /// The state the program finished in, once it has.
+    pub fn final_state(&self) -> Option<&State> {
+        return self.end.as_ref();
+    }
+
+    /// Every line the program has printed so far.
+    pub fn output(&self) -> &[String] {
+        return &self.output;
+    }
+
+    /// How many lines the program has printed so far.
+    pub fn printed(&self) -> usize {
+        return self.output.len();
+    }
+
+    /// Whether the pause is at a breakpoint.
+    pub fn at_breakpoint(&self) -> bool {
+        let Some(pause) = self.pause() else {
+            return false;
+        };
+        return self.breaks(pause, self.entered);
+    }
+
+    pub fn step_in(&mut self) {
+        if self.pause.is_some() {
+            self.advance();
+        }
+    }
+
+    pub fn step_over(&mut self) {
+        self.run_until(|pause, depth, _| pause.depth <= depth);
+    }
+
+    pub fn step_out(&mut self) {
+        self.run_until(|pause, depth, _| pause.depth < depth);
+    }
+
+    pub fn resume(&mut self) {
+        self.run_until(|_, _, _| false);
+    }
+
+    // run to the next pause that `stop` accepts, given the depth we started at and
+    // whether the pause is the first statement of a call, or that has a breakpoint
+    fn run_until(&mut self, stop: impl Fn(&Pause, usize, bool) -> bool) {
+        let Some(depth) = self.pause().map(|p| p.depth) else {
+            return;
+        };
+        self.advance();
+        while let Some(pause) = self.pause() {
+            if stop(pause, depth, self.entered) || self.breaks(pause, self.entered) {
+                return;
+            }
+            self.advance();
+        }
+    }
+
+    fn breaks(&self, pause: &Pause, entered: bool) -> bool {
+        return self.breakpoints.iter().any(|b| match b {
+            Breakpoint::Line(line) => pause.span.line == *line,
+            Breakpoint::Function(name) => {
+                entered
+                    && pause.function.as_deref().is_some_and(|f| {
+                        // methods are named after their type, like `int::double`
+                        f == name || f.rsplit("::").next() == Some(name.as_str())
+                    })
+            }
+        });
+    }
+
+    /// The function each frame is running and the statement it is at, innermost first.
+    pub fn backtrace(&self) -> Vec<(Option<String>, Span)> {
+        if self.pause.is_none() {
+            return vec![];
+        }
+        // the last pause at each shallower depth is the statement making the call
+        return self.calls.iter().rev().cloned().collect();
+    }
+
+    /// The type environment in effect at the pause.
+    pub fn environment(&self) -> Option<TypeEnviroment> {
+        let pause = self.pause()?;
+        let (before, _) = typecheck::scope_at(
+            &self.program.terms,
+            self.environment.clone(),
+            pause.span.start,
+            0,
+        );
+        return Some(before);
+    }
+
+    /// Run a command, returning what to show. Running commands show the output
+    /// printed on the way and where the program stopped.
+    pub fn command(&mut self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        let (name, arg) = input.split_once(' ').unwrap_or((input, ""));
+        let arg = arg.trim();
+        let printed = self.printed();
+        match (name, arg) {
+            ("step" | "s", "") => self.step_in(),
+            ("next" | "n", "") => self.step_over(),
+            ("finish" | "f", "") => self.step_out(),
+            ("continue" | "c", "") => self.resume(),
+            ("break" | "b", target) if !target.is_empty() => {
+                self.add_breakpoint(Breakpoint::parse(target));
+                return Ok(format!("Breakpoint at {}\n", target));
+            }
+            ("delete" | "d", target) if !target.is_empty() => {
+                if !self.remove_breakpoint(&Breakpoint::parse(target)) {
+                    return Err(format!("No breakpoint at {}", target));
+                }
+                return Ok(String::new());
+            }
+            ("where" | "w", "") => return Ok(self.show()),
+            ("help" | "h", "") => return Ok(format!("{}\n", HELP)),
+            _ => return Err(format!("Unknown command {}, try help", input)),
+        }
+        let now = self.printed();
+        let mut out: String = self.output[printed.min(now)..now]
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        out.push_str(&self.show());
+        return Ok(out);
+    }
+
+    /// Where the program is paused, with the stack, the store and the types there.
+    pub fn show(&self) -> String {
+        let Some(pause) = self.pause() else {
+            return match &self.error {
+                Some(e) => diagnostic::render(e, &self.source, &self.file, self.color),
+                None => "Program finished\n".to_string(),
+            };
+        };
+        let function = pause.function.as_deref().unwrap_or("<main>");
+        let line = self
+            .source
+            .lines()
+            .nth(pause.span.line.saturating_sub(1))
+            .unwrap_or("");
+        let mut out = format!(
+            "Paused at line {} in {}\n{:>4} | {}\n",
+            pause.span.line, function, pause.span.line, line
+        );
+
+        out.push_str("Stack:\n");
+        let frames = pause.state.frames();
+        for (depth, (function, span)) in self.backtrace().iter().enumerate() {
+            let locations: Vec<String> = frames.iter().rev().nth(depth).map_or(vec![], |frame| {
+                frame
+                    .locations()
+                    .iter()
+                    .map(|(name, reference)| format!("{} -> {}", name, reference.location))
+                    .collect()
+            });
+            let function = function.as_deref().unwrap_or("<main>");
+            out.push_str(&format!("    {} at line {}", function, span.line));
+            if !locations.is_empty() {
+                out.push_str(&format!(": {}", locations.join(", ")));
+            }
+            out.push('\n');
+        }
+
+        out.push_str("Store:\n");
+        for (location, slot) in pause.state.store().cells() {
+            out.push_str(&format!(
+                "    {} = {}, lifetime {}\n",
+                location,
+                describe(&slot.value),
+                slot.lifetime
+            ));
+        }
+
+        out.push_str("Types:\n");
+        if let Some(gamma) = self.environment() {
+            // the names the checker binds for itself are not the user's
+            for (name, slot) in gamma.bindings() {
+                let visible = name.chars().all(|c| c.is_alphanumeric() || c == '_');
+                if visible && !matches!(slot.value, Type::Function { .. }) {
+                    out.push_str(&format!("    {}: {}\n", name, gamma.describe(&slot.value)));
+                }
+            }
+        }
+        return out;
+    }
+}
+
+// a store cell's value, saying whether a reference owns or borrows what it points at
+pub(crate) fn describe(value: &Value) -> String {
+    return match value {
+        Value::Reference(r) if r.owned => format!("ref {} (owned)", r.location),
+        Value::Reference(r) => format!("ref {} (borrowed)", r.location),
+        Value::Undefined => "moved out".to_string(),
+        value => value.to_string(),
+    };
+}
@@ -0,0 +1,206 @@
+use crate::{
+    constants::{Edit, Error, ErrorKind, Label, Suggestion},
+    lint::Warning,
+    span::Span,
+};
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+struct Annotation<'a> {
+    span: Span,
+    message: &'a str,
+    primary: bool,
+}
+
+fn paint(color: bool, style: &str, text: &str) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+// render an error like rustc does, quoting the source lines it points at with
+// carets under the primary location and dashes under the secondary labels
+pub fn render(error: &Error, source: &str, file: &str, color: bool) -> String {
+    // type errors carry a code that `explain` knows about, like rustc's E0382
+    let header = match &error.kind {
+        ErrorKind::Type(e) => format!("Type error[{}]: {}", e.code(), e.description()),
+        kind => kind.to_string(),
+    };
+    return annotate(
+        paint(color, RED, &header),
+        error.span,
+        &error.labels,
+        &error.suggestions,
+        source,
+        file,
+        color,
+    );
+}
+
+// render a lint warning, which reads as an error when the lint is denied
+pub fn render_warning(
+    warning: &Warning,
+    denied: bool,
+    source: &str,
+    file: &str,
+    color: bool,
+) -> String {
+    let (level, style) = if denied {
+        ("Lint error", RED)
+    } else {
+        ("Warning", YELLOW)
+    };
+    let header = format!("{}[{}]: {}", level, warning.lint.name(), warning.message);
+    return annotate(
+        paint(color, style, &header),
+        Some(warning.span),
+        &[],
+        &[],
+        source,
+        file,
+        color,
+    );
+}
+
+fn annotate(
+    header: String,
+    span: Option<Span>,
+    labels: &[Label],
+    suggestions: &[Suggestion],
+    source: &str,
+    file: &str,
+    color: bool,
+) -> String {
+    let paint = |style: &str, text: &str| paint(color, style, text);
+
+    let mut out = format!("{}\n", header);
+    // spans made up by the checker itself start at line 0 and cannot be quoted
+    let Some(primary) = span.filter(|span| span.line > 0) else {
+        return out;
+    };
+
+    let mut annotations = vec![Annotation {
+        span: primary,
+        message: "",
+        primary: true,
+    }];
+    for label in labels.iter().filter(|label| label.span.line > 0) {
+        annotations.push(Annotation {
+            span: label.span,
+            message: &label.message,
+            primary: false,
+        });
+    }
+    annotations.sort_by_key(|a| (a.span.line, a.span.column));
+
+    let width = annotations
+        .iter()
+        .map(|a| a.span.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+    let gutter = paint(BLUE, &format!("{} |", " ".repeat(width)));
+
+    out.push_str(&format!(
+        "{}{} {}:{}\n",
+        " ".repeat(width),
+        paint(BLUE, "-->"),
+        file,
+        primary
+    ));
+    out.push_str(&format!("{}\n", gutter));
+
+    let mut last_line = None;
+    for annotation in &annotations {
+        let span = annotation.span;
+        let Some(text) = source.lines().nth(span.line.saturating_sub(1)) else {
+            continue;
+        };
+        if last_line != Some(span.line) {
+            if last_line.is_some_and(|l| l + 1 < span.line) {
+                out.push_str(&format!("{}\n", paint(BLUE, "...")));
+            }
+            let number = format!("{:>width$} |", span.line, width = width);
+            out.push_str(&format!("{} {}\n", paint(BLUE, &number), text));
+            last_line = Some(span.line);
+        }
+
+        // underline up to the end of the line the span starts on
+        let line_start = source[..span.start].rfind('\n').map_or(0, |i| i + 1);
+        let end = span.end.min(line_start + text.len()).max(span.start);
+        let length = source
+            .get(span.start..end)
+            .map_or(1, |s| s.chars().count())
+            .max(1);
+        let (marker, style) = if annotation.primary {
+            ("^", RED)
+        } else {
+            ("-", BLUE)
+        };
+        let underline = format!("{} {}", marker.repeat(length), annotation.message);
+        out.push_str(&format!(
+            "{} {}{}\n",
+            gutter,
+            " ".repeat(span.column - 1),
+            paint(style, underline.trim_end())
+        ));
+    }
+    out.push_str(&format!("{}\n", gutter));
+
+    // suggestions show the line they change as it would read once fixed
+    for suggestion in suggestions {
+        out.push_str(&format!(
+            "{} {}\n",
+            paint(BLUE, "help:"),
+            suggestion.message
+        ));
+        for edit in suggestion.edits.iter().filter(|edit| edit.span.line > 0) {
+            let line_start = source[..edit.span.start].rfind('\n').map_or(0, |i| i + 1);
+            let line_end = source[edit.span.start..]
+                .find('\n')
+                .map_or(source.len(), |i| edit.span.start + i);
+            let fixed = apply_edits(
+                &source[line_start..line_end.max(edit.span.end)],
+                &[Edit {
+                    span: Span {
+                        start: edit.span.start - line_start,
+                        end: edit.span.end - line_start,
+                        ..edit.span
+                    },
+                    replacement: edit.replacement.clone(),
+                }],
+            );
+            let number = format!("{:>width$} |", edit.span.line, width = width);
+            out.push_str(&format!("{} {}\n", paint(BLUE, &number), fixed));
+        }
+    }
+    return out;
+}
+
+// apply edits to the source, skipping any that overlap an edit before them
+pub fn apply_edits(source: &str, edits: &[Edit]) -> String {
+    let mut edits: Vec<&Edit> = edits.iter().collect();
+    edits.sort_by_key(|edit| edit.span.start);
+    let mut out = String::new();
+    let mut position = 0;
+    for edit in edits {
+        if edit.span.start < position || edit.span.end > source.len() {
+            continue;
+        }
+        out.push_str(&source[position..edit.span.start]);
+        out.push_str(&edit.replacement);
+        position = edit.span.end;
+    }
+    out.push_str(&source[position..]);
+    return out;
+}
+
+// errors go to stderr, in color only when that is a terminal
+pub fn use_color() -> bool {
+    use std::io::IsTerminal;
+    return std::env::var("NO_COLOR").is_err() && std::io::stderr().is_terminal();
+}
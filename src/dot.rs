@@ -0,0 +1,94 @@
+//! Renders a runtime state as a Graphviz DOT graph, for drawing how the store
+//! changes as a program runs.
+//!
+//! Each stack frame is a cluster of its variables, with an edge from each variable
+//! to the store cell it lives in. Cells are labelled with their location, value and
+//! lifetime. A reference is an edge to the cell it points at, solid when it owns the
+//! cell and dashed when it borrows it. Cells whose value was moved out are filled.
+
+use crate::{
+    ast::{Reference, Value},
+    state::State,
+};
+
+const MOVED_OUT: &str = "#f4cccc";
+
+/// Render `state` as a graph called `title`. `frames` names the stack frames,
+/// outermost first. Frames without a name are numbered.
+pub fn graph(state: &State, title: &str, frames: &[String]) -> String {
+    let mut out = format!("digraph {} {{\n", quote(title));
+    out.push_str(&format!("    label={}\n", quote(title)));
+    out.push_str("    labelloc=t\n");
+    out.push_str("    rankdir=LR\n");
+    out.push_str("    node [shape=box, fontname=monospace]\n");
+
+    for (i, frame) in state.frames().iter().enumerate() {
+        let name = match frames.get(i) {
+            Some(name) => name.clone(),
+            None if i == 0 => "<main>".to_string(),
+            None => format!("frame {}", i),
+        };
+        out.push_str(&format!("    subgraph cluster_frame_{} {{\n", i));
+        out.push_str(&format!("        label={}\n", quote(&name)));
+        for variable in frame.locations().keys() {
+            out.push_str(&format!(
+                "        {} [label={}, shape=plaintext]\n",
+                quote(&format!("frame {} {}", i, variable)),
+                quote(variable)
+            ));
+        }
+        out.push_str("    }\n");
+    }
+
+    for (location, slot) in state.store().cells() {
+        let value = match &slot.value {
+            Value::Reference(r) => format!("ref {}", r.location),
+            Value::Undefined => "moved out".to_string(),
+            value => value.to_string(),
+        };
+        let label = format!("{}\n{}\nlifetime {}", location, value, slot.lifetime);
+        let fill = match slot.value {
+            Value::Undefined => format!(", style=filled, fillcolor={}", quote(MOVED_OUT)),
+            _ => String::new(),
+        };
+        out.push_str(&format!(
+            "    {} [label={}{}]\n",
+            quote(location),
+            quote(&label),
+            fill
+        ));
+    }
+
+    for (i, frame) in state.frames().iter().enumerate() {
+        for (variable, reference) in frame.locations() {
+            let from = format!("frame {} {}", i, variable);
+            out.push_str(&edge(&from, reference));
+        }
+    }
+    for (location, slot) in state.store().cells() {
+        if let Value::Reference(reference) = &slot.value {
+            out.push_str(&edge(location, reference));
+        }
+    }
+    out.push_str("}\n");
+    return out;
+}
+
+// owning references are drawn solid, borrows dashed
+fn edge(from: &str, reference: &Reference) -> String {
+    let style = if reference.owned { "solid" } else { "dashed" };
+    return format!(
+        "    {} -> {} [style={}]\n",
+        quote(from),
+        quote(&reference.location),
+        style
+    );
+}
+
+fn quote(s: &str) -> String {
+    let escaped = s
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    return format!("\"{}\"", escaped);
+}
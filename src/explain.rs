@@ -0,0 +1,180 @@
+// long-form explanations of the type error codes, printed by `explain <code>`
+pub const EXPLANATIONS: &[(&str, &str, &str)] = &[
+    (
+        "M0001",
+        "MoveNotWritable",
+        include_str!("../docs/errors/M0001.md"),
+    ),
+    (
+        "M0002",
+        "CopyNotReadable",
+        include_str!("../docs/errors/M0002.md"),
+    ),
+    (
+        "M0003",
+        "MutrefAlreadyBorrowedImmut",
+        include_str!("../docs/errors/M0003.md"),
+    ),
+    (
+        "M0004",
+        "RefAlreadyBorrowedMut",
+        include_str!("../docs/errors/M0004.md"),
+    ),
+    (
+        "M0005",
+        "MutrefImmut",
+        include_str!("../docs/errors/M0005.md"),
+    ),
+    (
+        "M0006",
+        "TypeMoved",
+        include_str!("../docs/errors/M0006.md"),
+    ),
+    (
+        "M0007",
+        "AssignBorrowed",
+        include_str!("../docs/errors/M0007.md"),
+    ),
+    (
+        "M0008",
+        "NotWithinScope",
+        include_str!("../docs/errors/M0008.md"),
+    ),
+    (
+        "M0009",
+        "WriteThroughImmutableRef",
+        include_str!("../docs/errors/M0009.md"),
+    ),
+    (
+        "M0010",
+        "ExplicitBorrowRequired",
+        include_str!("../docs/errors/M0010.md"),
+    ),
+    (
+        "M0011",
+        "IncompatibleTypes",
+        include_str!("../docs/errors/M0011.md"),
+    ),
+    (
+        "M0012",
+        "LiteralOutOfRange",
+        include_str!("../docs/errors/M0012.md"),
+    ),
+    (
+        "M0013",
+        "LetAlreadyDefined",
+        include_str!("../docs/errors/M0013.md"),
+    ),
+    (
+        "M0014",
+        "LetExprNoReturn",
+        include_str!("../docs/errors/M0014.md"),
+    ),
+    (
+        "M0015",
+        "VariableNotDefined",
+        include_str!("../docs/errors/M0015.md"),
+    ),
+    (
+        "M0016",
+        "NotAReference",
+        include_str!("../docs/errors/M0016.md"),
+    ),
+    (
+        "M0017",
+        "FunctionNotDefined",
+        include_str!("../docs/errors/M0017.md"),
+    ),
+    (
+        "M0018",
+        "FunctionDeclDupArg",
+        include_str!("../docs/errors/M0018.md"),
+    ),
+    (
+        "M0019",
+        "FunctionCallIncompatableArgumentCount",
+        include_str!("../docs/errors/M0019.md"),
+    ),
+    (
+        "M0020",
+        "FunctionCallIncompatableArgumentType",
+        include_str!("../docs/errors/M0020.md"),
+    ),
+    (
+        "M0021",
+        "FunctionUnexpectedReturn",
+        include_str!("../docs/errors/M0021.md"),
+    ),
+    (
+        "M0022",
+        "TraitAlreadyDefined",
+        include_str!("../docs/errors/M0022.md"),
+    ),
+    (
+        "M0023",
+        "TraitNotDefined",
+        include_str!("../docs/errors/M0023.md"),
+    ),
+    (
+        "M0024",
+        "TraitMethodMissing",
+        include_str!("../docs/errors/M0024.md"),
+    ),
+    (
+        "M0025",
+        "TraitMethodMismatch",
+        include_str!("../docs/errors/M0025.md"),
+    ),
+    (
+        "M0026",
+        "MethodNotInTrait",
+        include_str!("../docs/errors/M0026.md"),
+    ),
+    (
+        "M0027",
+        "MethodMissingSelf",
+        include_str!("../docs/errors/M0027.md"),
+    ),
+    (
+        "M0028",
+        "MethodNotDefined",
+        include_str!("../docs/errors/M0028.md"),
+    ),
+    (
+        "M0029",
+        "MethodAmbiguous",
+        include_str!("../docs/errors/M0029.md"),
+    ),
+    (
+        "M0030",
+        "MethodAlreadyDefined",
+        include_str!("../docs/errors/M0030.md"),
+    ),
+    (
+        "M0031",
+        "ImplAlreadyDefined",
+        include_str!("../docs/errors/M0031.md"),
+    ),
+    (
+        "M0032",
+        "SelfOutsideImpl",
+        include_str!("../docs/errors/M0032.md"),
+    ),
+    (
+        "M0034",
+        "NotMutable",
+        include_str!("../docs/errors/M0034.md"),
+    ),
+    (
+        "M0035",
+        "MoveOutOfReference",
+        include_str!("../docs/errors/M0035.md"),
+    ),
+];
+
+pub fn explain(code: &str) -> Option<&'static str> {
+    return EXPLANATIONS
+        .iter()
+        .find(|(c, _, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, _, text)| *text);
+}
@@ -0,0 +1,287 @@
+//! Prints a program back as canonical muse: four space indents, one statement per
+//! line, `name: type` arguments and ` : type` return types. Comments are kept where
+//! they were, and runs of blank lines become one.
+
+use std::collections::VecDeque;
+
+use crate::{
+    ast::{Argument, MethodSignature, Term},
+    lexer::Lexer,
+    parser::Parser,
+    token::{Comment, Token, TokenKind},
+    typing::Type,
+    Error,
+};
+
+const INDENT: &str = "    ";
+
+/// Format muse source, failing with the lex or parse errors if it does not parse.
+pub fn format(source: &str) -> Result<String, Vec<Error>> {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.tokenize().map_err(|e| vec![e.into()])?;
+    let comments = lexer.comments().iter().cloned().collect();
+    let program = Parser::new(tokens.clone())
+        .parse()
+        .map_err(|errors| errors.into_iter().map(Error::from).collect::<Vec<_>>())?;
+
+    let mut printer = Printer {
+        source,
+        tokens,
+        comments,
+        out: String::new(),
+        last: None,
+    };
+    printer.block(&program.terms, 0, source.len(), None);
+    let mut out = printer.out;
+    while out.ends_with("\n\n") {
+        out.pop();
+    }
+    return Ok(out);
+}
+
+struct Printer<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    comments: VecDeque<Comment>,
+    out: String,
+    last: Option<usize>, // where the last item printed in the current block ended
+}
+
+impl Printer<'_> {
+    fn line(&mut self, depth: usize, text: &str) {
+        for _ in 0..depth {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    // keep one blank line where the source had any before an item starting at `start`
+    fn gap(&mut self, start: usize) {
+        if let Some(last) = self.last {
+            let between = &self.source[last.min(start)..start];
+            if between.matches('\n').count() > 1 && !self.out.ends_with("\n\n") {
+                self.out.push('\n');
+            }
+        }
+    }
+
+    // comments on their own lines before `start`
+    fn comments_before(&mut self, start: usize, depth: usize) {
+        while self.comments.front().is_some_and(|c| c.span.start < start) {
+            let comment = self.comments.pop_front().unwrap();
+            self.gap(comment.span.start);
+            self.line(depth, &comment.text);
+            self.last = Some(comment.span.end);
+        }
+    }
+
+    // other comments inside a statement, or after it on the same line, go at the end of its line
+    fn trailing_comments(&mut self, start: usize, end: usize) -> String {
+        let mut text = String::new();
+        while let Some(comment) = self.comments.front() {
+            // a comment inside starts before `end`, only one after has text in between
+            let inside = comment.span.start < end;
+            let same_line = inside || !self.source[end..comment.span.start].contains('\n');
+            if comment.span.start < start || !same_line {
+                break;
+            }
+            text.push(' ');
+            text.push_str(&comment.text);
+            self.last = Some(comment.span.end);
+            self.comments.pop_front();
+        }
+        return text;
+    }
+
+    // block comments inside a statement stay before the token they preceded, as long as
+    // the printed statement has the same tokens as the source
+    fn inline_comments(&mut self, text: String, start: usize, end: usize) -> String {
+        let inside: Vec<&Comment> = self
+            .comments
+            .iter()
+            .take_while(|c| c.span.start < end)
+            .collect();
+        let count = inside.len();
+        if count == 0
+            || inside
+                .iter()
+                .any(|c| c.span.start < start || !c.text.starts_with("/*"))
+        {
+            return text;
+        }
+        let Ok(printed) = Lexer::new(&text).tokenize() else {
+            return text;
+        };
+        let printed: Vec<&Token> = printed
+            .iter()
+            .filter(|t| t.kind != TokenKind::Eof)
+            .collect();
+        let source = &self.tokens[self.token_at(start)..self.token_at(end)];
+        if printed.len() != source.len()
+            || printed.iter().zip(source).any(|(a, b)| a.kind != b.kind)
+        {
+            return text;
+        }
+        let mut out = String::new();
+        let mut from = 0;
+        for _ in 0..count {
+            let comment = self.comments.pop_front().unwrap();
+            // right after the token before it, or first if none is
+            match self.token_at(comment.span.start) - self.token_at(start) {
+                0 => {
+                    out.push_str(&comment.text);
+                    out.push(' ');
+                }
+                index => {
+                    let at = printed[index - 1].span.end;
+                    out.push_str(&text[from..at]);
+                    out.push(' ');
+                    out.push_str(&comment.text);
+                    from = at;
+                }
+            }
+            self.last = Some(comment.span.end);
+        }
+        out.push_str(&text[from..]);
+        return out;
+    }
+
+    // statements between braces, or the whole program, ending before `end`
+    fn block(&mut self, terms: &[Term], depth: usize, end: usize, self_type: Option<&Type>) {
+        self.last = None;
+        for term in terms {
+            let span = term.span().unwrap_or_default();
+            self.comments_before(span.start, depth);
+            self.gap(span.start);
+            self.term(term, depth, self_type);
+            // a trailing comment may end after the term
+            self.last = Some(self.last.map_or(span.end, |last| last.max(span.end)));
+        }
+        self.comments_before(end, depth);
+    }
+
+    fn term(&mut self, term: &Term, depth: usize, self_type: Option<&Type>) {
+        let span = term.span().unwrap_or_default();
+        match term {
+            Term::FunctionDeclaration {
+                name,
+                args,
+                body,
+                ty,
+                ..
+            } => {
+                let header = signature(name, args, ty.as_ref(), self_type);
+                let comment = self.trailing_comments(span.start, self.header_end(span.start));
+                self.line(depth, &format!("{} {{{}", header, comment));
+                self.block(body, depth + 1, span.end, self_type);
+                let comment = self.trailing_comments(span.end, span.end);
+                self.line(depth, &format!("}}{}", comment));
+            }
+            Term::TraitDeclaration { name, methods, .. } => {
+                let comment = self.trailing_comments(span.start, self.header_end(span.start));
+                self.line(depth, &format!("trait {} {{{}", name, comment));
+                // signatures have no spans, so find where each starts from its `fn`
+                let starts = self.fn_tokens(self.header_end(span.start), span.end);
+                self.last = None;
+                for (method, start) in methods.iter().zip(starts) {
+                    self.comments_before(start, depth + 1);
+                    self.gap(start);
+                    let MethodSignature { name, args, ty } = method;
+                    let end = self.signature_end(start);
+                    let comment = self.trailing_comments(start, end);
+                    let text = signature(name, args, ty.as_ref(), Some(&Type::SelfAlias));
+                    self.line(depth + 1, &format!("{}{}", text, comment));
+                    self.last = Some(end);
+                }
+                self.comments_before(span.end, depth + 1);
+                let comment = self.trailing_comments(span.end, span.end);
+                self.line(depth, &format!("}}{}", comment));
+            }
+            Term::Impl {
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => {
+                let header = match trait_name {
+                    Some(trait_name) => format!("impl {} for {} {{", trait_name, ty.syntax()),
+                    None => format!("impl {} {{", ty.syntax()),
+                };
+                let comment = self.trailing_comments(span.start, self.header_end(span.start));
+                self.line(depth, &format!("{}{}", header, comment));
+                self.block(methods, depth + 1, span.end, Some(ty));
+                let comment = self.trailing_comments(span.end, span.end);
+                self.line(depth, &format!("}}{}", comment));
+            }
+            _ => {
+                let text = self.inline_comments(term.to_string(), span.start, span.end);
+                let comment = self.trailing_comments(span.start, span.end);
+                self.line(depth, &format!("{}{}", text, comment));
+            }
+        }
+    }
+
+    fn token_at(&self, offset: usize) -> usize {
+        return self.tokens.partition_point(|t| t.span.start < offset);
+    }
+
+    // the end of the `{` that opens a declaration starting at `start`
+    fn header_end(&self, start: usize) -> usize {
+        let tokens = &self.tokens[self.token_at(start)..];
+        return tokens
+            .iter()
+            .find(|t| t.kind == TokenKind::LCurl)
+            .map_or(start, |t| t.span.end);
+    }
+
+    // where each `fn` between `start` and `end` begins
+    fn fn_tokens(&self, start: usize, end: usize) -> Vec<usize> {
+        return self.tokens[self.token_at(start)..self.token_at(end)]
+            .iter()
+            .filter(|t| t.kind == TokenKind::Fn)
+            .map(|t| t.span.start)
+            .collect();
+    }
+
+    // the end of the last token of a trait method signature starting at `start`
+    fn signature_end(&self, start: usize) -> usize {
+        let from = self.token_at(start);
+        let tokens = &self.tokens[from..];
+        let next = tokens
+            .iter()
+            .skip(1)
+            .position(|t| t.kind == TokenKind::Fn || t.kind == TokenKind::RCurl)
+            .map_or(tokens.len() - 1, |i| i);
+        return tokens[next].span.end;
+    }
+}
+
+fn signature(
+    name: &str,
+    args: &[Argument],
+    ret: Option<&Type>,
+    self_type: Option<&Type>,
+) -> String {
+    let args: Vec<String> = args.iter().map(|a| argument(a, self_type)).collect();
+    let mut text = format!("fn {}({})", name, args.join(", "));
+    if let Some(ret) = ret {
+        text.push_str(&format!(" : {}", ret.syntax()));
+    }
+    return text;
+}
+
+// `self` needs no annotation when it has the type of its impl
+fn argument(arg: &Argument, self_type: Option<&Type>) -> String {
+    let mut text = format!(
+        "{}{}{}",
+        if arg.mutable { "mut " } else { "" },
+        if arg.reference { "ref " } else { "" },
+        arg.name
+    );
+    let implied = arg.name == "self" && (arg.ty == Type::SelfAlias || Some(&arg.ty) == self_type);
+    if !implied {
+        text.push_str(&format!(": {}", arg.ty.syntax()));
+    }
+    return text;
+}
@@ -1,16 +1,30 @@
-use std::{collections::HashMap, env};
+use std::collections::BTreeMap;
 
 use crate::{
-    ast::{Program, Value},
+    ast::{Program, Term, Value},
+    constants::Error,
+    log::trace,
     properties::{assert_preservation, assert_progess},
     reduction::Evaluate,
-    state::{StackFrame, State, Store},
+    span::Span,
+    state::{StackFrame, State, Store, Trace},
     typecheck::TypeCheck,
     typing::TypeEnviroment,
 };
+/// Runs programs one statement at a time, checking the progress and preservation
+/// properties around each step. State carries over between calls to `run`.
+#[derive(Debug, Clone)]
 pub struct Interpreter {
-    pub program_state: State,
+    program_state: State,
     typing_enviroment: TypeEnviroment,
+    output: Vec<String>,                   // sink for lines written by print
+    snapshots: Option<Vec<(Span, State)>>, // the state after each top-level term, when kept
+}
+
+impl Default for Interpreter {
+    fn default() -> Interpreter {
+        return Interpreter::new();
+    }
 }
 
 impl Interpreter {
@@ -18,50 +32,104 @@ impl Interpreter {
         return Interpreter {
             program_state: State::new(
                 vec![StackFrame {
-                    locations: HashMap::new(),
-                    functions: HashMap::new(),
+                    locations: BTreeMap::new(),
+                    functions: BTreeMap::new(),
                 }],
                 Store::new(),
             ),
             typing_enviroment: TypeEnviroment::new(),
+            output: vec![],
+            snapshots: None,
         };
     }
 
-    pub fn run(&mut self, mut ast: Program) -> Result<Value, String> {
+    // ref and mut ref arguments must then be borrowed explicitly at each call site
+    pub fn require_explicit_borrows(&mut self) {
+        self.typing_enviroment.require_explicit_borrows();
+    }
+
+    /// The runtime state: the stack of frames and the store.
+    pub fn state(&self) -> &State {
+        return &self.program_state;
+    }
+
+    /// The type environment after the statements run so far.
+    pub fn environment(&self) -> &TypeEnviroment {
+        return &self.typing_enviroment;
+    }
+
+    /// Every line written by `print`, including those before an error.
+    pub fn output(&self) -> &[String] {
+        return &self.output;
+    }
+
+    /// Keep a copy of the state after each top-level term from now on.
+    pub fn record_snapshots(&mut self) {
+        self.snapshots = Some(vec![]);
+    }
+
+    /// The state after each top-level term that ran, with the term's span.
+    pub fn snapshots(&self) -> &[(Span, State)] {
+        return self.snapshots.as_deref().unwrap_or_default();
+    }
+
+    // wait for the debugger before every statement from now on
+    pub(crate) fn attach(&mut self, trace: Trace) {
+        self.program_state.trace = Some(trace);
+    }
+
+    /// Type check and evaluate a program, returning the value of its last statement,
+    /// which is epsilon for declarations. The first error stops the run, and
+    /// carries its kind and where in the source it happened.
+    pub fn run(&mut self, mut ast: Program) -> Result<Value, Error> {
         // initial type check
         ast.type_check(self.typing_enviroment.clone(), 0)?;
- 
+
+        let mut value = Value::Epsilon;
         for mut term in ast.terms {
-            // assert properties
+            self.program_state.pause(&term, None)?;
+
+            // assert properties, on copies that the debugger does not see
+            let before = self.program_state.untraced();
             assert_progess(
-                self.program_state.clone(),
+                before.clone(),
                 term.clone(),
                 self.typing_enviroment.clone(),
                 0,
             )?;
+
+            // evaluate the term
+            let checked = term.clone();
+            let (s, result) = term.evaluate(self.program_state.clone(), 0)?;
             assert_preservation(
-                self.program_state.clone(),
-                term.clone(),
+                before,
+                checked,
                 self.typing_enviroment.clone(),
+                (s.untraced(), result.clone()),
                 0,
             )?;
-
-            // evaluate the term
-            let (s, _) = term.evaluate(self.program_state.clone(), 0)?;
+            value = match result {
+                Term::Value(v, _) => v,
+                _ => Value::Epsilon,
+            };
 
             // type check the term
             let (gamma2, _) = term.type_check(self.typing_enviroment.clone(), 0)?;
 
             self.typing_enviroment = gamma2;
             self.program_state = s;
+            if let Some(trace) = &self.program_state.trace {
+                trace.flush(&self.program_state.printed);
+            }
+            self.output.append(&mut self.program_state.printed);
+            if let Some(snapshots) = &mut self.snapshots {
+                let span = term.span().unwrap_or_default();
+                snapshots.push((span, self.program_state.untraced()));
+            }
         }
 
-        println!("{:#?}", self.typing_enviroment);
-
-        if env::var("EVAL_OUT").is_ok() {
-            self.program_state.print();
-        }
+        trace!(Typeck, "{:#?}", self.typing_enviroment);
 
-        return Ok(Value::Epsilon);
+        return Ok(value);
     }
 }
@@ -1,67 +1,252 @@
-use regex::Regex;
-
-use crate::token::Token;
+use crate::{
+    constants::LexError,
+    log::{debug, trace},
+    span::Span,
+    token::{Comment, Token, TokenKind},
+    typing::IntType,
+};
 pub struct Lexer {
-    input: Vec<String>,
+    input: Vec<(usize, char)>, // characters with their byte offsets
+    length: usize,             // byte length of the input
     current_position: usize,
+    line: usize,
+    column: usize,
+    comments: Vec<Comment>, // skipped as trivia, but kept for the formatter
 }
 
 impl Lexer {
     pub fn new(input: &str) -> Lexer {
         Lexer {
-            input: Regex::new(r#"[^\W_]+|\S"#)
-                .expect("regex")
-                .captures_iter(input)
-                .map(|c| c.get(0).unwrap().as_str().to_string())
-                .collect::<Vec<String>>(),
+            input: input.char_indices().collect(),
+            length: input.len(),
             current_position: 0,
+            line: 1,
+            column: 1,
+            comments: vec![],
+        }
+    }
+
+    // every comment seen so far, in source order
+    pub fn comments(&self) -> &[Comment] {
+        return &self.comments;
+    }
+
+    // lex the rest of `input` from byte `offset`, so spans are positions in the whole input
+    pub fn starting_at(input: &str, offset: usize) -> Lexer {
+        let before = &input[..offset];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        Lexer {
+            current_position: before.chars().count(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            ..Lexer::new(input)
         }
     }
 
-    fn next_token(&mut self) -> Token {
-        // if token is valid, return token, else return error
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.input
+            .get(self.current_position + offset)
+            .map(|(_, c)| *c)
+    }
+
+    fn offset(&self) -> usize {
         match self.input.get(self.current_position) {
-            Some(token) => {
-                match token.as_str() {
-                    "*" => Token::Deref,
-                    "box" => Token::Box,
-                    "ref" => Token::Ref,
-                    "let" => Token::Let,
-                    "mut" => Token::Mut,
-                    "fn" => Token::Fn,
-                    ":" => Token::Colon,
-                    "," => Token::Comma,
-                    "(" => Token::LParen,
-                    ")" => Token::RParen,
-                    "{" => Token::LCurl,
-                    "}" => Token::RCurl,
-                    "=" => Token::Assign,
-                    _ => {
-                        if token.chars().all(char::is_numeric) {
-                            Token::NumericLiteral(token.parse::<i64>().unwrap())
-                        } else if token.chars().all(char::is_alphabetic) {
-                            Token::Identifier(token.to_string())
-                        } else {
-                            // panic
-                            panic!("Invalid token: {}", token)
+            Some((i, _)) => *i,
+            None => self.length,
+        }
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.current_position += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    // the span from a position saved with `mark` up to the current character
+    fn mark(&self) -> Span {
+        Span::new(self.offset(), self.offset(), self.line, self.column)
+    }
+
+    fn span_from(&self, mark: Span) -> Span {
+        Span {
+            end: self.offset(),
+            ..mark
+        }
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let mut s = String::new();
+        while let Some(c) = self.peek(0).filter(|c| f(*c)) {
+            s.push(c);
+            self.advance();
+        }
+        s
+    }
+
+    // skip whitespace and comments, `// ...` to the end of the line and `/* ... */`
+    fn skip_trivia(&mut self) -> Result<(), LexError> {
+        loop {
+            match (self.peek(0), self.peek(1)) {
+                (Some(c), _) if c.is_whitespace() => {
+                    self.advance();
+                }
+                (Some('/'), Some('/')) => {
+                    let start = self.mark();
+                    let text = self.take_while(|c| c != '\n');
+                    let span = self.span_from(start);
+                    self.comments.push(Comment { text, span });
+                }
+                (Some('/'), Some('*')) => {
+                    let start = self.mark();
+                    let mut text = String::new();
+                    loop {
+                        match (self.peek(0), self.peek(1)) {
+                            (Some('*'), Some('/')) if text.len() >= 2 => {
+                                self.advance();
+                                self.advance();
+                                text.push_str("*/");
+                                break;
+                            }
+                            (Some(c), _) => {
+                                self.advance();
+                                text.push(c);
+                            }
+                            (None, _) => {
+                                return Err(LexError::UnterminatedComment(self.span_from(start)))
+                            }
                         }
                     }
+                    let span = self.span_from(start);
+                    self.comments.push(Comment { text, span });
                 }
+                _ => return Ok(()),
             }
-            None => Token::EOF,
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
-        let mut tokens = Vec::new();
+    fn next_token(&mut self) -> Result<Token, LexError> {
+        self.skip_trivia()?;
+        let start = self.mark();
+        let Some(c) = self.advance() else {
+            return Ok(Token {
+                kind: TokenKind::Eof,
+                span: start,
+            });
+        };
+        let kind = match c {
+            '*' => TokenKind::Deref,
+            '.' => TokenKind::Dot,
+            ':' => TokenKind::Colon,
+            ',' => TokenKind::Comma,
+            '(' => TokenKind::LParen,
+            ')' => TokenKind::RParen,
+            '{' => TokenKind::LCurl,
+            '}' => TokenKind::RCurl,
+            '+' => TokenKind::Plus,
+            '-' => TokenKind::Minus,
+            // operators that may be followed by `=`
+            '=' | '!' | '<' | '>' => {
+                let equals = self.peek(0) == Some('=');
+                if equals {
+                    self.advance();
+                }
+                match (c, equals) {
+                    ('=', false) => TokenKind::Assign,
+                    ('=', true) => TokenKind::Eq,
+                    ('!', true) => TokenKind::Ne,
+                    ('<', false) => TokenKind::Lt,
+                    ('<', true) => TokenKind::Le,
+                    ('>', false) => TokenKind::Gt,
+                    ('>', true) => TokenKind::Ge,
+                    _ => return Err(LexError::UnexpectedCharacter(c, self.span_from(start))),
+                }
+            }
+            c if c.is_ascii_digit() => {
+                // literals like 255u8 are split into digits and type suffix
+                let digits = format!("{}{}", c, self.take_while(|c| c.is_ascii_digit()));
+                let suffix = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                let literal = format!("{}{}", digits, suffix);
+                let Ok(n) = digits.parse::<i128>() else {
+                    return Err(LexError::InvalidLiteral(literal, self.span_from(start)));
+                };
+                let ty = match suffix.as_str() {
+                    "" => None,
+                    _ => match IntType::from_name(&suffix) {
+                        Some(ty) => Some(ty),
+                        None => {
+                            return Err(LexError::InvalidLiteral(literal, self.span_from(start)))
+                        }
+                    },
+                };
+                TokenKind::NumericLiteral(n, ty)
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let word = format!(
+                    "{}{}",
+                    c,
+                    self.take_while(|c| c.is_alphanumeric() || c == '_')
+                );
+                match word.as_str() {
+                    "box" => TokenKind::Box,
+                    "ref" => TokenKind::Ref,
+                    "let" => TokenKind::Let,
+                    "mut" => TokenKind::Mut,
+                    "fn" => TokenKind::Fn,
+                    "trait" => TokenKind::Trait,
+                    "impl" => TokenKind::Impl,
+                    "for" => TokenKind::For,
+                    "true" => TokenKind::BoolLiteral(true),
+                    "false" => TokenKind::BoolLiteral(false),
+                    "assert" => TokenKind::Assert,
+                    "print" => TokenKind::Print,
+                    _ => TokenKind::Identifier(word),
+                }
+            }
+            _ => return Err(LexError::UnexpectedCharacter(c, self.span_from(start))),
+        };
+        Ok(Token {
+            kind,
+            span: self.span_from(start),
+        })
+    }
+
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, LexError> {
+        let mut tokens: Vec<Token> = Vec::new();
         loop {
-            let token = self.next_token();
-            self.current_position += 1;
-            if token == Token::EOF {
+            let Token { kind, span } = self.next_token()?;
+            if kind == TokenKind::Eof {
                 break;
             }
-            tokens.push(token);
+            // a minus directly before a literal is a sign, unless it follows an operand
+            if let TokenKind::NumericLiteral(n, ty) = kind {
+                if tokens.last().map(|t| &t.kind) == Some(&TokenKind::Minus)
+                    && !matches!(
+                        tokens.get(tokens.len().wrapping_sub(2)).map(|t| &t.kind),
+                        Some(
+                            TokenKind::Identifier(_)
+                                | TokenKind::NumericLiteral(..)
+                                | TokenKind::RParen
+                        )
+                    )
+                {
+                    let minus = tokens.pop().unwrap();
+                    tokens.push(Token {
+                        kind: TokenKind::NumericLiteral(-n, ty),
+                        span: minus.span.to(span),
+                    });
+                    continue;
+                }
+            }
+            trace!(Lexer, "Lexed {:?} at {}", kind, span);
+            tokens.push(Token { kind, span });
         }
-        tokens
+        debug!(Lexer, "Lexed {} tokens", tokens.len());
+        Ok(tokens)
     }
 }
@@ -0,0 +1,110 @@
+//! Featherweight muse: a lexer, parser, borrow checking type checker and interpreter.
+//!
+//! The usual pipeline is [`parse`], then [`check`], then [`Interpreter::run`]:
+//!
+//! ```
+//! let program = lightweight_muse::parse("let x = 1\nprint(x)").unwrap();
+//! lightweight_muse::check(&program).unwrap();
+//! let mut interpreter = lightweight_muse::Interpreter::new();
+//! interpreter.run(program).unwrap();
+//! assert_eq!(vec!["1".to_string()], interpreter.output());
+//! ```
+#![allow(clippy::needless_return, clippy::result_large_err)]
+
+pub mod ast;
+pub mod constants;
+pub mod dap;
+pub mod debugger;
+pub mod diagnostic;
+pub mod dot;
+pub mod explain;
+pub mod format;
+pub mod interpreter;
+pub mod json;
+pub mod lexer;
+pub mod lint;
+pub mod log;
+pub mod lsp;
+pub mod parser;
+mod properties;
+mod reduction;
+pub mod repl;
+mod run_tests;
+pub mod span;
+pub mod state;
+pub mod timeline;
+pub mod token;
+mod typecheck;
+pub mod typing;
+
+pub use ast::Program;
+pub use constants::Error;
+pub use interpreter::Interpreter;
+pub use typing::TypeEnviroment;
+
+/// A program that passed the type checker, and the environment it left behind.
+///
+/// The checker annotates the program as it goes, marking each variable use as a
+/// copy or a move and resolving method calls, so this copy is the one to lint.
+#[derive(Debug, Clone)]
+pub struct Checked {
+    pub program: Program,
+    pub environment: TypeEnviroment,
+}
+
+/// Lex and parse source text. A lexer error stops at once, the parser reports
+/// every error it recovered from.
+pub fn parse(source: &str) -> Result<Program, Vec<Error>> {
+    let tokens = lexer::Lexer::new(source)
+        .tokenize()
+        .map_err(|e| vec![e.into()])?;
+    return parser::Parser::new(tokens)
+        .parse()
+        .map_err(|errors| errors.into_iter().map(Error::from).collect());
+}
+
+/// Type check a program in an empty environment, reporting every independent error.
+pub fn check(program: &Program) -> Result<Checked, Vec<Error>> {
+    return check_with(program, TypeEnviroment::new());
+}
+
+/// Type check a program in the given environment, for instance one that
+/// requires explicit borrows.
+pub fn check_with(program: &Program, environment: TypeEnviroment) -> Result<Checked, Vec<Error>> {
+    let mut program = program.clone();
+    let environment = program.check_all(environment, 0)?;
+    return Ok(Checked {
+        program,
+        environment,
+    });
+}
+
+/// Drop the suggested fixes that would not work. Each is applied to `source` on its
+/// own and kept only if the source from `start` on still parses and, checked in
+/// `environment`, has fewer type errors than `errors`.
+pub fn verify_suggestions(
+    errors: &mut [Error],
+    source: &str,
+    start: usize,
+    environment: &TypeEnviroment,
+) {
+    let before = errors.len();
+    for error in errors.iter_mut() {
+        error.suggestions.retain(|suggestion| {
+            if suggestion.edits.iter().any(|edit| edit.span.start < start) {
+                return false;
+            }
+            let patched = diagnostic::apply_edits(source, &suggestion.edits);
+            let Ok(tokens) = lexer::Lexer::starting_at(&patched, start).tokenize() else {
+                return false;
+            };
+            let Ok(program) = parser::Parser::new(tokens).parse() else {
+                return false;
+            };
+            return match check_with(&program, environment.clone()) {
+                Ok(_) => true,
+                Err(after) => after.len() < before,
+            };
+        });
+    }
+}
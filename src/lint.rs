@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{Argument, LVal, Program, Term},
+    span::Span,
+    typing::method_function_name,
+};
+
+// variants are named after the lints, which all happen to be about unused code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[allow(clippy::enum_variant_names)]
+pub enum Lint {
+    UnusedVariables,
+    UnusedMut,
+    UnusedFunctions,
+    UnusedMove,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 4] = [
+        Lint::UnusedVariables,
+        Lint::UnusedMut,
+        Lint::UnusedFunctions,
+        Lint::UnusedMove,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariables => "unused_variables",
+            Lint::UnusedMut => "unused_mut",
+            Lint::UnusedFunctions => "unused_functions",
+            Lint::UnusedMove => "unused_move",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        return Lint::ALL.into_iter().find(|lint| lint.name() == name);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Deny,
+}
+
+// how each lint is reported, every lint warns unless told otherwise
+#[derive(Debug, Clone, Default)]
+pub struct Levels {
+    levels: HashMap<Lint, Level>,
+}
+
+impl Levels {
+    pub fn set(&mut self, lint: Lint, level: Level) {
+        self.levels.insert(lint, level);
+    }
+
+    pub fn get(&self, lint: Lint) -> Level {
+        return *self.levels.get(&lint).unwrap_or(&Level::Warn);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub span: Span,
+}
+
+struct Binding {
+    name: String,
+    span: Span,
+    mutable: bool,
+    moved_into: bool, // initialised by moving another variable
+    read: bool,
+    mutated: bool,
+    checked: bool, // false for arguments whose signature a trait fixes
+}
+
+struct Linter {
+    scopes: Vec<Vec<Binding>>,
+    functions: HashMap<String, Vec<Argument>>,
+    called: HashSet<String>,
+    warnings: Vec<Warning>,
+}
+
+// lint a program that has been type checked, so moves and method calls are resolved
+pub fn lint(program: &Program) -> Vec<Warning> {
+    let mut linter = Linter {
+        scopes: vec![vec![]],
+        functions: HashMap::new(),
+        called: HashSet::new(),
+        warnings: vec![],
+    };
+    for term in &program.terms {
+        linter.declare_functions(term);
+    }
+    for term in &program.terms {
+        linter.visit(term);
+    }
+    linter.close_scope();
+
+    for term in &program.terms {
+        if let Term::FunctionDeclaration { name, span, .. } = term {
+            if !linter.called.contains(name) && !name.starts_with('_') {
+                linter.warn(
+                    Lint::UnusedFunctions,
+                    format!("function {}() is never called", name),
+                    *span,
+                );
+            }
+        }
+    }
+    linter.warnings.sort_by_key(|w| w.span.start);
+    return linter.warnings;
+}
+
+impl Linter {
+    fn warn(&mut self, lint: Lint, message: String, span: Span) {
+        self.warnings.push(Warning {
+            lint,
+            message,
+            span,
+        });
+    }
+
+    // the arguments of every function and method, to see which calls borrow mutably
+    fn declare_functions(&mut self, term: &Term) {
+        match term {
+            Term::FunctionDeclaration { name, args, .. } => {
+                self.functions.insert(name.clone(), args.clone());
+            }
+            Term::Impl {
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => {
+                for method in methods {
+                    if let Term::FunctionDeclaration { name, args, .. } = method {
+                        let function = method_function_name(ty, trait_name.as_deref(), name);
+                        self.functions.insert(function, args.clone());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn binding(&mut self, name: &str) -> Option<&mut Binding> {
+        return self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().find(|b| b.name == name));
+    }
+
+    fn read(&mut self, var: &LVal) {
+        if let Some(binding) = self.binding(&var.get_name()) {
+            binding.read = true;
+        }
+    }
+
+    fn mutate(&mut self, var: &LVal) {
+        if let Some(binding) = self.binding(&var.get_name()) {
+            binding.mutated = true;
+        }
+    }
+
+    fn close_scope(&mut self) {
+        let Some(scope) = self.scopes.pop() else {
+            return;
+        };
+        for b in scope.into_iter().filter(|b| b.checked) {
+            if !b.read && !b.name.starts_with('_') {
+                if b.moved_into {
+                    self.warn(
+                        Lint::UnusedMove,
+                        format!("value moved into {} is never used", b.name),
+                        b.span,
+                    );
+                } else {
+                    self.warn(
+                        Lint::UnusedVariables,
+                        format!("unused variable: {}", b.name),
+                        b.span,
+                    );
+                }
+            }
+            if b.mutable && !b.mutated {
+                self.warn(
+                    Lint::UnusedMut,
+                    format!("{} does not need to be mutable", b.name),
+                    b.span,
+                );
+            }
+        }
+    }
+
+    // parameters passed to `mut ref` arguments are borrowed mutably
+    fn visit_params(&mut self, function: &str, args_from: usize, params: &[Term]) {
+        let args = self.functions.get(function).cloned().unwrap_or_default();
+        for (i, param) in params.iter().enumerate() {
+            self.visit(param);
+            let mutable_borrow = args
+                .get(i + args_from)
+                .is_some_and(|arg| arg.mutable && arg.reference);
+            if let (Term::Variable(var), true) = (param, mutable_borrow) {
+                self.mutate(var);
+            }
+        }
+    }
+
+    fn visit_function(&mut self, args: &[Argument], body: &[Term], checked: bool) {
+        let scope = args
+            .iter()
+            .map(|arg| Binding {
+                name: arg.name.clone(),
+                span: arg.span,
+                mutable: arg.mutable,
+                moved_into: false,
+                read: arg.name == "self",
+                mutated: false,
+                checked,
+            })
+            .collect();
+        self.scopes.push(scope);
+        for term in body {
+            self.visit(term);
+        }
+        self.close_scope();
+    }
+
+    fn visit(&mut self, term: &Term) {
+        match term {
+            Term::Let {
+                mutable,
+                variable,
+                term,
+                ..
+            } => {
+                self.visit(term);
+                let moved_into = matches!(
+                    **term,
+                    Term::Variable(LVal::Variable {
+                        copyable: Some(false),
+                        ..
+                    })
+                );
+                let binding = Binding {
+                    name: variable.get_name(),
+                    span: variable.span(),
+                    mutable: *mutable,
+                    moved_into,
+                    read: false,
+                    mutated: false,
+                    checked: true,
+                };
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.push(binding);
+                }
+            }
+            Term::Assign { variable, term, .. } => {
+                self.visit(term);
+                // writing through a reference reads it, writing through a box changes it
+                if let LVal::Deref { .. } = variable {
+                    self.read(variable);
+                }
+                self.mutate(variable);
+            }
+            Term::Variable(var) => self.read(var),
+            Term::Ref { mutable, var, .. } => {
+                self.read(var);
+                if *mutable {
+                    self.mutate(var);
+                }
+            }
+            Term::Box { term, .. } | Term::Print { term, .. } => self.visit(term),
+            Term::Assert { cond, .. } => self.visit(cond),
+            Term::BinaryOp { lhs, rhs, .. } => {
+                self.visit(lhs);
+                self.visit(rhs);
+            }
+            Term::FunctionCall { name, params, .. } => {
+                self.called.insert(name.clone());
+                self.visit_params(name, 0, params);
+            }
+            Term::MethodCall {
+                receiver,
+                params,
+                function,
+                ..
+            } => {
+                self.read(receiver);
+                let Some(function) = function else {
+                    return;
+                };
+                self.called.insert(function.clone());
+                let borrows_mutably = self
+                    .functions
+                    .get(function)
+                    .and_then(|args| args.first())
+                    .is_some_and(|arg| arg.mutable && arg.reference);
+                if borrows_mutably {
+                    self.mutate(receiver);
+                }
+                self.visit_params(&function.clone(), 1, params);
+            }
+            Term::FunctionDeclaration { args, body, .. } => self.visit_function(args, body, true),
+            Term::Impl {
+                trait_name,
+                methods,
+                ..
+            } => {
+                for method in methods {
+                    if let Term::FunctionDeclaration { args, body, .. } = method {
+                        // a trait decides how its methods take their arguments
+                        self.visit_function(args, body, trait_name.is_none());
+                    }
+                }
+            }
+            Term::TraitDeclaration { .. } | Term::Value(..) => {}
+        }
+    }
+}
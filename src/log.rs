@@ -0,0 +1,160 @@
+//! Leveled tracing for each stage of the pipeline, off unless a level is set.
+//!
+//! ```
+//! use lightweight_muse::log::{self, Level, Target};
+//!
+//! log::set_level(Target::Typeck, Some(Level::Debug));
+//! ```
+
+use std::fmt::{self, Display};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error = 1,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    pub fn from_name(name: &str) -> Option<Level> {
+        return [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Trace,
+        ]
+        .into_iter()
+        .find(|level| level.to_string() == name);
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// the subsystem a message comes from, each has its own level
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Lexer,
+    Parser,
+    Typeck,
+    Eval,
+    Properties,
+}
+
+impl Target {
+    pub const ALL: [Target; 5] = [
+        Target::Lexer,
+        Target::Parser,
+        Target::Typeck,
+        Target::Eval,
+        Target::Properties,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Target> {
+        return Target::ALL
+            .into_iter()
+            .find(|target| target.to_string() == name);
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Target::Lexer => "lexer",
+            Target::Parser => "parser",
+            Target::Typeck => "typeck",
+            Target::Eval => "eval",
+            Target::Properties => "properties",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Where enabled messages go. The default writes them to stderr.
+pub trait Sink: Send + Sync {
+    fn log(&self, target: Target, level: Level, message: &fmt::Arguments);
+}
+
+pub struct Stderr;
+
+impl Sink for Stderr {
+    fn log(&self, target: Target, level: Level, message: &fmt::Arguments) {
+        eprintln!("[{} {}] {}", level, target, message);
+    }
+}
+
+// the most verbose level enabled for each target, 0 when it is off
+static LEVELS: [AtomicU8; 5] = [const { AtomicU8::new(0) }; 5];
+static SINK: RwLock<Option<Box<dyn Sink>>> = RwLock::new(None);
+
+/// Enable messages from `target` up to `level`, or turn it off with None.
+pub fn set_level(target: Target, level: Option<Level>) {
+    LEVELS[target as usize].store(level.map_or(0, |l| l as u8), Ordering::Relaxed);
+}
+
+/// Set the same level for every target.
+pub fn set_max_level(level: Option<Level>) {
+    for target in Target::ALL {
+        set_level(target, level);
+    }
+}
+
+/// Send messages to `sink` instead of stderr.
+pub fn set_sink(sink: Box<dyn Sink>) {
+    *SINK.write().unwrap() = Some(sink);
+}
+
+pub fn enabled(target: Target, level: Level) -> bool {
+    return level as u8 <= LEVELS[target as usize].load(Ordering::Relaxed);
+}
+
+#[doc(hidden)]
+pub fn write(target: Target, level: Level, message: fmt::Arguments) {
+    match SINK.read().unwrap().as_ref() {
+        Some(sink) => sink.log(target, level, &message),
+        None => Stderr.log(target, level, &message),
+    }
+}
+
+// messages are only formatted when their level is enabled
+macro_rules! debug {
+    ($target:ident, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Target::$target, $crate::log::Level::Debug) {
+            $crate::log::write(
+                $crate::log::Target::$target,
+                $crate::log::Level::Debug,
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+macro_rules! trace {
+    ($target:ident, $($arg:tt)+) => {
+        if $crate::log::enabled($crate::log::Target::$target, $crate::log::Level::Trace) {
+            $crate::log::write(
+                $crate::log::Target::$target,
+                $crate::log::Level::Trace,
+                format_args!($($arg)+),
+            );
+        }
+    };
+}
+
+pub(crate) use debug;
+pub(crate) use trace;
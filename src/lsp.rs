@@ -0,0 +1,550 @@
+//! A language server for muse, spoken over stdio by the `muse-lsp` binary.
+//!
+//! Documents are synced whole. Each change is lexed and parsed once, recovering
+//! from syntax errors, then checked and linted, and the errors and warnings are
+//! published as diagnostics. Hover shows a variable's type
+//! at that point in the program, so a moved variable shows as moved and a reference
+//! shows what it borrows. Go to definition finds the `let`, argument or function a
+//! name refers to, and document symbols list the functions, traits and impls.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{
+    ast::{MethodSignature, Program, Term},
+    constants::{Error, ErrorKind},
+    json::{self, object, Json},
+    lexer::Lexer,
+    lint,
+    parser::Parser,
+    span::Span,
+    token::{Token, TokenKind},
+    typecheck,
+    typing::{Type, TypeEnviroment},
+};
+
+// the JSON-RPC and LSP numbers this server uses
+const METHOD_NOT_FOUND: i64 = -32601;
+const INTERNAL_ERROR: i64 = -32603;
+const PARSE_ERROR: i64 = -32700;
+const SYNC_FULL: usize = 1;
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+const SYMBOL_CLASS: usize = 5;
+const SYMBOL_METHOD: usize = 6;
+const SYMBOL_INTERFACE: usize = 11;
+const SYMBOL_FUNCTION: usize = 12;
+
+#[derive(Debug, Default)]
+pub struct Server {
+    documents: BTreeMap<String, Document>, // each open document by uri
+    shutdown: bool,
+}
+
+// an open document, analyzed when it changes rather than on every request
+#[derive(Debug)]
+struct Document {
+    text: String,
+    tokens: Vec<Token>,
+    program: Program,   // the statements that parsed
+    errors: Vec<Error>, // the lex or parse errors
+}
+
+impl Document {
+    fn new(text: &str) -> Document {
+        let (tokens, program, errors) = match Lexer::new(text).tokenize() {
+            Ok(tokens) => {
+                let (program, errors) = Parser::new(tokens.clone()).parse_recovering();
+                (
+                    tokens,
+                    program,
+                    errors.into_iter().map(Error::from).collect(),
+                )
+            }
+            Err(e) => (vec![], Program { terms: vec![] }, vec![e.into()]),
+        };
+        return Document {
+            text: text.to_string(),
+            tokens,
+            program,
+            errors,
+        };
+    }
+}
+
+impl Server {
+    pub fn new() -> Server {
+        return Server::default();
+    }
+
+    /// Handle one request or notification, returning the messages to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let id = message.get("id");
+        let params = message.get("params");
+        let uri = params.at("textDocument.uri").as_str().unwrap_or_default();
+        let result = match message.get("method").as_str().unwrap_or_default() {
+            "initialize" => object([
+                (
+                    "capabilities",
+                    object([
+                        ("textDocumentSync", SYNC_FULL.into()),
+                        ("hoverProvider", true.into()),
+                        ("definitionProvider", true.into()),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    object([
+                        ("name", "muse-lsp".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ]),
+            "textDocument/didOpen" => {
+                let text = params.at("textDocument.text").as_str().unwrap_or_default();
+                let document = Document::new(text);
+                let diagnostics = publish(uri, &document);
+                self.documents.insert(uri.to_string(), document);
+                return vec![diagnostics];
+            }
+            "textDocument/didChange" => {
+                // whole documents are synced, so the last change is the new text
+                let changes = params.get("contentChanges").as_array();
+                let Some(text) = changes.last().and_then(|c| c.get("text").as_str()) else {
+                    return vec![];
+                };
+                let document = Document::new(text);
+                let diagnostics = publish(uri, &document);
+                self.documents.insert(uri.to_string(), document);
+                return vec![diagnostics];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![publish(uri, &Document::new(""))];
+            }
+            "textDocument/hover" => self.at_position(params, hover),
+            "textDocument/definition" => self.at_position(params, |document, offset| {
+                let span = definition(document, offset)?;
+                return Some(object([
+                    ("uri", uri.into()),
+                    ("range", range(&document.text, span)),
+                ]));
+            }),
+            "textDocument/documentSymbol" => match self.documents.get(uri) {
+                Some(document) => symbols(document).into(),
+                None => Json::Null,
+            },
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            method => {
+                // notifications we do not know are ignored, requests get an error
+                if id.is_null() {
+                    return vec![];
+                }
+                return vec![error_response(
+                    id.clone(),
+                    METHOD_NOT_FOUND,
+                    format!("Unknown method {}", method),
+                )];
+            }
+        };
+        if id.is_null() {
+            return vec![];
+        }
+        return vec![object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.clone()),
+            ("result", result),
+        ])];
+    }
+
+    /// Whether `shutdown` came before `exit`, as a clean exit needs.
+    pub fn is_shut_down(&self) -> bool {
+        return self.shutdown;
+    }
+
+    // answer a request about a position in an open document, or with null
+    fn at_position(
+        &self,
+        params: &Json,
+        answer: impl Fn(&Document, usize) -> Option<Json>,
+    ) -> Json {
+        let uri = params.at("textDocument.uri").as_str().unwrap_or_default();
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let offset = offset(&document.text, params.get("position"));
+        return answer(document, offset).unwrap_or(Json::Null);
+    }
+}
+
+/// Serve messages from `input` until the client says `exit`, returning the exit code.
+pub fn serve(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<u8> {
+    let mut server = Server::new();
+    while let Some(body) = json::read_frame(input)? {
+        let message = match Json::parse(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                json::write_frame(output, &error_response(Json::Null, PARSE_ERROR, e))?;
+                continue;
+            }
+        };
+        if message.get("method").as_str() == Some("exit") {
+            return Ok(if server.is_shut_down() { 0 } else { 1 });
+        }
+        // a bug in the checker must not take the editor's server down with it
+        let replies = match panic::catch_unwind(AssertUnwindSafe(|| server.handle(&message))) {
+            Ok(replies) => replies,
+            Err(panic) => internal_error(&message, panic),
+        };
+        for reply in replies {
+            json::write_frame(output, &reply)?;
+        }
+    }
+    // the client went away without asking us to exit
+    return Ok(1);
+}
+
+// answer a message whose handling panicked, with an error for a request or with
+// the failure as a diagnostic for a document that changed
+fn internal_error(message: &Json, panic: Box<dyn Any + Send>) -> Vec<Json> {
+    let reason = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+        (Some(reason), _) => reason.to_string(),
+        (_, Some(reason)) => reason.clone(),
+        _ => "unknown".to_string(),
+    };
+    let text = format!("Internal error: {}", reason);
+    let id = message.get("id");
+    if !id.is_null() {
+        return vec![error_response(id.clone(), INTERNAL_ERROR, text)];
+    }
+    let uri = message
+        .at("params.textDocument.uri")
+        .as_str()
+        .unwrap_or_default();
+    let diagnostic = object([
+        ("range", range("", Span::default())),
+        ("severity", SEVERITY_ERROR.into()),
+        ("source", "muse".into()),
+        ("message", text.into()),
+    ]);
+    return vec![object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            object([
+                ("uri", uri.into()),
+                ("diagnostics", vec![diagnostic].into()),
+            ]),
+        ),
+    ])];
+}
+
+fn error_response(id: Json, code: i64, message: String) -> Json {
+    return object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            object([("code", code.into()), ("message", message.into())]),
+        ),
+    ]);
+}
+
+// LSP counts lines from 0 and characters in UTF-16 code units
+fn position(source: &str, offset: usize) -> Json {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    return object([
+        ("line", before.matches('\n').count().into()),
+        (
+            "character",
+            before[line_start..].encode_utf16().count().into(),
+        ),
+    ]);
+}
+
+fn range(source: &str, span: Span) -> Json {
+    return object([
+        ("start", position(source, span.start)),
+        ("end", position(source, span.end)),
+    ]);
+}
+
+// the byte offset of an LSP position, clamped to the end of its line
+fn offset(source: &str, position: &Json) -> usize {
+    let line = position.get("line").as_u64().unwrap_or(0) as usize;
+    let character = position.get("character").as_u64().unwrap_or(0) as usize;
+    let mut start = 0;
+    for _ in 0..line {
+        match source[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return source.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in source[start..].char_indices() {
+        if units >= character || c == '\n' {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    return source.len();
+}
+
+// publish the syntax errors in a document, or its type errors, or if it checks,
+// the lint warnings
+fn publish(uri: &str, document: &Document) -> Json {
+    let source = document.text.as_str();
+    let checked = match document.errors.is_empty() {
+        true => crate::check(&document.program),
+        false => Err(document.errors.clone()),
+    };
+    let diagnostics: Vec<Json> = match checked {
+        Ok(checked) => lint::lint(&checked.program)
+            .iter()
+            .map(|warning| {
+                object([
+                    ("range", range(source, warning.span)),
+                    ("severity", SEVERITY_WARNING.into()),
+                    ("code", warning.lint.name().into()),
+                    ("source", "muse".into()),
+                    ("message", warning.message.clone().into()),
+                ])
+            })
+            .collect(),
+        Err(errors) => errors.iter().map(|e| diagnostic(e, uri, source)).collect(),
+    };
+    return object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ]);
+}
+
+fn diagnostic(error: &Error, uri: &str, source: &str) -> Json {
+    let (code, message) = match &error.kind {
+        ErrorKind::Type(e) => (Json::from(e.code()), e.description()),
+        kind => (Json::Null, kind.to_string()),
+    };
+    // the secondary labels, like where a value was moved or borrowed
+    let related: Vec<Json> = error
+        .labels
+        .iter()
+        .map(|label| {
+            object([
+                (
+                    "location",
+                    object([("uri", uri.into()), ("range", range(source, label.span))]),
+                ),
+                ("message", label.message.clone().into()),
+            ])
+        })
+        .collect();
+    return object([
+        ("range", range(source, error.span.unwrap_or_default())),
+        ("severity", SEVERITY_ERROR.into()),
+        ("code", code),
+        ("source", "muse".into()),
+        ("message", message.into()),
+        ("relatedInformation", related.into()),
+    ]);
+}
+
+// the identifier token under `offset`, and its index
+fn identifier_at(tokens: &[Token], offset: usize) -> Option<(usize, &str)> {
+    return tokens
+        .iter()
+        .enumerate()
+        .find_map(|(i, token)| match &token.kind {
+            TokenKind::Identifier(name)
+                if token.span.start <= offset && offset <= token.span.end =>
+            {
+                Some((i, name.as_str()))
+            }
+            _ => None,
+        });
+}
+
+// where `name` is written inside `span`, like the name of a function in its declaration
+fn name_span(tokens: &[Token], span: Span, name: &str) -> Span {
+    return tokens
+        .iter()
+        .filter(|t| span.start <= t.span.start && t.span.end <= span.end)
+        .find(|t| t.kind == TokenKind::Identifier(name.to_string()))
+        .map_or(span, |t| t.span);
+}
+
+fn hover(document: &Document, offset: usize) -> Option<Json> {
+    let Document {
+        text: source,
+        tokens,
+        program,
+        ..
+    } = document;
+    let (i, name) = identifier_at(tokens, offset)?;
+    let name = name.to_string();
+
+    // look before the statement, and after it for the name a `let` declares
+    let (before, after) = typecheck::scope_at(&program.terms, TypeEnviroment::new(), offset, 0);
+    let (gamma, slot) = match before.get_partial(&name) {
+        Ok(slot) => (&before, slot),
+        Err(_) => (&after, after.get_partial(&name).ok()?),
+    };
+    let mut text = match &slot.value {
+        Type::Function { args, ret } => {
+            let signature = MethodSignature {
+                name: name.clone(),
+                args: args.clone(),
+                ty: ret.as_deref().cloned(),
+            };
+            format!("```muse\n{}\n```", signature)
+        }
+        ty => format!("```muse\n{}: {}\n```", name, gamma.describe(ty)),
+    };
+    let moved = matches!(&slot.value, Type::Undefined(_))
+        || matches!(&slot.value, Type::Box(t) if matches!(**t, Type::Undefined(_)));
+    if let (true, Some(span)) = (moved, gamma.moved_at(&name)) {
+        text.push_str(&format!("\n\nMoved out at line {}", span.line));
+    }
+    return Some(object([
+        (
+            "contents",
+            object([("kind", "markdown".into()), ("value", text.into())]),
+        ),
+        ("range", range(source, tokens[i].span)),
+    ]));
+}
+
+fn definition(document: &Document, offset: usize) -> Option<Span> {
+    let Document {
+        tokens, program, ..
+    } = document;
+    let (i, name) = identifier_at(tokens, offset)?;
+    let previous = i.checked_sub(1).map(|i| &tokens[i].kind);
+    let next = tokens.get(i + 1).map(|t| &t.kind);
+
+    if previous == Some(&TokenKind::Fn) {
+        return Some(tokens[i].span);
+    }
+    if next == Some(&TokenKind::LParen) && previous != Some(&TokenKind::Dot) {
+        return program.terms.iter().find_map(|term| match term {
+            Term::FunctionDeclaration { name: n, span, .. } if n == name => {
+                Some(name_span(tokens, *span, name))
+            }
+            _ => None,
+        });
+    }
+    let mut found = None;
+    binding(&program.terms, tokens, name, offset, &mut found);
+    return found;
+}
+
+// the innermost `let` or argument that binds `name` where `offset` is
+fn binding(terms: &[Term], tokens: &[Token], name: &str, offset: usize, found: &mut Option<Span>) {
+    for term in terms {
+        let span = term.span().unwrap_or_default();
+        match term {
+            Term::Let { variable, .. } if variable.get_name() == name && span.start <= offset => {
+                *found = Some(variable.span());
+            }
+            Term::FunctionDeclaration { args, body, .. }
+                if span.start <= offset && offset <= span.end =>
+            {
+                for arg in args.iter().filter(|arg| arg.name == name) {
+                    *found = Some(name_span(tokens, arg.span, name));
+                }
+                binding(body, tokens, name, offset, found);
+            }
+            Term::Impl { methods, .. } if span.start <= offset && offset <= span.end => {
+                binding(methods, tokens, name, offset, found);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn symbols(document: &Document) -> Vec<Json> {
+    return document
+        .program
+        .terms
+        .iter()
+        .filter_map(|term| symbol(term, SYMBOL_FUNCTION, &document.text, &document.tokens))
+        .collect();
+}
+
+fn symbol(term: &Term, function_kind: usize, source: &str, tokens: &[Token]) -> Option<Json> {
+    let (name, detail, kind, span, children) = match term {
+        Term::FunctionDeclaration {
+            name,
+            args,
+            ty,
+            span,
+            ..
+        } => {
+            let signature = MethodSignature {
+                name: name.clone(),
+                args: args.clone(),
+                ty: ty.clone(),
+            };
+            (
+                name.clone(),
+                signature.to_string(),
+                function_kind,
+                *span,
+                vec![],
+            )
+        }
+        Term::TraitDeclaration { name, span, .. } => (
+            name.clone(),
+            format!("trait {}", name),
+            SYMBOL_INTERFACE,
+            *span,
+            vec![],
+        ),
+        Term::Impl {
+            trait_name,
+            ty,
+            methods,
+            span,
+        } => {
+            let name = match trait_name {
+                Some(trait_name) => format!("impl {} for {}", trait_name, ty.syntax()),
+                None => format!("impl {}", ty.syntax()),
+            };
+            let methods = methods
+                .iter()
+                .filter_map(|m| symbol(m, SYMBOL_METHOD, source, tokens))
+                .collect();
+            (name.clone(), name, SYMBOL_CLASS, *span, methods)
+        }
+        _ => return None,
+    };
+    // an impl has no name of its own to select, so select its first line
+    let selection = match term {
+        Term::Impl { .. } => Span {
+            end: source[span.start..span.end]
+                .find('\n')
+                .map_or(span.end, |i| span.start + i),
+            ..span
+        },
+        _ => name_span(tokens, span, &name),
+    };
+    return Some(object([
+        ("name", name.into()),
+        ("detail", detail.into()),
+        ("kind", kind.into()),
+        ("range", range(source, span)),
+        ("selectionRange", range(source, selection)),
+        ("children", Json::Array(children)),
+    ]));
+}
@@ -1,99 +1,638 @@
-use std::env;
-
-use typecheck::TypeCheck;
-use typing::TypeEnviroment;
-
-mod ast;
-mod constants;
-mod interpreter;
-mod lexer;
-mod parser;
-mod properties;
-mod reduction;
-mod run_tests;
-mod state;
-mod token;
-mod typecheck;
-mod typing;
-
-fn main() {
-    env::set_var("RUST_BACKTRACE", "1");
-    // take first arg as file dir
-    let args: Vec<String> = std::env::args().collect();
-
-    if args[1] == "-h" || args[1] == "--help" {
-        println!("Usage: ./main [options] <file>");
-        println!("Options:");
-        println!("\t-h, -help\tDisplay this message");
-        println!("\t-l, -lex\tEnable lexer output");
-        println!("\t-p, -parse\tEnable parser output");
-        println!("\t-t, -typecheck\tEnable typecheck output");
-        println!("\t-e, -eval\tEnable eval output");
-        return;
+#![allow(clippy::needless_return)]
+
+use std::io::{BufRead, IsTerminal, Read, Write};
+use std::process::ExitCode;
+
+use lightweight_muse::{
+    ast, constants::ErrorKind, debugger, diagnostic, dot, explain, lexer, lint, log, repl,
+    timeline, Error, Interpreter, TypeEnviroment,
+};
+
+// exit codes, so scripts can tell how far a program got
+const EXIT_USAGE: u8 = 1; // bad arguments, or a file that cannot be read or written
+const EXIT_PARSE: u8 = 2; // lex or parse errors
+const EXIT_TYPE: u8 = 3; // type errors, or a lint set to deny
+const EXIT_RUNTIME: u8 = 4; // an error while running
+const EXIT_UNFORMATTED: u8 = 5; // fmt --check found a file that is not formatted
+
+const USAGE: &str = "\
+Usage: lightweight-muse <command> [options] <file>...
+       lightweight-muse [options] <file>...    same as run
+       lightweight-muse repl [options]
+       lightweight-muse debug [options] <file>
+       lightweight-muse explain <code>
+
+Commands:
+        lex             Print the tokens of each file
+        parse           Print the syntax tree of each file
+        check           Type check and lint each file
+        run             Check and run each file
+        fmt             Print each file formatted
+        repl            Enter statements one at a time, :help lists the commands
+        debug           Step through a file, reading commands from stdin, help lists them
+        timeline        Print each file annotated with its moves, copies, borrows and drops
+
+Files:
+        -               Read the program from stdin
+
+Options:
+        -h, --help              Display this message
+        --emit <stages>         Also print tokens, ast, types or state, comma separated
+        -b, --explicit-borrows  Require explicit ref/mut ref at call sites
+        -f, --fix               Apply the suggested fixes to the file
+        --check                 With fmt, list the files that are not formatted instead
+        --break <target>        With debug, break at a line number or a function
+        --dot <file>            Write the store and stack as Graphviz graphs, one per statement
+        --dot-at <target>       With --dot, only draw them before a line or on entering a function
+        --html <file>           With timeline, also write it as an HTML page
+        --allow <lint>          Silence a lint
+        --deny <lint>           Make a lint an error
+        -v, -vv                 Trace every stage at debug or trace level
+        --log <target=level>    Trace lexer, parser, typeck, eval or properties, comma separated
+
+Exit codes: 0 success, 1 usage, 2 parse error, 3 type error, 4 runtime error,
+            5 not formatted";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+    Lex,
+    Parse,
+    Check,
+    Run,
+    Fmt,
+    Repl,
+    Debug,
+    Timeline,
+}
+
+// intermediate output printed on the way to the command's own output
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Emit {
+    Tokens,
+    Ast,
+    Types,
+    State,
+}
+
+struct Options {
+    command: Command,
+    files: Vec<String>,
+    emit: Vec<Emit>,
+    explicit_borrows: bool,
+    fix: bool,
+    check: bool,
+    breakpoints: Vec<debugger::Breakpoint>,
+    dot: Option<String>,
+    dot_at: Vec<debugger::Breakpoint>,
+    html: Option<String>,
+    levels: lint::Levels,
+    log: Vec<(Option<log::Target>, log::Level)>, // None sets every target
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    if args.first().map(|a| a.as_str()) == Some("explain") {
+        return match args.get(1).and_then(|code| explain::explain(code)) {
+            Some(text) => {
+                print!("{}", text);
+                ExitCode::SUCCESS
+            }
+            None => {
+                eprintln!(
+                    "No explanation for error code {}",
+                    args.get(1).map_or("", |c| c)
+                );
+                ExitCode::from(EXIT_USAGE)
+            }
+        };
+    }
+
+    let options = match parse_args(&args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{}\n\n{}", message, USAGE);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
+
+    // tracing is off unless asked for, later flags override earlier ones
+    for (target, level) in &options.log {
+        match target {
+            Some(target) => log::set_level(*target, Some(*level)),
+            None => log::set_max_level(Some(*level)),
+        }
+    }
+
+    if options.command == Command::Repl {
+        return ExitCode::from(run_repl(&options));
+    }
+    if options.command == Command::Debug {
+        return ExitCode::from(run_debugger(&options));
+    }
+
+    // keep going through every file, but exit with the first failure
+    let mut status = 0;
+    for file in &options.files {
+        let code = process(file, &options);
+        if status == 0 {
+            status = code;
+        }
+    }
+    return ExitCode::from(status);
+}
+
+// None when help was asked for
+fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut args = args.iter().peekable();
+    let command = match args.peek().map(|a| a.as_str()) {
+        Some("lex") => Some(Command::Lex),
+        Some("parse") => Some(Command::Parse),
+        Some("check") => Some(Command::Check),
+        Some("run") => Some(Command::Run),
+        Some("fmt") => Some(Command::Fmt),
+        Some("repl") => Some(Command::Repl),
+        Some("debug") => Some(Command::Debug),
+        Some("timeline") => Some(Command::Timeline),
+        _ => None,
+    };
+    if command.is_some() {
+        args.next();
     }
+    let mut options = Options {
+        command: command.unwrap_or(Command::Run),
+        files: vec![],
+        emit: vec![],
+        explicit_borrows: false,
+        fix: false,
+        check: false,
+        breakpoints: vec![],
+        dot: None,
+        dot_at: vec![],
+        html: None,
+        levels: lint::Levels::default(),
+        log: vec![],
+    };
 
-    for arg in &args {
+    while let Some(arg) = args.next() {
         match arg.as_str() {
-            "-l" | "-lex" => {
-                env::set_var("LEX_OUT", "1");
+            "-h" | "--help" => return Ok(None),
+            "-b" | "--explicit-borrows" => options.explicit_borrows = true,
+            "-f" | "--fix" => options.fix = true,
+            "--check" => options.check = true,
+            "--break" => {
+                let target = args.next().ok_or("--break needs a line or a function")?;
+                options
+                    .breakpoints
+                    .push(debugger::Breakpoint::parse(target));
+            }
+            "--dot" => options.dot = Some(args.next().ok_or("--dot needs a file")?.clone()),
+            "--html" => options.html = Some(args.next().ok_or("--html needs a file")?.clone()),
+            "--dot-at" => {
+                let target = args.next().ok_or("--dot-at needs a line or a function")?;
+                options.dot_at.push(debugger::Breakpoint::parse(target));
             }
-            "-p" | "-parse" => {
-                env::set_var("PARSE_OUT", "1");
+            "--emit" => {
+                let stages = args.next().ok_or("--emit needs a list of stages")?;
+                for stage in stages.split(',') {
+                    options.emit.push(match stage {
+                        "tokens" => Emit::Tokens,
+                        "ast" => Emit::Ast,
+                        "types" => Emit::Types,
+                        "state" => Emit::State,
+                        _ => return Err(format!("Unknown stage to emit: {}", stage)),
+                    });
+                }
             }
-            "-t" | "-typecheck" => {
-                env::set_var("TYPE_OUT", "1");
+            "--allow" | "--deny" => {
+                let name = args.next().ok_or(format!("{} needs a lint name", arg))?;
+                let lint = lint::Lint::from_name(name).ok_or(format!("Unknown lint {}", name))?;
+                let level = if arg == "--allow" {
+                    lint::Level::Allow
+                } else {
+                    lint::Level::Deny
+                };
+                options.levels.set(lint, level);
             }
-            "-e" | "-eval" => {
-                env::set_var("EVAL_OUT", "1");
+            "-v" => options.log.push((None, log::Level::Debug)),
+            "-vv" => options.log.push((None, log::Level::Trace)),
+            "--log" => {
+                let targets = args.next().ok_or("--log needs a list of targets")?;
+                for target in targets.split(',') {
+                    let (name, level) = target.split_once('=').unwrap_or((target, "debug"));
+                    let target = log::Target::from_name(name)
+                        .ok_or(format!("Unknown log target {}", name))?;
+                    let level = log::Level::from_name(level)
+                        .ok_or(format!("Unknown log level {}", level))?;
+                    options.log.push((Some(target), level));
+                }
             }
-            _ => {}
+            "-" => options.files.push(arg.clone()),
+            _ if arg.starts_with('-') => return Err(format!("Unknown option {}", arg)),
+            _ => options.files.push(arg.clone()),
         }
     }
 
-    // get file contents
-    let file_dir = &args[args.len() - 1];
-    if let Ok(file_contents) = std::fs::read_to_string(file_dir) {
-        // tokenize
-        let mut lexer = lexer::Lexer::new(&file_contents);
-        let tokens = lexer.tokenize();
-        if env::var("LEX_OUT").is_ok() {
-            println!("{:?}", tokens);
-        }
+    if options.files.is_empty() && options.command != Command::Repl {
+        return Err("No input files".to_string());
+    }
+    // the debugger reads its commands from stdin
+    if options.command == Command::Debug && (options.files.len() != 1 || options.files[0] == "-") {
+        return Err("debug needs exactly one file".to_string());
+    }
+    if options.dot.is_some() && options.files.len() != 1 {
+        return Err("--dot needs exactly one file".to_string());
+    }
+    if options.html.is_some() && options.files.len() != 1 {
+        return Err("--html needs exactly one file".to_string());
+    }
+    if options.dot.is_none() && !options.dot_at.is_empty() {
+        return Err("--dot-at needs --dot".to_string());
+    }
+    return Ok(Some(options));
+}
+
+fn read_source(file: &str) -> std::io::Result<String> {
+    if file == "-" {
+        let mut source = String::new();
+        std::io::stdin().read_to_string(&mut source)?;
+        return Ok(source);
+    }
+    return std::fs::read_to_string(file);
+}
 
-        // parse
-        let mut parser = parser::Parser::new(tokens);
-        let mut ast = parser.parse();
+fn report(errors: &[Error], source: &str, file: &str) {
+    for e in errors {
+        eprint!(
+            "{}",
+            diagnostic::render(e, source, file, diagnostic::use_color())
+        );
+    }
+}
 
-        if env::var("PARSE_OUT").is_ok() {
-            println!("{:#?}", ast);
+// run one file through the stages its command needs, returning the exit code
+fn process(file: &str, options: &Options) -> u8 {
+    let source = match read_source(file) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", file, e);
+            return EXIT_USAGE;
         }
+    };
+    let name = if file == "-" { "<stdin>" } else { file };
 
-        // interpret
-        let mut interpreter = interpreter::Interpreter::new();
-        match ast.type_check(TypeEnviroment::new(), 0) {
-            Ok(_) => {}
+    if options.command == Command::Lex || options.emit.contains(&Emit::Tokens) {
+        match lexer::Lexer::new(&source).tokenize() {
+            Ok(tokens) => {
+                for token in tokens {
+                    println!("{:?}", token);
+                }
+            }
             Err(e) => {
-                println!("TYPE ERROR: {}", e);
-                return;
+                report(&[e.into()], &source, name);
+                return EXIT_PARSE;
             }
-        };
+        }
+    }
+    if options.command == Command::Lex {
+        return 0;
+    }
+    if options.command == Command::Fmt {
+        return format(&source, name, options);
+    }
+    if options.command == Command::Timeline {
+        return timeline(&source, name, options);
+    }
 
-        // return;s
-        let res = interpreter.run(ast);
+    let ast = match lightweight_muse::parse(&source) {
+        Ok(ast) => ast,
+        Err(errors) => {
+            report(&errors, &source, name);
+            return EXIT_PARSE;
+        }
+    };
+    if options.command == Command::Parse || options.emit.contains(&Emit::Ast) {
+        println!("{:#?}", ast);
+    }
+    if options.command == Command::Parse {
+        return 0;
+    }
 
-        // print result
-        match res {
-            Ok(v) => match v {
-                ast::Value::Epsilon => {}
-                _ => println!("{:?}", v),
-            },
-            Err(e) => {
-                println!("ERROR: {}", e);
+    let mut gamma = TypeEnviroment::new();
+    if options.explicit_borrows {
+        gamma.require_explicit_borrows();
+    }
+    // report every independent type error before giving up
+    let checked = match lightweight_muse::check_with(&ast, gamma.clone()) {
+        Ok(checked) => checked,
+        Err(mut errors) => {
+            lightweight_muse::verify_suggestions(&mut errors, &source, 0, &gamma);
+            report(&errors, &source, name);
+            if options.fix {
+                fix(&errors, &source, file);
             }
+            return EXIT_TYPE;
+        }
+    };
+    if options.emit.contains(&Emit::Types) {
+        println!("{:#?}", checked.environment);
+    }
+
+    // lint the checked program, stopping only if a denied lint fired
+    let mut denied = false;
+    for warning in lint::lint(&checked.program) {
+        let level = options.levels.get(warning.lint);
+        if level == lint::Level::Allow {
+            continue;
         }
+        denied |= level == lint::Level::Deny;
+        eprint!(
+            "{}",
+            diagnostic::render_warning(
+                &warning,
+                level == lint::Level::Deny,
+                &source,
+                name,
+                diagnostic::use_color()
+            )
+        );
+    }
+    if denied {
+        return EXIT_TYPE;
+    }
+    if options.command == Command::Check {
+        return 0;
+    }
+
+    // with --dot-at the program runs once, in the debugger that stops at the points
+    let (res, output, state, graphs) = if !options.dot_at.is_empty() {
+        let (graphs, session) = match dot_points(&source, name, options) {
+            Ok(run) => run,
+            Err(errors) => {
+                report(&errors, &source, name);
+                return EXIT_TYPE;
+            }
+        };
+        let res = match session.error() {
+            Some(e) => Err(e.clone()),
+            None => Ok(session.value().cloned().unwrap_or(ast::Value::Epsilon)),
+        };
+        // only missing if the program's thread panicked, which it reported
+        let Some(state) = session.final_state().cloned() else {
+            return EXIT_RUNTIME;
+        };
+        (res, session.output().to_vec(), state, graphs)
     } else {
-        println!("No file provided")
+        let mut interpreter = Interpreter::new();
+        if options.explicit_borrows {
+            interpreter.require_explicit_borrows();
+        }
+        if options.dot.is_some() {
+            interpreter.record_snapshots();
+        }
+        let res = interpreter.run(checked.program);
+        let snapshots = interpreter.snapshots().iter();
+        let graphs = snapshots
+            .map(|(span, state)| dot::graph(state, &format!("after line {}", span.line), &[]))
+            .collect();
+        let output = interpreter.output().to_vec();
+        (res, output, interpreter.state().clone(), graphs)
+    };
+
+    for line in output {
+        println!("{}", line);
+    }
+    if options.emit.contains(&Emit::State) {
+        state.print();
+    }
+    if let Some(path) = &options.dot {
+        if let Err(e) = std::fs::write(path, graphs.concat()) {
+            eprintln!("Could not write {}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    }
+
+    match res {
+        Ok(ast::Value::Epsilon) => return 0,
+        Ok(v) => {
+            // the value of a trailing expression, shown as print would
+            println!("{}", state.render(&v).unwrap_or(v.to_string()));
+            return 0;
+        }
+        Err(e) => {
+            report(&[e], &source, name);
+            return EXIT_RUNTIME;
+        }
+    }
+}
+
+// the state at each --dot-at point, found by running the program in the debugger,
+// which is returned once the program finished
+fn dot_points(
+    source: &str,
+    file: &str,
+    options: &Options,
+) -> Result<(Vec<String>, debugger::Debugger), Vec<Error>> {
+    let mut gamma = TypeEnviroment::new();
+    if options.explicit_borrows {
+        gamma.require_explicit_borrows();
+    }
+    let mut session = debugger::Debugger::new(source, file, gamma)?;
+    for point in &options.dot_at {
+        session.add_breakpoint(point.clone());
+    }
+    if !session.at_breakpoint() {
+        session.resume();
+    }
+    let mut graphs = vec![];
+    while let Some(pause) = session.pause() {
+        let names: Vec<String> = session
+            .backtrace()
+            .iter()
+            .rev()
+            .map(|(function, _)| function.clone().unwrap_or("<main>".to_string()))
+            .collect();
+        let innermost = names.last().map_or("<main>", |name| name.as_str());
+        let title = format!("before line {} in {}", pause.span.line, innermost);
+        graphs.push(dot::graph(&pause.state, &title, &names));
+        session.resume();
+    }
+    return Ok((graphs, session));
+}
+
+// read entries from stdin until :quit or the end of input, a declaration may span lines
+fn run_repl(options: &Options) -> u8 {
+    let mut session = repl::Repl::new();
+    if options.explicit_borrows {
+        session.require_explicit_borrows();
+    }
+    session.set_color(diagnostic::use_color());
+    let interactive = std::io::stdin().is_terminal();
+    if interactive {
+        println!("{}", repl::HELP);
+    }
+
+    let mut entry = String::new();
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("{}", if entry.is_empty() { "muse> " } else { "....> " });
+            std::io::stdout().flush().unwrap();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                eprintln!("Could not read stdin: {}", e);
+                return EXIT_USAGE;
+            }
+            None => return 0,
+        };
+        entry.push_str(&line);
+        entry.push('\n');
+        if !repl::is_complete(&entry) {
+            continue;
+        }
+        if matches!(entry.trim(), ":quit" | ":q") {
+            return 0;
+        }
+        match session.eval(&entry) {
+            Ok(out) => print!("{}", out),
+            Err(e) => eprint!("{}", if e.ends_with('\n') { e } else { e + "\n" }),
+        }
+        entry.clear();
     }
+}
+
+fn run_debugger(options: &Options) -> u8 {
+    let file = &options.files[0];
+    let source = match read_source(file) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("Could not read {}: {}", file, e);
+            return EXIT_USAGE;
+        }
+    };
+    let mut gamma = TypeEnviroment::new();
+    if options.explicit_borrows {
+        gamma.require_explicit_borrows();
+    }
+    let mut session = match debugger::Debugger::new(&source, file, gamma) {
+        Ok(session) => session,
+        Err(errors) => {
+            report(&errors, &source, file);
+            return match errors[0].kind {
+                ErrorKind::Lex(_) | ErrorKind::Parse(_) => EXIT_PARSE,
+                _ => EXIT_TYPE,
+            };
+        }
+    };
+    session.set_color(diagnostic::use_color());
+    for breakpoint in &options.breakpoints {
+        session.add_breakpoint(breakpoint.clone());
+    }
+    let interactive = std::io::stdin().is_terminal();
+    if interactive {
+        println!("{}", debugger::HELP);
+    }
+    print!("{}", session.show());
+
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        if interactive {
+            print!("(debug) ");
+            std::io::stdout().flush().unwrap();
+        }
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            Some(Err(e)) => {
+                eprintln!("Could not read stdin: {}", e);
+                return EXIT_USAGE;
+            }
+            None => break,
+        };
+        if matches!(line.trim(), "quit" | "q") {
+            break;
+        }
+        if line.trim().is_empty() {
+            continue;
+        }
+        match session.command(&line) {
+            Ok(out) => print!("{}", out),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+    // the program failed if the session got as far as its error
+    if session.pause().is_none() && session.error().is_some() {
+        return EXIT_RUNTIME;
+    }
+    return 0;
+}
 
-    
+// apply the first suggestion for each error, as later ones are alternatives
+fn fix(errors: &[Error], source: &str, file: &str) {
+    let suggestions: Vec<_> = errors
+        .iter()
+        .filter_map(|e| e.suggestions.first())
+        .collect();
+    let edits: Vec<_> = suggestions.iter().flat_map(|s| s.edits.clone()).collect();
+    if edits.is_empty() {
+        return;
+    }
+    let fixed = diagnostic::apply_edits(source, &edits);
+    if file == "-" {
+        print!("{}", fixed);
+        return;
+    }
+    match std::fs::write(file, fixed) {
+        Ok(_) => eprintln!("Applied {} fixes to {}", suggestions.len(), file),
+        Err(e) => eprintln!("Could not write {}: {}", file, e),
+    }
+}
+
+fn timeline(source: &str, name: &str, options: &Options) -> u8 {
+    let mut gamma = TypeEnviroment::new();
+    if options.explicit_borrows {
+        gamma.require_explicit_borrows();
+    }
+    let timeline = match timeline::Timeline::new(source, gamma) {
+        Ok(timeline) => timeline,
+        Err(errors) => {
+            report(&errors, source, name);
+            return match errors[0].kind {
+                ErrorKind::Lex(_) | ErrorKind::Parse(_) => EXIT_PARSE,
+                _ => EXIT_TYPE,
+            };
+        }
+    };
+    print!("{}", timeline.text());
+    if let Some(path) = &options.html {
+        if let Err(e) = std::fs::write(path, timeline.html(name)) {
+            eprintln!("Could not write {}: {}", path, e);
+            return EXIT_USAGE;
+        }
+    }
+    return 0;
+}
+
+fn format(source: &str, name: &str, options: &Options) -> u8 {
+    let formatted = match lightweight_muse::format::format(source) {
+        Ok(formatted) => formatted,
+        Err(errors) => {
+            report(&errors, source, name);
+            return EXIT_PARSE;
+        }
+    };
+    if !options.check {
+        print!("{}", formatted);
+        return 0;
+    }
+    if formatted != source {
+        eprintln!("Would reformat {}", name);
+        return EXIT_UNFORMATTED;
+    }
+    return 0;
 }
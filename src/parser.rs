@@ -1,318 +1,558 @@
-use crate::ast::{Argument, LVal, Program, Term, Value};
-use crate::token::Token;
-use crate::typing::Type;
+use crate::ast::{Argument, BinOp, LVal, MethodSignature, Program, Term, Value};
+use crate::constants::ParseError;
+use crate::log::{debug, trace};
+use crate::span::Span;
+use crate::token::{Token, TokenKind};
+use crate::typing::{IntType, Type};
 
 pub struct Parser {
     tokens: Vec<Token>,
     current_position: usize,
+    self_type: Option<Type>, // type that `Self` stands for inside an impl block
+    errors: Vec<ParseError>, // errors recovered from so far
 }
 
 impl Parser {
-    fn check_consume(&mut self, token: Token) {
+    fn peek(&self, offset: usize) -> Option<&TokenKind> {
+        self.tokens
+            .get(self.current_position + offset)
+            .map(|t| &t.kind)
+    }
+
+    // location of the current token, or just past the last token once they run out
+    fn location(&self) -> Span {
         match self.tokens.get(self.current_position) {
-            Some(t) => {
-                if t == &token {
-                    self.current_position += 1;
-                } else {
-                    panic!("Expected {:?}, got {:?}", token, t);
+            Some(t) => t.span,
+            None => match self.tokens.last() {
+                Some(t) => {
+                    let column = t.span.column + (t.span.end - t.span.start);
+                    Span::new(t.span.end, t.span.end, t.span.line, column)
                 }
+                None => Span::new(0, 0, 1, 1),
+            },
+        }
+    }
+
+    // span covering every token consumed since `start`
+    fn span_from(&self, start: usize) -> Span {
+        let first = self.tokens[start].span;
+        let last = self.tokens[self.current_position.max(start + 1) - 1].span;
+        first.to(last)
+    }
+
+    fn error(&self, expected: &str) -> ParseError {
+        ParseError {
+            expected: expected.to_string(),
+            found: self.peek(0).cloned().unwrap_or(TokenKind::Eof),
+            span: self.location(),
+        }
+    }
+
+    // skip to the next statement boundary: a `let` or `fn`, or just past a `}`
+    // unless the enclosing block is the one that closes it
+    fn synchronize(&mut self, in_block: bool) {
+        while let Some(token) = self.peek(0) {
+            match token {
+                TokenKind::Let | TokenKind::Fn => return,
+                TokenKind::RCurl => {
+                    if !in_block {
+                        self.current_position += 1;
+                    }
+                    return;
+                }
+                _ => self.current_position += 1,
             }
-            None => panic!("Unexpected EOF"),
         }
     }
 
-    fn parse_let(&mut self) -> Term {
-        self.check_consume(Token::Let);
-        self.check_consume(Token::Mut);
-        let variable = self.parse_variable();
-        self.check_consume(Token::Assign);
-        let term = self.parse_term();
-        Term::Let {
-            mutable: true,
+    fn check_consume(&mut self, token: TokenKind) -> Result<(), ParseError> {
+        if self.peek(0) == Some(&token) {
+            self.current_position += 1;
+            return Ok(());
+        }
+        return Err(self.error(&token.to_string()));
+    }
+
+    fn parse_let(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        self.check_consume(TokenKind::Let)?;
+        let mutable = self.peek(0) == Some(&TokenKind::Mut);
+        if mutable {
+            self.current_position += 1;
+        }
+        let variable = self.parse_variable()?;
+        self.check_consume(TokenKind::Assign)?;
+        let term = self.parse_expression()?;
+        Ok(Term::Let {
+            mutable,
             variable,
             term: Box::new(term),
-        }
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_assignment(&mut self) -> Term {
-        let variable = self.parse_variable();
-        self.check_consume(Token::Assign);
-        let term = self.parse_term();
-        println!("Parsed assignment: {:?} = {:?}", variable, term);
-        Term::Assign {
+    fn parse_assignment(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let variable = self.parse_variable()?;
+        self.check_consume(TokenKind::Assign)?;
+        let term = self.parse_expression()?;
+        trace!(Parser, "Parsed assignment: {:?} = {:?}", variable, term);
+        Ok(Term::Assign {
             variable,
             term: Box::new(term),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_identifier(&mut self) -> Result<String, ParseError> {
+        match self.peek(0) {
+            Some(TokenKind::Identifier(s)) => {
+                let s = s.to_string();
+                self.current_position += 1;
+                Ok(s)
+            }
+            _ => Err(self.error("identifier")),
         }
     }
 
-    fn parse_function_declaration(&mut self) -> Term {
-        self.check_consume(Token::Fn);
-        let name: String = match self.tokens.get(self.current_position) {
-            Some(Token::Identifier(s)) => {
+    fn parse_signature(&mut self) -> Result<MethodSignature, ParseError> {
+        self.check_consume(TokenKind::Fn)?;
+        let name = self.parse_identifier()?;
+
+        self.check_consume(TokenKind::LParen)?;
+        let args = self.parse_args()?;
+        self.check_consume(TokenKind::RParen)?;
+
+        let ty = match self.peek(0) {
+            Some(TokenKind::Colon) => {
                 self.current_position += 1;
-                s.to_string()
+                Some(self.parse_type()?)
             }
-            _ => panic!("Expected identifier"),
+            _ => None,
         };
 
-        self.check_consume(Token::LParen);
-        let args = self.parse_args();
-        self.check_consume(Token::RParen);
+        Ok(MethodSignature { name, args, ty })
+    }
+
+    fn parse_trait(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        self.check_consume(TokenKind::Trait)?;
+        let name = self.parse_identifier()?;
+        self.check_consume(TokenKind::LCurl)?;
 
-        let ty = match self.tokens.get(self.current_position) {
-            Some(Token::Colon) => {
-                self.current_position += 1;
-                Some(self.parse_type())
+        let mut methods = Vec::new();
+        while self.peek(0) == Some(&TokenKind::Fn) {
+            methods.push(self.parse_signature()?);
+        }
+
+        self.check_consume(TokenKind::RCurl)?;
+        Ok(Term::TraitDeclaration {
+            name,
+            methods,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_impl(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        self.check_consume(TokenKind::Impl)?;
+        // `impl Trait for Type` or an inherent `impl Type`
+        let trait_name = match (self.peek(0), self.peek(1)) {
+            (Some(TokenKind::Identifier(_)), Some(TokenKind::For)) => {
+                let trait_name = self.parse_identifier()?;
+                self.check_consume(TokenKind::For)?;
+                Some(trait_name)
             }
             _ => None,
         };
+        let ty = self.parse_type()?;
+        self.check_consume(TokenKind::LCurl)?;
+
+        let outer_self_type = self.self_type.replace(ty.clone());
+        let mut methods = Vec::new();
+        while self.peek(0) == Some(&TokenKind::Fn) {
+            match self.parse_function_declaration() {
+                Ok(method) => methods.push(method),
+                Err(e) => {
+                    self.self_type = outer_self_type;
+                    return Err(e);
+                }
+            }
+        }
+        self.self_type = outer_self_type;
+
+        self.check_consume(TokenKind::RCurl)?;
+        Ok(Term::Impl {
+            trait_name,
+            ty,
+            methods,
+            span: self.span_from(start),
+        })
+    }
 
-        self.check_consume(Token::LCurl);
+    fn parse_function_declaration(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let MethodSignature { name, args, ty } = self.parse_signature()?;
 
+        self.check_consume(TokenKind::LCurl)?;
+
+        // errors in the body are recovered from here, so the rest of the body still parses
         let mut body = Vec::new();
         loop {
-            let term = self.parse_term();
-            body.push(term);
-            if self.tokens.get(self.current_position) == Some(&Token::RCurl) {
+            match self.parse_expression() {
+                Ok(term) => body.push(term),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(true);
+                }
+            }
+            if self.peek(0) == Some(&TokenKind::RCurl) {
                 break;
             }
-            if self.tokens.get(self.current_position) == None {
+            if self.peek(0).is_none() {
                 break;
             }
         }
 
-        self.check_consume(Token::RCurl);
+        self.check_consume(TokenKind::RCurl)?;
 
-        Term::FunctionDeclaration {
+        Ok(Term::FunctionDeclaration {
             name,
             args,
             body,
             ty,
-        }
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_type(&mut self) -> Type {
-        match self.tokens.get(self.current_position) {
-            Some(Token::Identifier(s)) => {
+    fn parse_type(&mut self) -> Result<Type, ParseError> {
+        match self.peek(0) {
+            Some(TokenKind::Identifier(s)) => {
+                let ty = match (s.as_str(), IntType::from_name(s)) {
+                    (_, Some(ty)) => Type::Numeric(ty),
+                    ("bool", None) => Type::Bool,
+                    ("Self", None) => self.self_type.clone().unwrap_or(Type::SelfAlias),
+                    _ => return Err(self.error("type")),
+                };
                 self.current_position += 1;
-                match s.as_str() {
-                    "int" => Type::Numeric,
-                    _ => panic!("Expected int type"),
-                }
+                Ok(ty)
             }
-            Some(Token::Box) => {
+            Some(TokenKind::Box) => {
                 self.current_position += 1;
-                Type::Box(Box::new(self.parse_type()))
+                Ok(Type::Box(Box::new(self.parse_type()?)))
             }
-            _ => panic!("Expected type"),
+            _ => Err(self.error("type")),
         }
     }
 
-    fn parse_args(&mut self) -> Vec<Argument> {
+    // `self` may leave out its annotation, in which case it has type `Self`
+    fn parse_argument_type(&mut self, name: &str) -> Result<Type, ParseError> {
+        if name == "self" && self.peek(0) != Some(&TokenKind::Colon) {
+            return Ok(self.self_type.clone().unwrap_or(Type::SelfAlias));
+        }
+        self.check_consume(TokenKind::Colon)?;
+        self.parse_type()
+    }
+
+    fn parse_args(&mut self) -> Result<Vec<Argument>, ParseError> {
         let mut args = Vec::new();
         loop {
-            match self.tokens.get(self.current_position) {
-                Some(Token::RParen) => {
+            let start = self.current_position;
+            match self.peek(0) {
+                Some(TokenKind::RParen) => {
                     break;
                 }
-                Some(Token::Comma) => {
+                Some(TokenKind::Comma) => {
                     self.current_position += 1;
                 }
-                Some(Token::Identifier(s)) => {
-                    self.current_position += 1;
-                    let name = s.to_string();
-                    self.check_consume(Token::Colon);
-                    let ty = self.parse_type();
+                Some(TokenKind::Identifier(_)) => {
+                    let name = self.parse_identifier()?;
+                    let ty = self.parse_argument_type(&name)?;
                     args.push(Argument {
                         name,
                         mutable: false,
                         reference: false,
                         ty,
+                        span: self.span_from(start),
                     });
                 }
-                Some(Token::Ref) => {
+                Some(TokenKind::Ref) => {
                     self.current_position += 1;
-                    let name = match self.tokens.get(self.current_position) {
-                        Some(Token::Identifier(s)) => {
-                            self.current_position += 1;
-                            s.to_string()
-                        }
-                        _ => panic!("Expected identifier"),
-                    };
-                    self.check_consume(Token::Colon);
-                    let ty = self.parse_type();
+                    let name = self.parse_identifier()?;
+                    let ty = self.parse_argument_type(&name)?;
                     args.push(Argument {
                         name,
                         mutable: false,
                         reference: true,
                         ty,
+                        span: self.span_from(start),
                     });
                 }
-                Some(Token::Mut) => {
+                Some(TokenKind::Mut) => {
                     self.current_position += 1;
-                    let reference = match self.tokens.get(self.current_position) {
-                        Some(Token::Ref) => {
+                    let reference = match self.peek(0) {
+                        Some(TokenKind::Ref) => {
                             self.current_position += 1;
                             true
                         }
                         _ => false,
                     };
-                    let name = match self.tokens.get(self.current_position) {
-                        Some(Token::Identifier(s)) => {
-                            self.current_position += 1;
-                            s.to_string()
-                        }
-                        _ => panic!("Expected identifier"),
-                    };
-                    self.check_consume(Token::Colon);
-                    let ty = self.parse_type();
+                    let name = self.parse_identifier()?;
+                    let ty = self.parse_argument_type(&name)?;
                     args.push(Argument {
                         name,
                         mutable: true,
                         reference,
                         ty,
+                        span: self.span_from(start),
                     });
                 }
-                _ => panic!("Expected identifier or comma"),
+                _ => return Err(self.error("argument or `)`")),
             }
         }
-        args
+        Ok(args)
     }
 
-    fn parse_function_call(&mut self) -> Term {
-        let name = match self.tokens.get(self.current_position) {
-            Some(Token::Identifier(s)) => {
-                self.current_position += 1;
-                s.to_string()
-            }
-            _ => panic!("Expected identifier"),
-        };
-        self.check_consume(Token::LParen);
+    fn parse_function_call(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let name = self.parse_identifier()?;
+        let params = self.parse_params()?;
+        Ok(Term::FunctionCall {
+            name,
+            params,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_method_call(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let receiver = self.parse_variable()?;
+        self.check_consume(TokenKind::Dot)?;
+        let method = self.parse_identifier()?;
+        let params = self.parse_params()?;
+        Ok(Term::MethodCall {
+            receiver,
+            method,
+            params,
+            function: None,
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_params(&mut self) -> Result<Vec<Term>, ParseError> {
+        self.check_consume(TokenKind::LParen)?;
 
         let mut params = Vec::new();
         loop {
-            match self.tokens.get(self.current_position) {
-                Some(Token::RParen) => {
+            match self.peek(0) {
+                Some(TokenKind::RParen) => {
                     break;
                 }
-                Some(Token::Comma) => {
+                Some(TokenKind::Comma) => {
                     self.current_position += 1;
                 }
+                None => return Err(self.error("`)`")),
                 _ => {
-                    let term = self.parse_term();
+                    let term = self.parse_expression()?;
                     params.push(term);
                 }
             }
         }
-        self.check_consume(Token::RParen);
-        Term::FunctionCall { name, params }
+        self.check_consume(TokenKind::RParen)?;
+        Ok(params)
     }
 
-    fn parse_term(&mut self) -> Term {
-        match self.tokens.get(self.current_position) {
-            Some(token) => {
-                match token {
-                    Token::Fn => self.parse_function_declaration(),
-                    Token::NumericLiteral(n) => {
-                        self.current_position += 1;
-                        Term::Value(Value::NumericLiteral(*n))
-                    }
-                    Token::Identifier(s) => {
-                        // check if assignment
-                        if self.tokens.get(self.current_position + 1) == Some(&Token::Assign) {
-                            self.parse_assignment()
-                        } else if self.tokens.get(self.current_position + 1) == Some(&Token::LParen)
-                        {
-                            self.parse_function_call()
-                        } else {
-                            self.current_position += 1;
-                            Term::Variable(LVal::Variable {
-                                name: s.to_string(),
-                                copyable: None,
-                            })
-                        }
-                    }
-                    Token::Box => {
-                        self.current_position += 1;
-                        Term::Box {
-                            term: Box::new(self.parse_term()),
-                        }
-                    }
-                    Token::Mut => {
-                        // should only be mut ref
-                        self.current_position += 1;
-                        self.check_consume(Token::Ref);
-                        // current token:
-                        println!("{:?}", self.tokens.get(self.current_position));
-                        Term::Ref {
-                            mutable: true,
-                            var: self.parse_variable(),
-                        }
-                    }
-                    Token::Ref => {
-                        self.current_position += 1;
-                        Term::Ref {
-                            mutable: false,
-                            var: self.parse_variable(),
-                        }
-                    }
-                    Token::Let => self.parse_let(),
+    fn parse_term(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let Some(token) = self.peek(0) else {
+            return Err(self.error("expression"));
+        };
+        match token {
+            TokenKind::Fn => self.parse_function_declaration(),
+            TokenKind::Trait => self.parse_trait(),
+            TokenKind::Impl => self.parse_impl(),
+            TokenKind::NumericLiteral(n, ty) => {
+                let value = Value::NumericLiteral(*n, *ty);
+                self.current_position += 1;
+                Ok(Term::Value(value, Some(self.span_from(start))))
+            }
+            TokenKind::BoolLiteral(b) => {
+                let value = Value::Bool(*b);
+                self.current_position += 1;
+                Ok(Term::Value(value, Some(self.span_from(start))))
+            }
+            TokenKind::Assert | TokenKind::Print => self.parse_builtin(),
+            TokenKind::Identifier(_) => {
+                // check if assignment
+                if self.peek(1) == Some(&TokenKind::Assign) {
+                    self.parse_assignment()
+                } else if self.peek(1) == Some(&TokenKind::LParen) {
+                    self.parse_function_call()
+                } else if self.peek(1) == Some(&TokenKind::Dot) {
+                    self.parse_method_call()
+                } else {
+                    Ok(Term::Variable(self.parse_variable()?))
+                }
+            }
+            TokenKind::Box => {
+                self.current_position += 1;
+                let term = Box::new(self.parse_term()?);
+                Ok(Term::Box {
+                    term,
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::Mut => {
+                // should only be mut ref
+                self.current_position += 1;
+                self.check_consume(TokenKind::Ref)?;
+                let var = self.parse_variable()?;
+                Ok(Term::Ref {
+                    mutable: true,
+                    var,
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::Ref => {
+                self.current_position += 1;
+                let var = self.parse_variable()?;
+                Ok(Term::Ref {
+                    mutable: false,
+                    var,
+                    span: self.span_from(start),
+                })
+            }
+            TokenKind::Let => self.parse_let(),
 
-                    Token::Deref => {
-                        if self.tokens.get(self.current_position + 2) == Some(&Token::Assign) {
-                            self.parse_assignment()
-                        } else {
-                            self.current_position += 1;
-                            Term::Variable(LVal::Deref {
-                                var: Box::new(self.parse_variable()),
-                            })
-                        }
-                    }
-                    _ => panic!("Invalid token: {:?}", token),
+            TokenKind::Deref => {
+                // skip over every deref to the variable, e.g. **x = 1
+                let mut var_position = self.current_position;
+                while self.tokens.get(var_position).map(|t| &t.kind) == Some(&TokenKind::Deref) {
+                    var_position += 1;
+                }
+                if self.tokens.get(var_position + 1).map(|t| &t.kind) == Some(&TokenKind::Assign) {
+                    self.parse_assignment()
+                } else {
+                    Ok(Term::Variable(self.parse_variable()?))
                 }
             }
-            None => panic!("Unexpected EOF"),
+            _ => Err(self.error("expression")),
         }
     }
 
-    fn parse_variable(&mut self) -> LVal {
-        match self.tokens.get(self.current_position) {
-            Some(token) => match token {
-                Token::Identifier(s) => {
-                    self.current_position += 1;
-                    LVal::Variable {
-                        name: s.to_string(),
-                        copyable: None,
-                    }
-                }
-                Token::Deref => {
-                    self.current_position += 1;
-                    let var = self.parse_variable();
-                    LVal::Deref { var: Box::new(var) }
-                }
-                _ => panic!("Invalid token: {:?}, expected variable", token),
-            },
-            None => panic!("Unexpected EOF"),
+    fn parse_builtin(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let builtin = self.tokens[self.current_position].kind.clone();
+        self.current_position += 1;
+        self.check_consume(TokenKind::LParen)?;
+        let term = Box::new(self.parse_expression()?);
+        self.check_consume(TokenKind::RParen)?;
+        let span = self.span_from(start);
+        match builtin {
+            TokenKind::Assert => Ok(Term::Assert { cond: term, span }),
+            _ => Ok(Term::Print { term, span }),
         }
     }
 
-    pub fn parse(&mut self) -> Program {
-        let mut terms = Vec::new();
+    fn parse_expression(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let lhs = self.parse_arithmetic()?;
+        let op = match self.peek(0) {
+            Some(TokenKind::Eq) => BinOp::Eq,
+            Some(TokenKind::Ne) => BinOp::Ne,
+            Some(TokenKind::Lt) => BinOp::Lt,
+            Some(TokenKind::Gt) => BinOp::Gt,
+            Some(TokenKind::Le) => BinOp::Le,
+            Some(TokenKind::Ge) => BinOp::Ge,
+            _ => return Ok(lhs),
+        };
+        self.current_position += 1;
+        let rhs = self.parse_arithmetic()?;
+        Ok(Term::BinaryOp {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+            span: self.span_from(start),
+        })
+    }
+
+    fn parse_arithmetic(&mut self) -> Result<Term, ParseError> {
+        let start = self.current_position;
+        let mut lhs = self.parse_term()?;
         loop {
-            let term = self.parse_term();
-            terms.push(term);
-            if self.tokens.get(self.current_position) == None {
-                break;
+            let op = match self.peek(0) {
+                Some(TokenKind::Plus) => BinOp::Add,
+                Some(TokenKind::Minus) => BinOp::Sub,
+                _ => break,
+            };
+            self.current_position += 1;
+            let rhs = self.parse_term()?;
+            lhs = Term::BinaryOp {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+                span: self.span_from(start),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn parse_variable(&mut self) -> Result<LVal, ParseError> {
+        let start = self.current_position;
+        match self.peek(0) {
+            Some(TokenKind::Identifier(s)) => {
+                let name = s.to_string();
+                self.current_position += 1;
+                Ok(LVal::Variable {
+                    name,
+                    copyable: None,
+                    span: self.span_from(start),
+                })
+            }
+            Some(TokenKind::Deref) => {
+                self.current_position += 1;
+                let var = self.parse_variable()?;
+                Ok(LVal::Deref {
+                    var: Box::new(var),
+                    span: self.span_from(start),
+                })
+            }
+            _ => Err(self.error("variable")),
+        }
+    }
+
+    // parse the whole program, reporting every syntax error rather than only the first
+    pub fn parse(&mut self) -> Result<Program, Vec<ParseError>> {
+        let (program, errors) = self.parse_recovering();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        Ok(program)
+    }
+
+    /// Parse as much as possible, returning the statements that parsed along with
+    /// the errors in the ones that did not.
+    pub fn parse_recovering(&mut self) -> (Program, Vec<ParseError>) {
+        let mut terms = Vec::new();
+        while self.peek(0).is_some() {
+            match self.parse_expression() {
+                Ok(term) => terms.push(term),
+                Err(e) => {
+                    debug!(Parser, "Recovering from {}", e);
+                    self.errors.push(e);
+                    self.synchronize(false);
+                }
             }
         }
-        Program { terms }
+        (Program { terms }, std::mem::take(&mut self.errors))
     }
 
     pub fn new(tokens: Vec<Token>) -> Parser {
         Parser {
             tokens,
             current_position: 0,
+            self_type: None,
+            errors: vec![],
         }
     }
 }
-
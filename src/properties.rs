@@ -2,55 +2,68 @@ use std::collections::HashSet;
 
 use crate::{
     ast::{LVal, Reference, Term, Value},
-    reduction::Evaluate,
+    constants::{Error, RuntimeError},
+    log::{debug, trace},
+    span::Span,
     state::State,
     typecheck::TypeCheck,
-    typing::{contains, Slot, Type, TypeEnviroment},
+    typing::{borrowed, IntType, Slot, Type, TypeEnviroment},
 };
 
+// `evaluated` is the state and term that evaluating `t1` in `s1` gave
 pub fn assert_preservation(
     s1: State,
     t1: Term,
     g1: TypeEnviroment,
+    evaluated: (State, Term),
     lifetime: usize,
-) -> Result<(), String> {
-    println!("Asserting progress");
+) -> Result<(), Error> {
+    debug!(Properties, "Asserting preservation");
 
     if !valid_state(s1.clone(), t1.clone())? {
-        return Err("Invalid state".to_string());
+        return Err(RuntimeError::InvariantViolated("Invalid state".to_string()).into());
     }
 
     if !well_formed(g1.clone())? {
-        return Err("Type enviroment is not well formed".to_string());
+        return Err(RuntimeError::InvariantViolated(
+            "Type enviroment is not well formed".to_string(),
+        )
+        .into());
     }
 
     if !safe_abstraction(s1.clone(), g1.clone())? {
-        return Err("Type enviroment is not a safe abstraction of current state".to_string());
+        return Err(RuntimeError::InvariantViolated(
+            "Type enviroment is not a safe abstraction of current state".to_string(),
+        )
+        .into());
     }
 
     let (g2, t) = t1.clone().type_check(g1, lifetime)?;
 
-    let (s2, t2) = t1.clone().evaluate(s1, lifetime)?;
+    let (s2, t2) = evaluated;
 
     if !safe_abstraction(s2.clone(), g2)? {
-        return Err(
+        return Err(RuntimeError::InvariantViolated(
             "Type enviroment is not a safe abstraction of current state after preservation"
                 .to_string(),
-        );
+        )
+        .into());
     }
 
     match t2 {
-        Term::Value(v) => {
+        Term::Value(v, _) => {
             if !valid_type(&s2, &v, t.clone())? {
-                println!("Invalid type");
-                println!("{:?} {:?}", v, t.clone());
-                return Err("Invalid type".to_string());
+                debug!(Properties, "Invalid type: {:?} {:?}", v, t);
+                return Err(RuntimeError::InvariantViolated("Invalid type".to_string()).into());
             } else {
                 return Ok(());
             }
         }
         _ => {
-            return Err("Term did not evaluate to a value".to_string());
+            return Err(RuntimeError::InvariantViolated(
+                "Term did not evaluate to a value".to_string(),
+            )
+            .into());
         }
     }
 }
@@ -60,44 +73,47 @@ pub fn assert_progess(
     t1: Term,
     g1: TypeEnviroment,
     lifetime: usize,
-) -> Result<(), String> {
-    println!("Asserting progress");
+) -> Result<(), Error> {
+    debug!(Properties, "Asserting progress");
 
     if !valid_state(s1.clone(), t1.clone())? {
-        return Err("Invalid state".to_string());
+        return Err(RuntimeError::InvariantViolated("Invalid state".to_string()).into());
     }
 
     if !well_formed(g1.clone())? {
-        return Err("Type enviroment is not well formed".to_string());
+        return Err(RuntimeError::InvariantViolated(
+            "Type enviroment is not well formed".to_string(),
+        )
+        .into());
     }
 
     if !safe_abstraction(s1.clone(), g1.clone())? {
-        return Err("Type enviroment is not a safe abstraction of current state".to_string());
+        return Err(RuntimeError::InvariantViolated(
+            "Type enviroment is not a safe abstraction of current state".to_string(),
+        )
+        .into());
     }
 
+    // whether a term that is not a value can step is left to its evaluation, which
+    // the interpreter does once, so a term that never finishes still gets to run
     t1.clone().type_check(g1, lifetime)?;
-
-    match t1 {
-        Term::Value(_) => {
-            return Ok(());
-        }
-        _ => {
-            t1.clone().evaluate(s1, lifetime)?;
-            return Ok(());
-        }
-    }
+    return Ok(());
 }
 
-pub fn safe_abstraction(s: State, g: TypeEnviroment) -> Result<bool, String> {
-    println!("P: {:#?}", s);
-    println!("G: {:#?}", g);
+pub fn safe_abstraction(s: State, g: TypeEnviroment) -> Result<bool, Error> {
+    trace!(Properties, "P: {:#?}", s);
+    trace!(Properties, "G: {:#?}", g);
 
     let xs: HashSet<String> = s.dom().into_iter().collect();
     let ys: HashSet<String> = g.dom().into_iter().collect();
 
     if xs != ys {
-        println!("{:?} {:?}", s.dom(), g.dom());
-        println!("Not the same domain");
+        debug!(
+            Properties,
+            "Not the same domain: {:?} {:?}",
+            s.dom(),
+            g.dom()
+        );
         return Ok(false);
     }
     for x in g.dom() {
@@ -107,9 +123,9 @@ pub fn safe_abstraction(s: State, g: TypeEnviroment) -> Result<bool, String> {
             &s.store.get(l.clone()).unwrap().value,
             g.get_partial(&x)?.value,
         )? {
-            println!("Invalid type");
-            println!(
-                "{:?} {:?}",
+            debug!(
+                Properties,
+                "Invalid type: {:?} {:?}",
                 s.store.get(l.clone()).unwrap().value,
                 g.get_partial(&x)?.value
             );
@@ -120,14 +136,14 @@ pub fn safe_abstraction(s: State, g: TypeEnviroment) -> Result<bool, String> {
     return Ok(true);
 }
 
-pub fn valid_store(s: State) -> Result<bool, String> {
+pub fn valid_store(s: State) -> Result<bool, Error> {
     let mut set = HashSet::new();
     for value in s.store.cells.values() {
         if set.contains(&value.value) {
             return Ok(false); // Duplicate value found
         }
         match value.value {
-            Value::NumericLiteral(_) => {}
+            Value::NumericLiteral(..) | Value::Bool(_) => {}
             _ => {
                 set.insert(value.value.clone());
             }
@@ -156,9 +172,9 @@ pub fn get_values(t: Term, mut set: HashSet<Value>) -> HashSet<Value> {
     }
 }
 
-pub fn valid_state(s: State, t: Term) -> Result<bool, String> {
+pub fn valid_state(s: State, t: Term) -> Result<bool, Error> {
     if !valid_store(s.clone())? {
-        return Err("Invalid store".to_string());
+        return Err(RuntimeError::InvariantViolated("Invalid store".to_string()).into());
     }
     let mut set = HashSet::new();
     set = get_values(t, set);
@@ -171,7 +187,7 @@ pub fn valid_state(s: State, t: Term) -> Result<bool, String> {
     return Ok(true);
 }
 
-pub fn well_formed(g: TypeEnviroment) -> Result<bool, String> {
+pub fn well_formed(g: TypeEnviroment) -> Result<bool, Error> {
     for x in g.dom() {
         for y in g.dom() {
             if x == y {
@@ -184,20 +200,9 @@ pub fn well_formed(g: TypeEnviroment) -> Result<bool, String> {
             let lv = LVal::Variable {
                 name: y.clone(),
                 copyable: None,
+                span: Span::default(),
             };
-            if contains(
-                t1.clone(),
-                Type::Reference {
-                    var: lv.clone(),
-                    mutable: true,
-                },
-            ) || contains(
-                t1.clone(),
-                Type::Reference {
-                    var: lv.clone(),
-                    mutable: false,
-                },
-            ) {
+            if borrowed(&t1).is_some_and(|(var, _)| var.same_place(&lv)) {
                 lv.clone().type_check(g.clone(), l1)?;
             }
         }
@@ -205,15 +210,18 @@ pub fn well_formed(g: TypeEnviroment) -> Result<bool, String> {
     return Ok(true);
 }
 
-pub fn valid_type(s: &State, v: &Value, t: Type) -> Result<bool, String> {
+pub fn valid_type(s: &State, v: &Value, t: Type) -> Result<bool, Error> {
     match (v, t) {
         (Value::Undefined, Type::Undefined(_)) => return Ok(true),
         (_, Type::Undefined(_t)) => return valid_type(s, v, *_t),
         (Value::Epsilon, Type::Epsilon) => return Ok(true),
-        (Value::NumericLiteral(_), Type::Numeric) => return Ok(true),
+        (Value::NumericLiteral(_, k1), Type::Numeric(k2)) => {
+            return Ok(k1.unwrap_or(IntType::I64) == k2)
+        }
+        (Value::Bool(_), Type::Bool) => return Ok(true),
         (Value::Reference(r @ Reference { owned: true, .. }), Type::Box(bt)) => {
             let vt = &s.store.get(r.clone()).unwrap().value;
-            return valid_type(s, &vt, *bt);
+            return valid_type(s, vt, *bt);
         }
         (Value::Reference(r @ Reference { owned: false, .. }), Type::Reference { var, .. }) => {
             let x: String = var.get_name();
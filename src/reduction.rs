@@ -1,30 +1,33 @@
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 use crate::{
-    ast::{Argument, LVal, Program, Term, Value},
+    ast::{Argument, BinOp, LVal, Program, Term, Value},
+    constants::{Error, RuntimeError},
+    log::{debug, trace},
     state::{add_function, bind, drop, drop_lifetime, insert, loc, read, write, StackFrame, State},
+    typing::{method_function_name, IntType, Type},
 };
 
 pub trait Evaluate {
-    fn evaluate(&mut self, s: State, lifetime: usize) -> Result<(State, Self), String>
+    fn evaluate(&mut self, s: State, lifetime: usize) -> Result<(State, Self), Error>
     where
         Self: Sized;
 }
 
 impl Evaluate for Value {
-    fn evaluate(&mut self, s: State, _lifetime: usize) -> Result<(State, Value), String> {
+    fn evaluate(&mut self, s: State, _lifetime: usize) -> Result<(State, Value), Error> {
         return Ok((s, self.clone()));
     }
 }
 
 impl Evaluate for LVal {
-    fn evaluate(&mut self, s: State, _lifetime: usize) -> Result<(State, LVal), String> {
+    fn evaluate(&mut self, s: State, _lifetime: usize) -> Result<(State, LVal), Error> {
         return Ok((s, self.clone()));
     }
 }
 
 impl Evaluate for Program {
-    fn evaluate(&mut self, s: State, lifetime: usize) -> Result<(State, Program), String> {
+    fn evaluate(&mut self, s: State, lifetime: usize) -> Result<(State, Program), Error> {
         let state = s;
         let (s, _) = match self.terms.remove(0).evaluate(state, lifetime) {
             Ok((s, t)) => (s, t),
@@ -36,13 +39,20 @@ impl Evaluate for Program {
 }
 
 impl Evaluate for Term {
-    fn evaluate(&mut self, s: State, lifetime: usize) -> Result<(State, Term), String> {
+    fn evaluate(&mut self, s: State, lifetime: usize) -> Result<(State, Term), Error> {
+        let span = self.span();
+        return self.reduce(s, lifetime).map_err(|e| e.or_at(span));
+    }
+}
+
+impl Term {
+    fn reduce(&mut self, s: State, lifetime: usize) -> Result<(State, Term), Error> {
         match self {
-            Term::FunctionCall { name, params } => {
-                println!("Reducing function call: {:?}()", name);
+            Term::FunctionCall { name, params, .. } => {
+                debug!(Eval, "Reducing function call: {:?}()", name);
                 let (args, body) = match s.top().functions.get(name) {
                     Some((args, body)) => (args.clone(), body.clone()),
-                    None => return Err(format!("Function: {:?} not found", name)),
+                    None => return Err(RuntimeError::FunctionNotDefined(name.clone()).into()),
                 };
 
                 let mut outer_state = s.clone();
@@ -62,11 +72,9 @@ impl Evaluate for Term {
                             },
                         ) => {
                             // create a reference
-                            let Ok(mut reference) = loc(&outer_state, &var) else {
-                                return Err(format!("Variable: {:?} not found", var));
-                            };
+                            let mut reference = loc(&outer_state, var)?;
                             reference.owned = false;
-                            (outer_state, Term::Value(Value::Reference(reference)))
+                            (outer_state, Term::Value(Value::Reference(reference), None))
                         }
                         (
                             Term::Variable(var),
@@ -77,20 +85,17 @@ impl Evaluate for Term {
                             },
                         ) => {
                             // read(S, w) = ⟨v⟩
-                            let Ok(mut reference) = loc(&outer_state, &var) else {
-                                return Err(format!("Variable: {:?} not found", var));
-                            };
+                            let mut reference = loc(&outer_state, var)?;
                             reference.owned = false;
-                            (outer_state, Term::Value(Value::Reference(reference)))
+                            (outer_state, Term::Value(Value::Reference(reference), None))
                         }
                         (_, _) => param.clone().evaluate(outer_state, lifetime)?,
                     };
                     let value = match t {
-                        Term::Value(v) => v,
+                        Term::Value(v, _) => v,
                         Term::Variable(var) => {
                             // read(S, w) = ⟨v⟩
-                            let value = read(&s, &var)?;
-                            value
+                            read(&s, &var)?
                         }
                         _ => panic!("expression {:?} does not return a value", param),
                     };
@@ -102,47 +107,95 @@ impl Evaluate for Term {
                 let mut new_state = outer_state.clone();
 
                 new_state.stack.push(StackFrame {
-                    locations: HashMap::new(),
+                    locations: BTreeMap::new(),
                     functions: s.top().functions.clone(),
                 });
 
                 for (arg, value) in args.iter().zip(values) {
+                    // a literal without a suffix has the type of the argument
+                    let value = match (value, &arg.ty) {
+                        (Value::NumericLiteral(n, None), Type::Numeric(ty)) => {
+                            Value::NumericLiteral(n, Some(*ty))
+                        }
+                        (value, _) => value,
+                    };
                     let (s4, r) = insert(new_state, lifetime + 1, &value);
                     new_state = bind(s4, &arg.name, r);
                 }
 
-                let mut t1: Term = Term::Value(Value::Epsilon);
+                let mut t1: Term = Term::Value(Value::Epsilon, None);
 
                 // evaluate the body of the function
-                println!("Evaluating body of function: {:?}()", name);
+                debug!(Eval, "Evaluating body of function: {:?}()", name);
                 for term in body {
+                    new_state.pause(&term, Some(name.as_str()))?;
                     let (s3, t2) = term.clone().evaluate(new_state, lifetime + 1)?;
                     new_state = s3;
                     t1 = t2;
                 }
 
-                println!("Finished evaluating body of function: {:?}()", name);
+                debug!(Eval, "Finished evaluating body of function: {:?}()", name);
 
                 let v = match t1 {
-                    Term::Value(v) => v,
+                    Term::Value(v, _) => v,
                     Term::Variable(var) => {
                         // get the value of the variable
-                        let value = read(&new_state, &var)?;
-                        value
+                        read(&new_state, &var)?
                     }
                     _ => Value::Epsilon,
                 };
 
                 let final_state = drop_lifetime(new_state, lifetime + 1);
 
-                return Ok((final_state, Term::Value(v)));
+                return Ok((final_state, Term::Value(v, None)));
             }
             Term::FunctionDeclaration {
                 name, args, body, ..
             } => {
-                println!("Reducing function declaration: {:?}()", name);
+                debug!(Eval, "Reducing function declaration: {:?}()", name);
                 let s2 = add_function(s, name.to_string(), args.clone(), body.clone());
-                return Ok((s2, Term::Value(Value::Epsilon)));
+                return Ok((s2, Term::Value(Value::Epsilon, None)));
+            }
+            Term::TraitDeclaration { name, .. } => {
+                debug!(Eval, "Reducing trait declaration: {:?}", name);
+                return Ok((s, Term::Value(Value::Epsilon, None)));
+            }
+            Term::Impl {
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => {
+                debug!(Eval, "Reducing impl block for {}", ty);
+                let mut s2 = s;
+                for method in methods.iter() {
+                    if let Term::FunctionDeclaration {
+                        name, args, body, ..
+                    } = method
+                    {
+                        let function = method_function_name(ty, trait_name.as_deref(), name);
+                        s2 = add_function(s2, function, args.clone(), body.clone());
+                    }
+                }
+                return Ok((s2, Term::Value(Value::Epsilon, None)));
+            }
+            Term::MethodCall {
+                receiver,
+                params,
+                function,
+                span,
+                ..
+            } => {
+                let Some(function) = function else {
+                    return Err(RuntimeError::UnresolvedMethod(receiver.to_string()).into());
+                };
+                // a method call is a call of the underlying function with the receiver as self
+                let mut call = Term::FunctionCall {
+                    name: function.clone(),
+                    params: [vec![Term::Variable(receiver.clone())], params.clone()].concat(),
+                    span: *span,
+                };
+                return call.evaluate(s, lifetime);
             }
             Term::Let { variable, term, .. } => {
                 let (s2, t) = match term.evaluate(s, lifetime) {
@@ -151,7 +204,7 @@ impl Evaluate for Term {
                 };
 
                 let value = match t {
-                    Term::Value(v) => v,
+                    Term::Value(v, _) => v,
                     _ => {
                         panic!("Invalid term, this should not happen")
                     }
@@ -160,10 +213,10 @@ impl Evaluate for Term {
                 let (s3, r) = insert(s2, lifetime, &value);
                 let s4 = bind(s3, &variable.get_name(), r);
 
-                return Ok((s4, Term::Value(Value::Epsilon)));
+                return Ok((s4, Term::Value(Value::Epsilon, None)));
             }
 
-            Term::Assign { variable, term } => {
+            Term::Assign { variable, term, .. } => {
                 let (s2, t) = match term.evaluate(s, lifetime) {
                     Ok((s2, t)) => (s2, t),
                     Err(e) => return Err(e),
@@ -171,27 +224,35 @@ impl Evaluate for Term {
 
                 // sanity check
                 let value = match t {
-                    Term::Value(v) => v,
+                    Term::Value(v, _) => v,
                     _ => panic!("Invalid term, this should not happen"),
                 };
 
                 // v′ = read(S, w)
-                let old_value = read(&s2, &variable)?;
+                let old_value = read(&s2, variable)?;
+
+                // a literal without a suffix has the type of the value it replaces
+                let value = match (value, &old_value) {
+                    (Value::NumericLiteral(n, None), Value::NumericLiteral(_, ty)) => {
+                        Value::NumericLiteral(n, *ty)
+                    }
+                    (value, _) => value,
+                };
 
                 // S′ = drop(S, v′)
                 let s3 = drop(s2, &old_value)?;
 
                 // S′′ = write(S′, w, v)
-                let s4 = write(s3, &variable, &value)?;
+                let s4 = write(s3, variable, &value)?;
 
-                return Ok((s4, Term::Value(Value::Epsilon)));
+                return Ok((s4, Term::Value(Value::Epsilon, None)));
             }
 
-            Term::Box { term } => {
-                println!("Reducing box");
+            Term::Box { term, .. } => {
+                debug!(Eval, "Reducing box");
                 // we need to evaluate the term to get the value before we can add to heap
                 let (s3, value) = match term.evaluate(s, lifetime) {
-                    Ok((s2, Term::Value(v))) => (s2, v),
+                    Ok((s2, Term::Value(v, _))) => (s2, v),
                     Err(e) => return Err(e),
                     _ => panic!("Invalid term, this should not happen"),
                 };
@@ -200,32 +261,99 @@ impl Evaluate for Term {
 
                 // S2 = S1 [ℓn ↦ → ⟨v⟩∗]
                 let (s4, r) = insert(s3, 0, &value);
-                return Ok((s4, Term::Value(Value::Reference(r))));
+                return Ok((s4, Term::Value(Value::Reference(r), None)));
             }
 
-            Term::Ref { mutable: _, var } => {
-                println!("Reducing ref of variable: {:?}", var.get_name());
+            Term::BinaryOp { op, lhs, rhs, .. } => {
+                debug!(Eval, "Reducing binary operation: {}", op);
+                let (s2, v1) = match lhs.evaluate(s, lifetime)? {
+                    (s2, Term::Value(v, _)) => (s2, v),
+                    _ => panic!("Invalid term, this should not happen"),
+                };
+                let (s3, v2) = match rhs.evaluate(s2, lifetime)? {
+                    (s3, Term::Value(v, _)) => (s3, v),
+                    _ => panic!("Invalid term, this should not happen"),
+                };
+                if let (Value::Bool(b1), Value::Bool(b2)) = (&v1, &v2) {
+                    let b = match op {
+                        BinOp::Eq => b1 == b2,
+                        BinOp::Ne => b1 != b2,
+                        _ => {
+                            return Err(
+                                RuntimeError::InvalidOperands(*op, v1.clone(), v2.clone()).into()
+                            )
+                        }
+                    };
+                    return Ok((s3, Term::Value(Value::Bool(b), None)));
+                }
+                let (Value::NumericLiteral(n1, ty1), Value::NumericLiteral(n2, ty2)) = (&v1, &v2)
+                else {
+                    return Err(RuntimeError::InvalidOperands(*op, v1.clone(), v2.clone()).into());
+                };
+                // a literal without a suffix has the type of the other operand
+                let ty = ty1.or(*ty2).unwrap_or(IntType::I64);
+                // operands are range checked, so i128 arithmetic cannot itself overflow
+                let n = match op {
+                    BinOp::Add => n1 + n2,
+                    BinOp::Sub => n1 - n2,
+                    BinOp::Eq => return Ok((s3, Term::Value(Value::Bool(n1 == n2), None))),
+                    BinOp::Ne => return Ok((s3, Term::Value(Value::Bool(n1 != n2), None))),
+                    BinOp::Lt => return Ok((s3, Term::Value(Value::Bool(n1 < n2), None))),
+                    BinOp::Gt => return Ok((s3, Term::Value(Value::Bool(n1 > n2), None))),
+                    BinOp::Le => return Ok((s3, Term::Value(Value::Bool(n1 <= n2), None))),
+                    BinOp::Ge => return Ok((s3, Term::Value(Value::Bool(n1 >= n2), None))),
+                };
+                if !ty.contains(n) {
+                    return Err(RuntimeError::IntegerOverflow(*n1, *op, *n2, ty).into());
+                }
+                return Ok((s3, Term::Value(Value::NumericLiteral(n, Some(ty)), None)));
+            }
+
+            Term::Assert { cond, .. } => {
+                debug!(Eval, "Reducing assertion: {}", cond);
+                let expr = cond.clone();
+                let (s2, v) = match cond.evaluate(s, lifetime)? {
+                    (s2, Term::Value(v, _)) => (s2, v),
+                    _ => panic!("Invalid term, this should not happen"),
+                };
+                match v {
+                    Value::Bool(true) => return Ok((s2, Term::Value(Value::Epsilon, None))),
+                    _ => return Err(RuntimeError::AssertionFailed(expr).into()),
+                }
+            }
+
+            Term::Print { term, .. } => {
+                debug!(Eval, "Reducing print");
+                let (mut s2, v) = match term.evaluate(s, lifetime)? {
+                    (s2, Term::Value(v, _)) => (s2, v),
+                    _ => panic!("Invalid term, this should not happen"),
+                };
+                let line = s2.render(&v)?;
+                s2.printed.push(line);
+                return Ok((s2, Term::Value(Value::Epsilon, None)));
+            }
+
+            Term::Ref { var, .. } => {
+                debug!(Eval, "Reducing ref of variable: {:?}", var.get_name());
                 // check that term is a variable
                 // read(S, w) = ⟨v⟩
-                let Ok(mut reference) = loc(&s, &var) else {
-                    return Err(format!("Variable: {:?} not found", var));
-                };
+                let mut reference = loc(&s, var)?;
                 reference.owned = false;
-                return Ok((s, Term::Value(Value::Reference(reference))));
+                return Ok((s, Term::Value(Value::Reference(reference), None)));
             }
 
             Term::Variable(var) => {
-                if var.is_copyable()? == true {
-                    println!("Reducing copy of variable: {:?}", var.get_name());
+                if var.is_copyable()? {
+                    debug!(Eval, "Reducing copy of variable: {:?}", var.get_name());
                     // read(S, w) = ⟨v⟩
                     let value = read(&s, var)?;
-                    return Ok((s, Term::Value(value)));
-                } else if var.is_copyable()? == false {
-                    println!("Reducing move of variable: {:?}", var.get_name());
+                    return Ok((s, Term::Value(value, None)));
+                } else if !var.is_copyable()? {
+                    debug!(Eval, "Reducing move of variable: {:?}", var.get_name());
                     let value = read(&s, var)?;
                     let s2 = write(s, var, &Value::Undefined)?;
 
-                    return Ok((s2, Term::Value(value)));
+                    return Ok((s2, Term::Value(value, None)));
                 } else {
                     panic!(
                         "Variable: {:?} has not been typechecked properly",
@@ -233,9 +361,9 @@ impl Evaluate for Term {
                     )
                 }
             }
-            Term::Value(val) => {
-                println!("Reducing value: {:?}", val);
-                return Ok((s, Term::Value(val.clone())));
+            Term::Value(val, span) => {
+                trace!(Eval, "Reducing value: {:?}", val);
+                return Ok((s, Term::Value(val.clone(), *span)));
             }
         }
     }
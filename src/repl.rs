@@ -0,0 +1,203 @@
+//! An interactive session that keeps its interpreter between entries.
+//!
+//! Each entry is type checked against everything entered before it. Only an entry
+//! that checks and runs is committed, one that fails leaves the session as it was.
+//! Entries are kept as one source, so an error can point back at an earlier entry.
+
+use crate::{
+    ast::Value,
+    diagnostic,
+    interpreter::Interpreter,
+    lexer::Lexer,
+    parser::Parser,
+    token::TokenKind,
+    typing::{Slot, Type},
+    Error,
+};
+
+pub const HELP: &str = "\
+Enter a statement or declaration to run it, or a command:
+        :type <name>     Show the type of a variable or function
+        :env             Show every binding and its type
+        :store           Show where each variable lives and the store
+        :borrows <name>  Show the live references to a variable
+        :reset           Forget everything entered so far
+        :help            Show this message
+        :quit            Leave";
+
+pub struct Repl {
+    interpreter: Interpreter,
+    source: String, // every committed entry, one after another
+    explicit_borrows: bool,
+    color: bool,
+}
+
+impl Default for Repl {
+    fn default() -> Repl {
+        return Repl::new();
+    }
+}
+
+impl Repl {
+    pub fn new() -> Repl {
+        return Repl {
+            interpreter: Interpreter::new(),
+            source: String::new(),
+            explicit_borrows: false,
+            color: false,
+        };
+    }
+
+    pub fn require_explicit_borrows(&mut self) {
+        self.explicit_borrows = true;
+        self.interpreter.require_explicit_borrows();
+    }
+
+    /// Render errors in color.
+    pub fn set_color(&mut self, color: bool) {
+        self.color = color;
+    }
+
+    pub fn interpreter(&self) -> &Interpreter {
+        return &self.interpreter;
+    }
+
+    /// Run one entry, returning what to show: printed lines and the value, or the
+    /// result of a command. Errors come back rendered against the entry.
+    pub fn eval(&mut self, input: &str) -> Result<String, String> {
+        let input = input.trim();
+        if let Some(command) = input.strip_prefix(':') {
+            return self.command(command);
+        }
+        if input.is_empty() {
+            return Ok(String::new());
+        }
+
+        let source = format!("{}{}\n", self.source, input);
+        let render = |errors: Vec<Error>| -> String {
+            return errors
+                .iter()
+                .map(|e| diagnostic::render(e, &source, "<repl>", self.color))
+                .collect();
+        };
+        let tokens = Lexer::starting_at(&source, self.source.len())
+            .tokenize()
+            .map_err(|e| render(vec![e.into()]))?;
+        let program = Parser::new(tokens)
+            .parse()
+            .map_err(|errors| render(errors.into_iter().map(Error::from).collect()))?;
+        let environment = self.interpreter.environment();
+        let checked = crate::check_with(&program, environment.clone()).map_err(|mut errors| {
+            // fixes can only change this entry
+            crate::verify_suggestions(&mut errors, &source, self.source.len(), environment);
+            return render(errors);
+        })?;
+
+        // run a copy, so a runtime error leaves nothing half done
+        let mut interpreter = self.interpreter.clone();
+        let printed = interpreter.output().len();
+        let value = interpreter
+            .run(checked.program)
+            .map_err(|e| render(vec![e]))?;
+
+        let mut out: Vec<String> = interpreter.output()[printed..].to_vec();
+        if value != Value::Epsilon {
+            out.push(
+                interpreter
+                    .state()
+                    .render(&value)
+                    .unwrap_or(value.to_string()),
+            );
+        }
+        self.interpreter = interpreter;
+        self.source = source;
+        return Ok(lines(out));
+    }
+
+    fn command(&mut self, command: &str) -> Result<String, String> {
+        let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+        let arg = arg.trim();
+        let environment = self.interpreter.environment();
+        match (name, arg) {
+            ("type" | "t", name) if !name.is_empty() => {
+                match environment.get_partial(&name.to_string()) {
+                    Ok(Slot { value, .. }) => {
+                        return Ok(format!("{}: {}\n", name, value.describe()))
+                    }
+                    Err(e) => return Err(e.description()),
+                }
+            }
+            ("env", "") => {
+                let bindings = environment
+                    .bindings()
+                    .filter(|(name, _)| is_identifier(name))
+                    .map(|(name, slot)| format!("{}: {}", name, slot.value.describe()))
+                    .collect();
+                return Ok(lines(bindings));
+            }
+            ("store", "") => {
+                let state = self.interpreter.state();
+                let mut out = vec![];
+                for frame in state.frames() {
+                    for (name, reference) in frame.locations() {
+                        out.push(format!("{} -> {}", name, reference.location));
+                    }
+                }
+                for (location, slot) in state.store().cells() {
+                    out.push(format!("{} = {}", location, slot.value));
+                }
+                return Ok(lines(out));
+            }
+            ("borrows", name) if !name.is_empty() => {
+                if environment.get_partial(&name.to_string()).is_err() {
+                    return Err(format!("{} is not defined", name));
+                }
+                let borrows = environment
+                    .bindings()
+                    .filter(|(_, slot)| borrowed(&slot.value).as_deref() == Some(name))
+                    .map(|(borrower, slot)| format!("{}: {}", borrower, slot.value.describe()))
+                    .collect();
+                return Ok(lines(borrows));
+            }
+            ("reset", "") => {
+                self.interpreter = Interpreter::new();
+                self.source.clear();
+                if self.explicit_borrows {
+                    self.interpreter.require_explicit_borrows();
+                }
+                return Ok(String::new());
+            }
+            ("help" | "h", "") => return Ok(format!("{}\n", HELP)),
+            _ => return Err(format!("Unknown command :{}, try :help", command)),
+        }
+    }
+}
+
+/// Whether `input` is a whole entry, or a declaration still waiting for its `}`.
+pub fn is_complete(input: &str) -> bool {
+    let Ok(tokens) = Lexer::new(input).tokenize() else {
+        // let the parser report it
+        return true;
+    };
+    let open = tokens.iter().filter(|t| t.kind == TokenKind::LCurl).count();
+    let close = tokens.iter().filter(|t| t.kind == TokenKind::RCurl).count();
+    return open <= close;
+}
+
+fn lines(lines: Vec<String>) -> String {
+    return lines.iter().map(|line| format!("{}\n", line)).collect();
+}
+
+// names the checker binds for itself, like function arguments, are not identifiers
+fn is_identifier(name: &str) -> bool {
+    return name.chars().all(|c| c.is_alphanumeric() || c == '_');
+}
+
+// the variable a binding of type `ty` borrows, through any boxes
+fn borrowed(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Box(t) => borrowed(t),
+        Type::Reference { var, .. } => Some(var.get_name()),
+        _ => None,
+    }
+}
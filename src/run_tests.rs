@@ -1,32 +1,847 @@
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
 
     use crate::{
-        ast::LVal, constants::TypeError, interpreter::Interpreter, lexer::Lexer, parser::Parser,
-        typing::Type,
+        ast::{BinOp, LVal, Program, Term, Value},
+        constants::{Edit, Error, ErrorKind, LexError, RuntimeError, TypeError},
+        debugger::{Breakpoint, Debugger},
+        diagnostic, dot, explain, format,
+        interpreter::Interpreter,
+        json::Json,
+        lexer::Lexer,
+        lint::{self, Lint},
+        log,
+        parser::Parser,
+        repl::{self, Repl},
+        span::Span,
+        timeline::{EventKind, Timeline},
+        token::TokenKind,
+        typing::{IntType, Type, TypeEnviroment},
     };
 
-    fn run(file: &str) -> Result<HashMap<String, String>, String> {
+    fn parse(file: &str) -> Result<Program, ErrorKind> {
         let input = std::fs::read_to_string(file).expect("Error reading file");
         let mut lexer = Lexer::new(input.as_str());
-        let tokens = lexer.tokenize();
+        let tokens = lexer.tokenize()?;
         let mut parser = Parser::new(tokens);
-        let ast = parser.parse();
+        return parser.parse().map_err(|e| e[0].clone().into());
+    }
+
+    fn run(file: &str) -> Result<HashMap<String, String>, ErrorKind> {
+        let ast = parse(file)?;
         let mut interpreter = Interpreter::new();
         let result = interpreter.run(ast);
         match result {
-            Ok(_) => Ok(interpreter.program_state.output()),
-            Err(e) => return Err(e),
+            Ok(_) => Ok(interpreter.state().output()),
+            Err(e) => return Err(e.kind),
+        }
+    }
+
+    fn printed(file: &str) -> Result<Vec<String>, ErrorKind> {
+        let ast = parse(file)?;
+        let mut interpreter = Interpreter::new();
+        interpreter.run(ast).map_err(|e| e.kind)?;
+        Ok(interpreter.output().to_vec())
+    }
+
+    #[test]
+    fn print_output() {
+        assert_eq!(
+            Ok(["2", "5", "5", "5", "5"].map(String::from).to_vec()),
+            printed("tests/good/print_assert.mu")
+        );
+    }
+
+    #[test]
+    fn explicit_borrow_required() {
+        let ast = parse("tests/bad/explicit_borrow_required.mu").unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.require_explicit_borrows();
+        assert_eq!(
+            Err(TypeError::ExplicitBorrowRequired("a".to_string(), true).into()),
+            interpreter.run(ast).map_err(|e| e.kind)
+        );
+    }
+
+    #[test]
+    fn error_kinds() {
+        let ast = parse("tests/bad/int_overflow.mu").unwrap();
+        let error = Interpreter::new().run(ast).unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ErrorKind::Runtime(RuntimeError::IntegerOverflow(
+                255,
+                BinOp::Add,
+                1,
+                IntType::U8
+            ))
+        ));
+        // the wrapped error is exposed as the source for embedders
+        let source = std::error::Error::source(&error).unwrap();
+        assert_eq!(error.to_string(), source.to_string());
+    }
+
+    #[test]
+    fn all_type_errors() {
+        let mut ast = parse("tests/bad/multiple_errors.mu").unwrap();
+        let errors = ast.check_all(TypeEnviroment::new(), 0).unwrap_err();
+        let found: Vec<(ErrorKind, usize)> = errors
+            .into_iter()
+            .map(|e| (e.kind, e.span.unwrap().line))
+            .collect();
+        // uses of z, b and f are not reported again, their definitions failed
+        assert_eq!(
+            vec![
+                (
+                    TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric(IntType::I64)))).into(),
+                    3
+                ),
+                (TypeError::AssignBorrowed("a".to_string()).into(), 8),
+                (
+                    TypeError::IncompatibleTypes(Type::Bool, Type::Numeric(IntType::I64)).into(),
+                    10
+                ),
+                (
+                    TypeError::FunctionUnexpectedReturn(Type::Numeric(IntType::I64), Type::Bool)
+                        .into(),
+                    13
+                ),
+            ],
+            found
+        );
+    }
+
+    // the type errors of a program given as source, or an empty list if it checks
+    fn type_errors(source: &str) -> Vec<TypeError> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let mut ast = Parser::new(tokens).parse().unwrap();
+        let Err(errors) = ast.check_all(TypeEnviroment::new(), 0) else {
+            return vec![];
+        };
+        return errors
+            .into_iter()
+            .map(|e| match e.kind {
+                ErrorKind::Type(e) => e,
+                kind => panic!("expected a type error, got {}", kind),
+            })
+            .collect();
+    }
+
+    #[test]
+    fn error_codes_in_sync() {
+        // every TypeError variant in constants.rs has exactly one explanation
+        let source = include_str!("constants.rs");
+        let start = source.find("pub enum TypeError {").unwrap();
+        let end = start + source[start..].find("\n}").unwrap();
+        let variants: Vec<&str> = source[start..end]
+            .lines()
+            .skip(1)
+            .filter_map(|line| line.trim().split(['(', ',']).next())
+            .filter(|name| !name.is_empty())
+            .collect();
+        let explained: Vec<&str> = explain::EXPLANATIONS.iter().map(|(_, v, _)| *v).collect();
+        let mut sorted = variants.clone();
+        sorted.sort();
+        let mut explained_sorted = explained.clone();
+        explained_sorted.sort();
+        assert_eq!(sorted, explained_sorted);
+
+        for (code, variant, text) in explain::EXPLANATIONS {
+            assert!(text.starts_with(&format!("# {}:", code)));
+            assert_eq!(
+                1,
+                explain::EXPLANATIONS
+                    .iter()
+                    .filter(|(c, ..)| c == code)
+                    .count()
+            );
+            // the erroneous example fails with this code and the fixed one checks
+            let examples: Vec<&str> = text
+                .split("```mu\n")
+                .skip(1)
+                .map(|block| block.split("```").next().unwrap())
+                .collect();
+            let [bad, good] = examples.as_slice() else {
+                panic!("{} should have an erroneous and a fixed example", code);
+            };
+            let errors = type_errors(bad);
+            assert_eq!(1, errors.len(), "{}: {:?}", code, errors);
+            assert_eq!(*code, errors[0].code());
+            assert!(format!("{:?}", errors[0]).starts_with(variant));
+            assert_eq!(Vec::<TypeError>::new(), type_errors(good), "{}", code);
+        }
+    }
+
+    #[test]
+    fn suggested_fixes() {
+        for file in [
+            "tests/bad/move_borrowed.mu",
+            "tests/bad/worked_example.mu",
+            "tests/bad/move_into_call.mu",
+            "tests/bad/needs_box.mu",
+        ] {
+            let input = std::fs::read_to_string(file).expect("Error reading file");
+            let mut ast = parse(file).unwrap();
+            let errors = ast.check_all(TypeEnviroment::new(), 0).unwrap_err();
+            let edits: Vec<Edit> = errors
+                .iter()
+                .flat_map(|e| e.suggestions.first().unwrap().edits.clone())
+                .collect();
+            // applying the suggestions leaves a program that checks
+            let fixed = diagnostic::apply_edits(&input, &edits);
+            assert_eq!(Vec::<TypeError>::new(), type_errors(&fixed), "{}", file);
         }
     }
 
+    fn verified_fixes(source: &str, explicit_borrows: bool) -> Vec<String> {
+        let mut gamma = TypeEnviroment::new();
+        if explicit_borrows {
+            gamma.require_explicit_borrows();
+        }
+        let program = crate::parse(source).unwrap();
+        let mut errors = crate::check_with(&program, gamma.clone()).unwrap_err();
+        crate::verify_suggestions(&mut errors, source, 0, &gamma);
+        return errors
+            .iter()
+            .flat_map(|e| &e.suggestions)
+            .map(|s| diagnostic::apply_edits(source, &s.edits))
+            .collect();
+    }
+
+    #[test]
+    fn verified_suggestions() {
+        // borrowing at both moves checks, so it is offered
+        assert_eq!(
+            vec!["let x = box 1\nlet y = ref x\nlet z = ref x\n".to_string()],
+            verified_fixes("let x = box 1\nlet y = x\nlet z = x\n", false)
+        );
+        // none of these rewrites would check, so none are offered
+        let call = "fn f(mut ref a: box int, b: box int) {\n    let c = b\n}\nlet mut x = box 1\n";
+        assert!(verified_fixes(&format!("{}f(x, x)\n", call), false).is_empty());
+        assert!(verified_fixes(&format!("{}f(mut ref x, x)\n", call), true).is_empty());
+        let by_value = "fn f(a: box int) {\n    let b = a\n}\nlet x = box 1\nf(x)\nlet z = x\n";
+        assert!(verified_fixes(by_value, false).is_empty());
+    }
+
+    #[test]
+    fn lint_warnings() {
+        let mut ast = parse("tests/good/lints.mu").unwrap();
+        ast.check_all(TypeEnviroment::new(), 0).unwrap();
+        let warnings: Vec<(Lint, usize)> = lint::lint(&ast)
+            .iter()
+            .map(|w| (w.lint, w.span.line))
+            .collect();
+        assert_eq!(
+            vec![
+                (Lint::UnusedFunctions, 5),
+                (Lint::UnusedMut, 6),
+                (Lint::UnusedVariables, 13),
+                (Lint::UnusedMove, 17),
+            ],
+            warnings
+        );
+        // programs that use everything they declare are left alone
+        let mut ast = parse("tests/good/fn_inplace.mu").unwrap();
+        ast.check_all(TypeEnviroment::new(), 0).unwrap();
+        assert_eq!(Vec::<lint::Warning>::new(), lint::lint(&ast));
+    }
+
+    #[test]
+    fn reproducible_state() {
+        // each interpreter numbers its own locations, so two runs end in the same state
+        let states: Vec<String> = (0..2)
+            .map(|_| {
+                let mut interpreter = Interpreter::new();
+                interpreter
+                    .run(parse("tests/good/multiple_move.mu").unwrap())
+                    .unwrap();
+                format!("{:?}", interpreter.state())
+            })
+            .collect();
+        assert_eq!(states[0], states[1]);
+        assert!(states[0].contains("\"l-1\""));
+    }
+
+    #[test]
+    fn repl_session() {
+        let mut repl = Repl::new();
+        assert_eq!(Ok(String::new()), repl.eval("let x = box 1"));
+        assert_eq!(
+            Ok("2\n".to_string()),
+            repl.eval("let r = ref x\nprint(**r + 1)")
+        );
+        assert_eq!(Ok("r: ref x\n".to_string()), repl.eval(":borrows x"));
+        assert_eq!(Ok("x: box int\n".to_string()), repl.eval(":type x"));
+
+        // a failed entry leaves the session as it was
+        let error = repl.eval("let y = x").unwrap_err();
+        assert!(
+            error.contains("[M0001]") && error.contains("<repl>:4:9"),
+            "{}",
+            error
+        );
+        assert!(repl.eval("print(oops)").is_err());
+        assert_eq!(Ok("1\n".to_string()), repl.eval("print(*x)"));
+        assert!(repl.eval("let big = 127i8 + 1i8").is_err());
+        assert!(repl.eval(":type big").is_err());
+
+        // declarations span lines, and their value is shown
+        assert!(!repl::is_complete("fn add(a: int, b: int) : int {\n"));
+        let add = "fn add(a: int, b: int) : int {\n a + b\n}\nadd(2, 3)";
+        assert_eq!(Ok("5\n".to_string()), repl.eval(add));
+
+        assert_eq!(Ok(String::new()), repl.eval(":reset"));
+        assert_eq!(Ok(String::new()), repl.eval(":env"));
+        assert_eq!(Ok(String::new()), repl.eval("let x = 2"));
+    }
+
+    #[test]
+    fn repl_survives_bad_places() {
+        // entries naming a place that is not defined, moved or only borrowed fail
+        // on their own, and the session keeps going
+        let mut repl = Repl::new();
+        assert!(repl.eval("let y = ref z").is_err());
+        assert_eq!(Ok(String::new()), repl.eval("let mut x = box 1"));
+        assert_eq!(Ok(String::new()), repl.eval("let y = mut ref x"));
+        let error = repl.eval("let z = *y").unwrap_err();
+        assert!(error.contains("[M0035]"), "{}", error);
+        assert_eq!(
+            Ok("x: box int\ny: mut ref x\n".to_string()),
+            repl.eval(":env")
+        );
+    }
+
+    #[test]
+    fn format_round_trip() {
+        let mut files: Vec<_> = ["tests/good", "tests/bad"]
+            .iter()
+            .flat_map(|dir| std::fs::read_dir(dir).unwrap())
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        files.sort();
+        for file in files {
+            let source = std::fs::read_to_string(&file).unwrap();
+            let Ok(formatted) = format::format(&source) else {
+                assert!(crate::parse(&source).is_err(), "{}", file.display());
+                continue;
+            };
+            // the fixtures are kept formatted, so formatting changes nothing
+            assert_eq!(source, formatted, "{}", file.display());
+        }
+    }
+
+    #[test]
+    fn format_comments() {
+        let source = "// leading\nlet   x=1 // trailing\n\n\n\nfn f(a : int):int{ /* header */\n  // inside\n  a+1\n  // last\n}\ntrait T{\n  fn t(self) // sig\n}\n// end";
+        let expected = "\
+// leading
+let x = 1 // trailing
+
+fn f(a: int) : int { /* header */
+    // inside
+    a + 1
+    // last
+}
+trait T {
+    fn t(self) // sig
+}
+// end
+";
+        assert_eq!(Ok(expected.to_string()), format::format(source));
+        assert_eq!(Ok(expected.to_string()), format::format(expected));
+        // block comments inside a statement stay next to the token before them
+        let inline = "let y = /*a*/ add(x /* b */,2) /* c */\n";
+        assert_eq!(
+            Ok("let y = /*a*/ add(x /* b */, 2) /* c */\n".to_string()),
+            format::format(inline)
+        );
+    }
+
+    #[test]
+    fn debugger_steps() {
+        let source = "\
+fn inner(a: int) : int {
+    a + 1
+}
+fn outer(ref b: int) : int {
+    let c = inner(*b)
+    c
+}
+let x = 1
+let y = outer(x)
+let z = box y
+print(*z)
+";
+        let mut debugger = Debugger::new(source, "steps.mu", TypeEnviroment::new()).unwrap();
+        let at = |d: &Debugger| d.pause().map(|p| (p.span.line, p.function.clone()));
+        let main = |line| Some((line, None));
+        let function = |line, name: &str| Some((line, Some(name.to_string())));
+
+        // stepping over calls stays at the top level
+        assert_eq!(main(1), at(&debugger));
+        debugger.step_over();
+        debugger.step_over();
+        assert_eq!(main(8), at(&debugger));
+        debugger.step_over();
+        assert_eq!(main(9), at(&debugger));
+
+        // stepping in goes through each call, finishing leaves it
+        debugger.step_in();
+        assert_eq!(function(5, "outer"), at(&debugger));
+        debugger.step_in();
+        assert_eq!(function(2, "inner"), at(&debugger));
+        let frames: Vec<_> = debugger
+            .backtrace()
+            .iter()
+            .map(|(f, s)| (s.line, f.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                function(2, "inner").unwrap(),
+                function(5, "outer").unwrap(),
+                main(9).unwrap()
+            ],
+            frames
+        );
+        let shown = debugger.show();
+        assert!(
+            shown.contains("= ref l-1 (borrowed), lifetime 1"),
+            "{}",
+            shown
+        );
+        debugger.step_out();
+        assert_eq!(function(6, "outer"), at(&debugger));
+        debugger.step_out();
+        assert_eq!(main(10), at(&debugger));
+
+        // the pause shows the stack, who owns what, and the types
+        let shown = debugger.show();
+        assert!(
+            shown.contains("<main> at line 10: x -> l-1, y -> l-5"),
+            "{}",
+            shown
+        );
+        assert!(shown.contains("    y: int\n"), "{}", shown);
+        debugger.step_in();
+        assert!(
+            debugger.show().contains("(owned), lifetime 0"),
+            "{}",
+            debugger.show()
+        );
+        debugger.step_in();
+        assert_eq!(None, at(&debugger));
+        assert_eq!("Program finished\n", debugger.show());
+
+        // breakpoints on functions stop on entry, on lines wherever they are
+        let mut debugger = Debugger::new(source, "steps.mu", TypeEnviroment::new()).unwrap();
+        debugger.add_breakpoint(Breakpoint::parse("inner"));
+        debugger.add_breakpoint(Breakpoint::parse("10"));
+        debugger.resume();
+        assert_eq!(function(2, "inner"), at(&debugger));
+        debugger.resume();
+        assert_eq!(main(10), at(&debugger));
+    }
+
+    #[test]
+    fn debugger_never_finishes() {
+        // the program runs as it is stepped, so it stops at breakpoints however deep
+        let source = "\
+fn forever(n: int) : int {
+    print(n)
+    forever(n + 1)
+}
+forever(0)
+";
+        let mut debugger = Debugger::new(source, "forever.mu", TypeEnviroment::new()).unwrap();
+        debugger.add_breakpoint(Breakpoint::parse("2"));
+        for _ in 0..3 {
+            debugger.resume();
+        }
+        let pause = debugger.pause().unwrap();
+        assert_eq!((2, 3), (pause.span.line, pause.depth));
+        assert_eq!(&["0", "1"], debugger.output());
+        assert_eq!(4, debugger.backtrace().len());
+    }
+
+    #[test]
+    fn dot_graph() {
+        let source = "\
+let x = box 1
+let r = ref x
+let y = box 2
+let z = y
+";
+        let mut interpreter = Interpreter::new();
+        interpreter.record_snapshots();
+        interpreter.run(crate::parse(source).unwrap()).unwrap();
+        let lines: Vec<usize> = interpreter
+            .snapshots()
+            .iter()
+            .map(|(span, _)| span.line)
+            .collect();
+        assert_eq!(vec![1, 2, 3, 4], lines);
+
+        let graph = dot::graph(&interpreter.snapshots()[3].1, "after line 4", &[]);
+        assert!(
+            graph.starts_with("digraph \"after line 4\" {\n"),
+            "{}",
+            graph
+        );
+        assert!(graph.contains("label=\"<main>\""), "{}", graph);
+        // variables point at their cells, owning references are solid and borrows dashed
+        assert!(
+            graph.contains("\"frame 0 x\" -> \"l-2\" [style=solid]"),
+            "{}",
+            graph
+        );
+        assert!(
+            graph.contains("\"l-2\" -> \"l-1\" [style=solid]"),
+            "{}",
+            graph
+        );
+        assert!(
+            graph.contains("\"l-3\" -> \"l-2\" [style=dashed]"),
+            "{}",
+            graph
+        );
+        assert!(
+            graph.contains("\"l-1\" [label=\"l-1\\n1\\nlifetime 0\"]"),
+            "{}",
+            graph
+        );
+        // y was moved into z
+        assert!(
+            graph.contains("\"l-5\" [label=\"l-5\\nmoved out\\nlifetime 0\", style=filled"),
+            "{}",
+            graph
+        );
+        assert!(graph.ends_with("}\n"));
+    }
+
+    #[test]
+    fn timeline() {
+        let source = "\
+fn f(a: box int) : int {
+    let b = *a
+    b
+}
+let x = box 1
+let y = box 2
+let mut z = ref x
+print(**z)
+z = ref y
+let n = f(x)
+";
+        let timeline = Timeline::new(source, TypeEnviroment::new()).unwrap();
+        let events: Vec<(usize, EventKind)> = timeline
+            .events()
+            .iter()
+            .map(|event| (event.span.line, event.kind.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (2, EventKind::Copy("*a".to_string())),
+                (3, EventKind::Copy("b".to_string())),
+                (
+                    4,
+                    EventKind::ScopeDrop {
+                        function: "f".to_string(),
+                        variables: vec!["a".to_string(), "b".to_string()],
+                    }
+                ),
+                (
+                    7,
+                    EventKind::Borrow {
+                        variable: "x".to_string(),
+                        mutable: false,
+                        reborrow: false,
+                    }
+                ),
+                (8, EventKind::Copy("**z".to_string())),
+                (
+                    9,
+                    EventKind::Borrow {
+                        variable: "y".to_string(),
+                        mutable: false,
+                        reborrow: false,
+                    }
+                ),
+                (10, EventKind::Move("x".to_string())),
+            ],
+            events
+        );
+
+        // reassigning z ends the first borrow, the second lasts to the end
+        let borrows: Vec<(usize, Option<usize>, Vec<String>)> = timeline
+            .borrows()
+            .iter()
+            .map(|borrow| (borrow.start, borrow.end, borrow.holders.clone()))
+            .collect();
+        assert_eq!(
+            vec![
+                (7, Some(9), vec!["z".to_string()]),
+                (9, None, vec!["z".to_string()])
+            ],
+            borrows
+        );
+        let text = timeline.text();
+        assert!(text.contains(" 9 | ' + z = ref y\n"), "{}", text);
+        assert!(text.contains("^ end of f drops a, b\n"), "{}", text);
+        let html = timeline.html("program.mu");
+        assert!(html.starts_with("<!DOCTYPE html>"), "{}", html);
+        assert!(html.contains("<title>program.mu</title>"), "{}", html);
+
+        let errors = Timeline::new("let x = 1\nx = 2\n", TypeEnviroment::new()).unwrap_err();
+        assert_eq!(1, errors.len());
+    }
+
+    #[test]
+    fn timeline_references() {
+        // a ref argument, or a box holding a borrow, drops nothing at the end of scope
+        let source = "\
+fn g(ref r: int, c: box int) : int {
+    let d = box ref c
+    let e = *r
+    e
+}
+let x = 1
+let y = g(x, box 2)
+";
+        let timeline = Timeline::new(source, TypeEnviroment::new()).unwrap();
+        let dropped: Vec<&EventKind> = timeline
+            .events()
+            .iter()
+            .map(|event| &event.kind)
+            .filter(|kind| matches!(kind, EventKind::ScopeDrop { .. }))
+            .collect();
+        assert_eq!(
+            vec![&EventKind::ScopeDrop {
+                function: "g".to_string(),
+                variables: vec!["c".to_string(), "e".to_string()],
+            }],
+            dropped
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let text =
+            r#"{"id":1,"text":"a \"b\"\n\u00e9\ud83d\ude00","list":[true,null,-2.5],"empty":{}}"#;
+        let value = Json::parse(text).unwrap();
+        assert_eq!(Some("a \"b\"\né😀"), value.get("text").as_str());
+        assert_eq!(Some(1), value.get("id").as_u64());
+        assert!(value.at("empty.missing").is_null());
+        assert_eq!(Ok(value.clone()), Json::parse(&value.to_string()));
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse(r#""\ud83d\u0041""#).is_err());
+    }
+
+    struct Capture(Arc<Mutex<Vec<String>>>);
+
+    impl log::Sink for Capture {
+        fn log(&self, target: log::Target, level: log::Level, message: &std::fmt::Arguments) {
+            let line = format!("{} {} {}", level, target, message);
+            self.0.lock().unwrap().push(line);
+        }
+    }
+
+    #[test]
+    fn custom_log_sink() {
+        let lines = Arc::new(Mutex::new(vec![]));
+        log::set_sink(Box::new(Capture(lines.clone())));
+        log::set_level(log::Target::Typeck, Some(log::Level::Trace));
+        type_errors("let logged_variable = box 1\nlet y = logged_variable\n");
+        log::set_level(log::Target::Typeck, None);
+        log::set_sink(Box::new(log::Stderr));
+
+        let lines = lines.lock().unwrap();
+        assert!(lines
+            .iter()
+            .any(|l| l.starts_with("trace typeck Checking if logged_variable is borrowed")));
+        // other tests may log at the same time, but never from a target left off
+        assert!(lines.iter().all(|l| l.contains(" typeck ")));
+    }
+
+    #[test]
+    fn parsed_spans() {
+        let program = crate::parse("let x = y + 1").unwrap();
+        let expected = Term::Let {
+            mutable: false,
+            variable: LVal::Variable {
+                name: "x".to_string(),
+                copyable: None,
+                span: Span::new(4, 5, 1, 5),
+            },
+            term: Box::new(Term::BinaryOp {
+                op: BinOp::Add,
+                lhs: Box::new(Term::Variable(LVal::Variable {
+                    name: "y".to_string(),
+                    copyable: None,
+                    span: Span::new(8, 9, 1, 9),
+                })),
+                rhs: Box::new(Term::Value(
+                    Value::NumericLiteral(1, None),
+                    Some(Span::new(12, 13, 1, 13)),
+                )),
+                span: Span::new(8, 13, 1, 9),
+            }),
+            span: Span::new(0, 13, 1, 1),
+        };
+        assert_eq!(vec![expected], program.terms);
+
+        // the same program laid out differently only differs in its spans
+        let spaced = crate::parse("\nlet  x =  y+1").unwrap();
+        assert_ne!(program, spaced);
+        assert_eq!(program.without_spans(), spaced.without_spans());
+    }
+
+    fn error_span(file: &str) -> Option<(usize, usize)> {
+        let ast = parse(file).unwrap();
+        let Err(Error { span, .. }) = Interpreter::new().run(ast) else {
+            panic!("{} should fail", file);
+        };
+        span.map(|span| (span.line, span.column))
+    }
+
+    #[test]
+    fn error_spans() {
+        // the assignment to x while it is borrowed
+        assert_eq!(Some((3, 1)), error_span("tests/bad/assign_borrowed.mu"));
+        // the addition that overflows
+        assert_eq!(Some((2, 13)), error_span("tests/bad/int_overflow.mu"));
+        // the second move out of x
+        assert_eq!(Some((3, 13)), error_span("tests/bad/worked_example.mu"));
+    }
+
+    fn rendered(file: &str) -> String {
+        let input = std::fs::read_to_string(file).expect("Error reading file");
+        let ast = parse(file).unwrap();
+        let error = Interpreter::new().run(ast).unwrap_err();
+        diagnostic::render(&error, &input, file, false)
+    }
+
+    #[test]
+    fn rendered_diagnostics() {
+        assert_eq!(
+            "Type error[M0007]: Cannot assign to borrowed reference: x
+ --> tests/bad/assign_borrowed.mu:3:1
+  |
+2 | let mut y = ref x
+  |             ----- x is borrowed here
+3 | x = 1
+  | ^^^^^
+  |
+",
+            rendered("tests/bad/assign_borrowed.mu")
+        );
+        assert_eq!(
+            "Type error[M0006]: Type of Box Numeric i64 is undefined, indicating that it was moved
+ --> tests/bad/worked_example.mu:3:13
+  |
+2 | let mut y = x
+  |             - value moved here
+3 | let mut z = x
+  |             ^
+  |
+help: borrow with `ref x` instead of moving
+2 | let mut y = ref x
+3 | let mut z = ref x
+",
+            rendered("tests/bad/worked_example.mu")
+        );
+        // a copied reference is one borrow
+        assert_eq!(
+            "Type error[M0007]: Cannot assign to borrowed reference: x
+ --> tests/bad/assign_borrowed_copied.mu:4:1
+  |
+2 | let y = ref x
+  |         ----- x is borrowed here
+...
+4 | x = box 2
+  | ^^^^^^^^^
+  |
+",
+            rendered("tests/bad/assign_borrowed_copied.mu")
+        );
+        // terms in messages are shown as source
+        assert_eq!(
+            "Type error[M0014]: Let expression does not return a value: f()
+ --> tests/bad/let_no_value.mu:4:1
+  |
+4 | let x = f()
+  | ^^^^^^^^^^^
+  |
+",
+            rendered("tests/bad/let_no_value.mu")
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        let input =
+            std::fs::read_to_string("tests/bad/parse_errors.mu").expect("Error reading file");
+        let tokens = Lexer::new(input.as_str()).tokenize().unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        let found: Vec<(String, TokenKind, usize, usize)> = errors
+            .into_iter()
+            .map(|e| (e.expected, e.found, e.span.line, e.span.column))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "variable".to_string(),
+                    TokenKind::NumericLiteral(2, None),
+                    2,
+                    9
+                ),
+                (
+                    "`ref`".to_string(),
+                    TokenKind::Identifier("a".to_string()),
+                    4,
+                    21
+                ),
+                ("expression".to_string(), TokenKind::LParen, 7, 13),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn lex_errors() {
+        let lex = |input: &str| Lexer::new(input).tokenize().map(|_| ());
+        assert_eq!(
+            Ok(()),
+            lex("let mut x_1 = 1 // comment\n/* block\ncomment */ x_1")
+        );
+        assert_eq!(
+            Err(LexError::UnexpectedCharacter('#', Span::new(14, 15, 1, 15))),
+            lex("let mut x = 1 # 2")
+        );
+        assert_eq!(
+            Err(LexError::InvalidLiteral(
+                "12ab".to_string(),
+                Span::new(12, 16, 1, 13)
+            )),
+            lex("let mut x = 12ab")
+        );
+        assert_eq!(
+            Err(LexError::UnterminatedComment(Span::new(14, 29, 1, 15))),
+            lex("let mut x = 1 /* never closed")
+        );
+        let Err(e) = Lexer::new("let mut x = 1\nlet mut y = x ! 2").tokenize() else {
+            panic!("`!` on its own should not lex");
+        };
+        assert_eq!((2, 15), (e.span().line, e.span().column));
+    }
+
     macro_rules! run_tests {
         ($($name:ident: $value:expr,)*) => {
         $(
             #[test]
             fn $name() {
-                let (input, expected) : (&str, Result<Vec<(&str, &str)>, String>)= $value;
+                let (input, expected) : (&str, Result<Vec<(&str, &str)>, ErrorKind>)= $value;
 
                 match expected {
                     Ok(out) => {
@@ -100,21 +915,101 @@ mod tests {
             ("x", " 0"),
             ("y", "ref 0"),
         ])),
+        print_assert: ("tests/good/print_assert.mu", Ok(vec![
+            ("x", " 5"),
+            ("y", "ref 5"),
+        ])),
+        trait_dispatch: ("tests/good/trait_dispatch.mu", Ok(vec![
+            ("x", " 2"),
+            ("a", " 2"),
+            ("b", " Undefined"),
+            ("c", " 100"),
+            ("d", " 7"),
+        ])),
+        inherent_methods: ("tests/good/inherent_methods.mu", Ok(vec![
+            ("x", "ref 5"),
+            ("a", " 5"),
+            ("r", "ref 5"),
+            ("b", " 5"),
+            ("y", " Undefined"),
+            ("c", " 2"),
+        ])),
+        explicit_borrow: ("tests/good/explicit_borrow.mu", Ok(vec![
+            ("x", " 1"),
+            ("y", " 0"),
+            ("z", " 2"),
+        ])),
+        int_inferred: ("tests/good/int_inferred.mu", Ok(vec![
+            ("x", " 3"),
+            ("y", " 4"),
+            ("z", " 7"),
+        ])),
+        sized_ints: ("tests/good/sized_ints.mu", Ok(vec![
+            ("x", " 254"),
+            ("y", " 255"),
+            ("z", " -128"),
+            ("w", " 18446744073709551614"),
+            ("v", " -10"),
+            ("i", " 2147483646"),
+            ("j", " 2147483647"),
+        ])),
+        comments: ("tests/good/comments.mu", Ok(vec![
+            ("heap_x", "ref 1"),
+            ("x_2", " 2"),
+            ("y_1", " 3"),
+            ("z", " 3"),
+        ])),
 
         // bad
 
-        double_mut_ref: ("tests/bad/double_mut_ref.mu", Err(TypeError::MutrefAlreadyBorrowedImmut("x".to_string()).to_string())),
-        assign_borrowed: ("tests/bad/assign_borrowed.mu", Err(TypeError::AssignBorrowed("x".to_string()).to_string())),
-        assign_mut_borrowed: ("tests/bad/assign_mut_borrowed.mu", Err(TypeError::AssignBorrowed("x".to_string()).to_string())),
-        bad_typing: ("tests/bad/bad_typing.mu", Err(TypeError::IncompatibleTypes(Type::Box(Box::new(Type::Numeric)), Type::Reference { var: LVal::Variable { name: "z".to_string(), copyable: None }, mutable: false }).to_string())),
-        dec_after_partial_move: ("tests/bad/dec_after_partial_move.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric))).to_string())),
-        mut_after_immut: ("tests/bad/mut_after_immut.mu", Err(TypeError::MutrefAlreadyBorrowedImmut("x".to_string()).to_string())),
-        mut_from_immut: ("tests/bad/mut_from_immut.mu", Err(TypeError::MutrefImmut("y".to_string()).to_string())),
-        function_incorrect_arg_type: ("tests/bad/function_incorrect_arg_type.mu", Err(TypeError::FunctionCallIncompatableArgumentType(Type::Numeric, Type::Box(Box::new(Type::Numeric))).to_string())),
-        function_incorrect_arg_count: ("tests/bad/function_incorrect_arg_count.mu", Err(TypeError::FunctionCallIncompatableArgumentCount(2, 1).to_string())),
-        function_incorrect_return_type: ("tests/bad/function_incorrect_return_type.mu", Err(TypeError::FunctionUnexpectedReturn(Type::Numeric, Type::Box(Box::new(Type::Numeric))).to_string())),
-        worked_example: ("tests/bad/worked_example.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric))).to_string())),
-        func_not_def: ("tests/bad/func_not_def.mu", Err(TypeError::FunctionNotDefined("g".to_string()).to_string())),
-        func_dup_arg: ("tests/bad/func_dup_arg.mu", Err(TypeError::FunctionDeclDupArg("x".to_string()).to_string())),
+        double_mut_ref: ("tests/bad/double_mut_ref.mu", Err(TypeError::MutrefAlreadyBorrowedImmut("x".to_string()).into())),
+        assign_borrowed: ("tests/bad/assign_borrowed.mu", Err(TypeError::AssignBorrowed("x".to_string()).into())),
+        assign_mut_borrowed: ("tests/bad/assign_mut_borrowed.mu", Err(TypeError::AssignBorrowed("x".to_string()).into())),
+        bad_typing: ("tests/bad/bad_typing.mu", Err(TypeError::IncompatibleTypes(Type::Box(Box::new(Type::Numeric(IntType::I64))), Type::Reference { var: LVal::Variable { name: "z".to_string(), copyable: None, span: Span::new(54, 59, 4, 5) }, mutable: false }).into())),
+        dec_after_partial_move: ("tests/bad/dec_after_partial_move.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        mut_after_immut: ("tests/bad/mut_after_immut.mu", Err(TypeError::MutrefAlreadyBorrowedImmut("x".to_string()).into())),
+        mut_from_immut: ("tests/bad/mut_from_immut.mu", Err(TypeError::MutrefImmut("y".to_string()).into())),
+        function_incorrect_arg_type: ("tests/bad/function_incorrect_arg_type.mu", Err(TypeError::FunctionCallIncompatableArgumentType(Type::Numeric(IntType::I64), Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        function_incorrect_arg_count: ("tests/bad/function_incorrect_arg_count.mu", Err(TypeError::FunctionCallIncompatableArgumentCount(2, 1).into())),
+        function_incorrect_return_type: ("tests/bad/function_incorrect_return_type.mu", Err(TypeError::FunctionUnexpectedReturn(Type::Numeric(IntType::I64), Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        worked_example: ("tests/bad/worked_example.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        func_not_def: ("tests/bad/func_not_def.mu", Err(TypeError::FunctionNotDefined("g".to_string()).into())),
+        func_dup_arg: ("tests/bad/func_dup_arg.mu", Err(TypeError::FunctionDeclDupArg("x".to_string()).into())),
+        int_literal_out_of_range: ("tests/bad/int_literal_out_of_range.mu", Err(TypeError::LiteralOutOfRange(256, IntType::U8).into())),
+        int_inferred_out_of_range: ("tests/bad/int_inferred_out_of_range.mu", Err(TypeError::LiteralOutOfRange(300, IntType::U8).into())),
+        int_mismatched_types: ("tests/bad/int_mismatched_types.mu", Err(TypeError::IncompatibleTypes(Type::Numeric(IntType::I32), Type::Numeric(IntType::I64)).into())),
+        int_overflow: ("tests/bad/int_overflow.mu", Err(RuntimeError::IntegerOverflow(255, BinOp::Add, 1, IntType::U8).into())),
+        int_inferred_argument_out_of_range: ("tests/bad/int_inferred_argument_out_of_range.mu", Err(TypeError::LiteralOutOfRange(256, IntType::U8).into())),
+        int_inferred_overflow: ("tests/bad/int_inferred_overflow.mu", Err(RuntimeError::IntegerOverflow(255, BinOp::Add, 1, IntType::U8).into())),
+        assert_failed: ("tests/bad/assert_failed.mu", Err(RuntimeError::AssertionFailed(Box::new(Term::BinaryOp {
+            op: BinOp::Lt,
+            lhs: Box::new(Term::Variable(LVal::Variable { name: "y".to_string(), copyable: Some(true), span: Span::new(39, 40, 3, 8) })),
+            rhs: Box::new(Term::Variable(LVal::Variable { name: "x".to_string(), copyable: Some(true), span: Span::new(43, 44, 3, 12) })),
+            span: Span::new(39, 44, 3, 8),
+        })).into())),
+        trait_method_missing: ("tests/bad/trait_method_missing.mu", Err(TypeError::TraitMethodMissing("Size".to_string(), "grow".to_string()).into())),
+        trait_method_mismatch: ("tests/bad/trait_method_mismatch.mu", Err(TypeError::TraitMethodMismatch("Size".to_string(), "size".to_string()).into())),
+        method_not_defined: ("tests/bad/method_not_defined.mu", Err(TypeError::MethodNotDefined(Type::Box(Box::new(Type::Numeric(IntType::I64))), "size".to_string()).into())),
+        method_after_move: ("tests/bad/method_after_move.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        method_receiver_borrowed: ("tests/bad/method_receiver_borrowed.mu", Err(TypeError::MutrefAlreadyBorrowedImmut("x".to_string()).into())),
+        method_receiver_conflict: ("tests/bad/method_receiver_conflict.mu", Err(TypeError::MoveNotWritable("x".to_string()).into())),
+        method_receiver_mut_borrowed: ("tests/bad/method_receiver_mut_borrowed.mu", Err(TypeError::RefAlreadyBorrowedMut("x".to_string()).into())),
+        method_already_defined: ("tests/bad/method_already_defined.mu", Err(TypeError::MethodAlreadyDefined(Type::Numeric(IntType::I64), "get".to_string()).into())),
+        self_outside_impl: ("tests/bad/self_outside_impl.mu", Err(TypeError::SelfOutsideImpl("size".to_string()).into())),
+        explicit_borrow_alias: ("tests/bad/explicit_borrow_alias.mu", Err(TypeError::MutrefAlreadyBorrowedImmut("x".to_string()).into())),
+        assert_not_bool: ("tests/bad/assert_not_bool.mu", Err(TypeError::IncompatibleTypes(Type::Bool, Type::Numeric(IntType::I64)).into())),
+        deref_not_reference: ("tests/bad/deref_not_reference.mu", Err(TypeError::NotAReference("x".to_string()).into())),
+        variable_not_defined: ("tests/bad/variable_not_defined.mu", Err(TypeError::VariableNotDefined("z".to_string()).into())),
+        move_borrowed: ("tests/bad/move_borrowed.mu", Err(TypeError::MoveNotWritable("x".to_string()).into())),
+        copy_mut_borrowed: ("tests/bad/copy_mut_borrowed.mu", Err(TypeError::CopyNotReadable("x".to_string()).into())),
+        assign_immutable: ("tests/bad/assign_immutable.mu", Err(TypeError::NotMutable("x".to_string()).into())),
+        mut_ref_immutable: ("tests/bad/mut_ref_immutable.mu", Err(TypeError::NotMutable("x".to_string()).into())),
+        ref_not_defined: ("tests/bad/ref_not_defined.mu", Err(TypeError::VariableNotDefined("z".to_string()).into())),
+        print_ref_not_defined: ("tests/bad/print_ref_not_defined.mu", Err(TypeError::VariableNotDefined("z".to_string()).into())),
+        assign_through_not_defined: ("tests/bad/assign_through_not_defined.mu", Err(TypeError::VariableNotDefined("x".to_string()).into())),
+        mut_ref_after_move: ("tests/bad/mut_ref_after_move.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        assign_ref_after_move: ("tests/bad/assign_ref_after_move.mu", Err(TypeError::TypeMoved(Type::Box(Box::new(Type::Numeric(IntType::I64)))).into())),
+        move_out_of_ref: ("tests/bad/move_out_of_ref.mu", Err(TypeError::MoveOutOfReference("*y".to_string()).into())),
+        move_out_of_mut_ref: ("tests/bad/move_out_of_mut_ref.mu", Err(TypeError::MoveOutOfReference("*y".to_string()).into())),
     }
 }
@@ -0,0 +1,43 @@
+use std::fmt::{self, Display};
+
+// a region of the source file, as byte offsets plus the line and column it starts at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Span {
+        Span {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    // the empty span where self starts, to insert text before it
+    pub fn shrink_to_lo(self) -> Span {
+        Span {
+            end: self.start,
+            ..self
+        }
+    }
+
+    // span from the start of self to the end of other
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            end: other.end.max(self.end),
+            ..self
+        }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
@@ -1,38 +1,57 @@
-use std::{
-    collections::HashMap,
-    sync::atomic::{AtomicUsize, Ordering},
-};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::{Arc, Mutex};
 
 use crate::{
     ast::{Argument, LVal, Reference, Term, Value},
+    constants::RuntimeError,
+    debugger::{Pause, Step},
     typing::Slot,
 };
 type Location = String;
+// ordered maps, so that anything walking a frame or the store sees the same order every run
 #[derive(Debug, Clone)]
 pub struct StackFrame {
-    pub locations: HashMap<String, Reference>,
-    pub functions: HashMap<String, (Vec<Argument>, Vec<Term>)>,
+    pub(crate) locations: BTreeMap<String, Reference>,
+    pub(crate) functions: BTreeMap<String, (Vec<Argument>, Vec<Term>)>,
+}
+
+impl StackFrame {
+    /// Where each variable in this frame lives in the store.
+    pub fn locations(&self) -> &BTreeMap<String, Reference> {
+        return &self.locations;
+    }
+
+    /// The functions declared in this frame, as their arguments and body.
+    pub fn functions(&self) -> &BTreeMap<String, (Vec<Argument>, Vec<Term>)> {
+        return &self.functions;
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Store {
-    pub cells: HashMap<Location, Slot<Value>>,
+    pub(crate) cells: BTreeMap<Location, Slot<Value>>,
+    next_location: usize, // locations are numbered per store, from l-1
 }
 
-static COUNTER: AtomicUsize = AtomicUsize::new(1);
-fn get_id() -> usize {
-    COUNTER.fetch_add(1, Ordering::Relaxed)
+impl Default for Store {
+    fn default() -> Store {
+        return Store::new();
+    }
 }
 
 impl Store {
     pub fn new() -> Store {
         Store {
-            cells: HashMap::new(),
+            cells: BTreeMap::new(),
+            next_location: 1,
         }
     }
 
-    pub fn allocate(&mut self, value: Value, lifetime: usize) -> Reference {
-        let location = format!("l-{}", get_id());
+    pub(crate) fn allocate(&mut self, value: Value, lifetime: usize) -> Reference {
+        let location = format!("l-{}", self.next_location);
+        self.next_location += 1;
         let reference = Reference {
             location: location.clone(),
             owned: true,
@@ -40,25 +59,30 @@ impl Store {
         };
         let slot = Slot {
             value: value.clone(),
-            lifetime: lifetime,
+            lifetime,
         };
         self.cells.insert(reference.location.clone(), slot);
         return reference;
     }
 
-    pub fn read(&self, reference: Reference) -> Result<Value, String> {
+    pub fn read(&self, reference: Reference) -> Result<Value, RuntimeError> {
         let location = reference.location;
-        let value: Value = self.cells.get(&location).unwrap().value.clone();
-        Ok(value)
+        match self.cells.get(&location) {
+            Some(slot) => Ok(slot.value.clone()),
+            None => Err(RuntimeError::DanglingReference(location)),
+        }
     }
 
-    pub fn write(&mut self, reference: Reference, value: Value) -> Result<(), String> {
+    pub(crate) fn write(&mut self, reference: Reference, value: Value) -> Result<(), RuntimeError> {
         let location = reference.location;
-        self.cells.get_mut(&location).unwrap().value = value;
+        match self.cells.get_mut(&location) {
+            Some(slot) => slot.value = value,
+            None => return Err(RuntimeError::DanglingReference(location)),
+        }
         Ok(())
     }
 
-    pub fn drop_lifetime(&mut self, lifetime: usize) {
+    pub(crate) fn drop_lifetime(&mut self, lifetime: usize) {
         // filter out all cells with the given lifetime
         let cells = self.cells.clone();
         for (location, slot) in cells {
@@ -68,17 +92,14 @@ impl Store {
         }
     }
 
-    pub fn drop(&mut self, value: &Value) -> Result<(), String> {
-        match value {
-            Value::Reference(r) => {
-                if r.owned {
-                    let location = &r.location;
-                    let value = self.cells.get(location).unwrap().value.clone();
-                    self.drop(&value)?;
-                    self.cells.remove(location);
-                }
+    pub(crate) fn drop(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        if let Value::Reference(r) = value {
+            if r.owned {
+                let location = &r.location;
+                let value = self.cells.get(location).unwrap().value.clone();
+                self.drop(&value)?;
+                self.cells.remove(location);
             }
-            _ => (),
         };
         Ok(())
     }
@@ -86,21 +107,114 @@ impl Store {
     pub fn get(&self, reference: Reference) -> Option<&Slot<Value>> {
         self.cells.get(&reference.location)
     }
+
+    /// Every live cell, by location.
+    pub fn cells(&self) -> &BTreeMap<Location, Slot<Value>> {
+        return &self.cells;
+    }
+}
+
+// the link to a debugger driving the program from another thread. Every clone of a
+// state shares one trace, so lines printed inside a call that fails are still sent
+#[derive(Debug, Clone)]
+pub(crate) struct Trace {
+    steps: Sender<Step>,
+    resume: Arc<Mutex<Receiver<()>>>,
+    sent: Arc<AtomicUsize>, // lines of the running top-level statement already sent
+}
+
+impl Trace {
+    pub(crate) fn new(steps: Sender<Step>, resume: Receiver<()>) -> Trace {
+        return Trace {
+            steps,
+            resume: Arc::new(Mutex::new(resume)),
+            sent: Arc::new(AtomicUsize::new(0)),
+        };
+    }
+
+    // send the lines the running statement printed since the last time
+    fn send_printed(&self, printed: &[String]) -> Result<(), RuntimeError> {
+        let sent = self.sent.swap(printed.len(), Ordering::Relaxed);
+        for line in &printed[sent.min(printed.len())..] {
+            self.send(Step::Printed(line.clone()))?;
+        }
+        return Ok(());
+    }
+
+    fn send(&self, step: Step) -> Result<(), RuntimeError> {
+        return self.steps.send(step).map_err(|_| RuntimeError::Stopped);
+    }
+
+    // a top-level statement finished, having printed `printed`
+    pub(crate) fn flush(&self, printed: &[String]) {
+        // if the debugger went away the next pause stops the program
+        let _ = self.send_printed(printed);
+        self.sent.store(0, Ordering::Relaxed);
+    }
 }
 
 #[derive(Debug, Clone)]
 
 pub struct State {
-    pub stack: Vec<StackFrame>,
-    pub store: Store,
+    pub(crate) stack: Vec<StackFrame>,
+    pub(crate) store: Store,
+    pub(crate) printed: Vec<String>, // lines written by print, not yet flushed to the interpreter
+    pub(crate) trace: Option<Trace>, // set while a debugger is attached
 }
 
 impl State {
-    pub fn new(stack: Vec<StackFrame>, heap: Store) -> State {
-        State { stack, store: heap }
+    pub(crate) fn new(stack: Vec<StackFrame>, heap: Store) -> State {
+        State {
+            stack,
+            store: heap,
+            printed: vec![],
+            trace: None,
+        }
+    }
+
+    // wait before `term` is evaluated until the debugger says to go on, if one is
+    // attached, and stop the program if the debugger went away
+    pub(crate) fn pause(&self, term: &Term, function: Option<&str>) -> Result<(), RuntimeError> {
+        let Some(trace) = &self.trace else {
+            return Ok(());
+        };
+        trace.send_printed(&self.printed)?;
+        trace.send(Step::Paused(Pause {
+            span: term.span().unwrap_or_default(),
+            depth: self.stack.len() - 1,
+            function: function.map(|f| f.to_string()),
+            state: self.untraced(),
+        }))?;
+        let resume = trace.resume.lock().unwrap();
+        return resume.recv().map_err(|_| RuntimeError::Stopped);
+    }
+
+    // a copy that records nothing, for evaluating a term only to check a property
+    pub(crate) fn untraced(&self) -> State {
+        return State {
+            trace: None,
+            ..self.clone()
+        };
+    }
+
+    /// The stack frames, outermost first.
+    pub fn frames(&self) -> &[StackFrame] {
+        return &self.stack;
+    }
+
+    pub fn store(&self) -> &Store {
+        return &self.store;
+    }
+
+    // render a value for print, following references to the value they point at
+    pub fn render(&self, value: &Value) -> Result<String, RuntimeError> {
+        match value {
+            Value::Reference(r) => self.render(&self.store.read(r.clone())?),
+            _ => Ok(value.to_string()),
+        }
     }
 
-    #[allow(dead_code)]
+    /// The value of every variable, written as print would show it.
     pub fn output(&self) -> HashMap<String, String> {
         let mut output = HashMap::new();
         for frame in &self.stack {
@@ -142,14 +256,10 @@ impl State {
         }
     }
 
-    pub fn locate(&self, name: String) -> Result<Reference, String> {
+    pub fn locate(&self, name: String) -> Result<Reference, RuntimeError> {
         match self.top().locations.get(name.as_str()) {
             Some(reference) => Ok(reference.clone()),
-            None => Err(format!(
-                "Error locating variable: {:?} does not exist in {:#?}",
-                name,
-                self.top()
-            )),
+            None => Err(RuntimeError::VariableNotFound(name)),
         }
     }
 
@@ -174,71 +284,74 @@ impl State {
         return env;
     }
 
-    pub fn top_mut(&mut self) -> &mut StackFrame {
+    pub(crate) fn top_mut(&mut self) -> &mut StackFrame {
         let length = self.stack.len();
         return &mut self.stack[length - 1];
     }
 
-    pub fn add_function(&mut self, name: String, args: Vec<Argument>, body: Vec<Term>) {
+    pub(crate) fn add_function(&mut self, name: String, args: Vec<Argument>, body: Vec<Term>) {
         self.top_mut().functions.insert(name, (args, body));
     }
 }
 
 // Helper functions
-pub fn add_function(mut s: State, name: String, args: Vec<Argument>, body: Vec<Term>) -> State {
+pub(crate) fn add_function(
+    mut s: State,
+    name: String,
+    args: Vec<Argument>,
+    body: Vec<Term>,
+) -> State {
     s.add_function(name, args, body);
     return s;
 }
 
-pub fn loc(s: &State, variable: &LVal) -> Result<Reference, String> {
+pub(crate) fn loc(s: &State, variable: &LVal) -> Result<Reference, RuntimeError> {
     // loc(S, x) = ℓ
     match variable {
         LVal::Variable { name, .. } => s.locate(name.clone()),
         LVal::Deref { var, .. } => {
             let name = var.get_name();
-            // get the reference to the value
-            let reference = s.locate(name.clone())?;
+            // get the reference to the value, following any inner derefs first
+            let reference = loc(s, var)?;
             // get the value from the heap
-            let value = s.store.get(reference).unwrap().value.clone();
-            match value {
+            match s.store.read(reference)? {
                 Value::Reference(r) => Ok(r),
-                _ => Err(format!(
-                    "Error dereferencing variable: {:?} is not a reference",
-                    name
-                )),
+                _ => Err(RuntimeError::NotAReference(name)),
             }
         }
     }
 }
 
-pub fn read(s: &State, variable: &LVal) -> Result<Value, String> {
+pub(crate) fn read(s: &State, variable: &LVal) -> Result<Value, RuntimeError> {
     s.store.read(loc(s, variable)?)
 }
 
-pub fn write(mut s: State, variable: &LVal, value: &Value) -> Result<State, String> {
+pub(crate) fn write(mut s: State, variable: &LVal, value: &Value) -> Result<State, RuntimeError> {
     s.store.write(loc(&s, variable)?, value.clone())?;
     Ok(s)
 }
 
-pub fn insert(mut s: State, lifetime: usize, value: &Value) -> (State, Reference) {
+pub(crate) fn insert(mut s: State, lifetime: usize, value: &Value) -> (State, Reference) {
     // S [ℓw ↦ → ⟨v⊥⟩m]
-    let r = s.store.allocate(value.clone(), lifetime as usize);
+    let r = s.store.allocate(value.clone(), lifetime);
     (s, r)
 }
 
-pub fn bind(mut s: State, variable: &String, reference: Reference) -> State {
+pub(crate) fn bind(mut s: State, variable: &str, reference: Reference) -> State {
     // S [ℓw ↦ → ⟨v⊥⟩m]
-    s.top_mut().locations.insert(variable.clone(), reference);
+    s.top_mut()
+        .locations
+        .insert(variable.to_string(), reference);
     s
 }
 
-pub fn drop(mut s: State, value: &Value) -> Result<State, String> {
+pub(crate) fn drop(mut s: State, value: &Value) -> Result<State, RuntimeError> {
     // S [ℓw ↦ → ⟨·⟩m]
     s.store.drop(value)?;
     Ok(s)
 }
 
-pub fn drop_lifetime(mut s: State, lifetime: usize) -> State {
+pub(crate) fn drop_lifetime(mut s: State, lifetime: usize) -> State {
     // S [ℓw ↦ → ⟨·⟩m]
     s.stack.remove(lifetime);
     s.store.drop_lifetime(lifetime);
@@ -0,0 +1,591 @@
+//! Annotates each line of a program with the ownership events that happen on it:
+//! moves, copies, borrows and reborrows, drops of the old value on assignment, and
+//! the drops when a function's scope ends. Each borrow also gets its live range,
+//! from the line it is made on to the line its last holder stops holding it.
+//!
+//! The events are read off the checked program, so a variable use is a copy or a
+//! move exactly when the checker marked it so, and borrows are tracked through the
+//! types the checker gives the variables holding them.
+
+use crate::{
+    ast::{Argument, LVal, Term},
+    span::Span,
+    typecheck::{function_scope, register_methods, TypeCheck},
+    typing::{borrowed, method_function_name, Slot, Type, TypeEnviroment},
+    Error,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    Move(String),
+    Copy(String),
+    Borrow {
+        variable: String,
+        mutable: bool,
+        reborrow: bool, // borrowed through a reference
+    },
+    AssignDrop(String),
+    ScopeDrop {
+        function: String,
+        variables: Vec<String>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub span: Span,
+    pub kind: EventKind,
+}
+
+/// A borrow made at `span`, live from line `start` to line `end`, or to the end of
+/// the program when `end` is None.
+#[derive(Debug, Clone)]
+pub struct Borrow {
+    pub span: Span,
+    pub variable: String,
+    pub holders: Vec<String>, // the variables that held it, none for one made for a call
+    pub start: usize,
+    pub end: Option<usize>,
+}
+
+// the borrows drawn in one lane, as their first line, their last line, whether they
+// end there rather than at the end of the program, and which borrow they are
+type Lane = Vec<(usize, usize, bool, usize)>;
+
+#[derive(Debug)]
+pub struct Timeline {
+    source: String,
+    events: Vec<Event>,
+    borrows: Vec<Borrow>,
+}
+
+impl Timeline {
+    /// Parse and check `source`, and find its ownership events.
+    pub fn new(source: &str, environment: TypeEnviroment) -> Result<Timeline, Vec<Error>> {
+        let program = crate::parse(source)?;
+        let checked = crate::check_with(&program, environment.clone()).map_err(|mut errors| {
+            crate::verify_suggestions(&mut errors, source, 0, &environment);
+            return errors;
+        })?;
+        let mut timeline = Timeline {
+            source: source.to_string(),
+            events: vec![],
+            borrows: vec![],
+        };
+        timeline.block(&checked.program.terms, environment, 0, None);
+        timeline.events.sort_by_key(|e| e.span.start);
+        return Ok(timeline);
+    }
+
+    /// Every event, in source order.
+    pub fn events(&self) -> &[Event] {
+        return &self.events;
+    }
+
+    /// Every borrow, in the order they are made.
+    pub fn borrows(&self) -> &[Borrow] {
+        return &self.borrows;
+    }
+
+    // walk the statements of a block, returning the environment after them. `close`
+    // is the offset of the brace that ends the block, if it is a function body
+    fn block(
+        &mut self,
+        terms: &[Term],
+        gamma: TypeEnviroment,
+        lifetime: usize,
+        close: Option<usize>,
+    ) -> TypeEnviroment {
+        let mut gamma = gamma;
+        let mut live: Vec<usize> = vec![]; // the borrows made in this block still held
+        for term in terms {
+            let made = self.borrows.len();
+            self.visit(term, &gamma);
+            match term {
+                Term::FunctionDeclaration {
+                    name,
+                    args,
+                    body,
+                    ty,
+                    span,
+                } => {
+                    let (_, g_block) = function_scope(gamma.clone(), name, args, ty, lifetime);
+                    self.function(name, args, body, g_block, lifetime + 1, *span);
+                }
+                Term::Impl {
+                    trait_name,
+                    ty,
+                    methods,
+                    ..
+                } => {
+                    let mut g = gamma.clone();
+                    register_methods(&mut g, trait_name, ty, methods);
+                    for method in methods {
+                        let Term::FunctionDeclaration {
+                            name,
+                            args,
+                            body,
+                            ty: ret,
+                            span,
+                        } = method
+                        else {
+                            continue;
+                        };
+                        let function = method_function_name(ty, trait_name.as_deref(), name);
+                        let (g2, g_block) = function_scope(g, &function, args, ret, lifetime);
+                        self.function(name, args, body, g_block, lifetime + 1, *span);
+                        g = g2;
+                    }
+                }
+                _ => {}
+            }
+            if let Ok((g, _)) = term.clone().type_check(gamma.clone(), lifetime) {
+                gamma = g;
+            }
+
+            // a borrow ends with the statement after which nothing holds it
+            live.extend(made..self.borrows.len());
+            let end = term.span().map_or(0, |span| self.line(span.end));
+            live.retain(|i| {
+                let borrow = &mut self.borrows[*i];
+                let holders = holders(&gamma, borrow.span);
+                if holders.is_empty() {
+                    borrow.end = Some(end);
+                    return false;
+                }
+                for holder in holders {
+                    if !borrow.holders.contains(&holder) {
+                        borrow.holders.push(holder);
+                    }
+                }
+                return true;
+            });
+        }
+
+        // what is still held at the end of a body ends with it, at the top level it
+        // lives until the program ends
+        if let Some(brace) = close {
+            let end = self.line(brace);
+            for i in live {
+                self.borrows[i].end = Some(end);
+            }
+        }
+        return gamma;
+    }
+
+    // walk a function body, then drop its arguments and the variables it declared,
+    // unless they were moved out or hold a borrow, at the closing brace
+    fn function(
+        &mut self,
+        name: &str,
+        args: &[Argument],
+        body: &[Term],
+        gamma: TypeEnviroment,
+        lifetime: usize,
+        span: Span,
+    ) {
+        let brace = span.end.saturating_sub(1);
+        let gamma = self.block(body, gamma, lifetime, Some(brace));
+        let declared = gamma
+            .bindings()
+            .filter(|(name, slot)| slot.lifetime == lifetime && !name.contains('-'))
+            .map(|(name, _)| name.clone());
+        let variables: Vec<String> = args
+            .iter()
+            .map(|arg| arg.name.clone())
+            .chain(declared)
+            .filter(|name| match gamma.get_partial(name) {
+                // what a reference borrows is dropped by its owner, even through a box
+                Ok(Slot { value, .. }) => {
+                    !matches!(value, Type::Undefined(_) | Type::Function { .. })
+                        && borrowed(&value).is_none()
+                }
+                Err(_) => true,
+            })
+            .collect();
+        if !variables.is_empty() {
+            self.events.push(Event {
+                span: Span::new(brace, brace + 1, self.line(brace), 0),
+                kind: EventKind::ScopeDrop {
+                    function: name.to_string(),
+                    variables,
+                },
+            });
+        }
+    }
+
+    // the events in one statement, given the environment before it
+    fn visit(&mut self, term: &Term, gamma: &TypeEnviroment) {
+        match term {
+            Term::Variable(var) => self.variable(var),
+            Term::Ref { mutable, var, span } => self.borrow(var, *mutable, *span, gamma),
+            Term::FunctionCall { name, params, .. } => {
+                let args = arguments(gamma, name);
+                self.params(&args, params, gamma);
+            }
+            Term::MethodCall {
+                receiver,
+                params,
+                function,
+                ..
+            } => {
+                let args = function
+                    .as_ref()
+                    .map_or(vec![], |function| arguments(gamma, function));
+                // self is borrowed or passed like any other argument
+                match args.first() {
+                    Some(arg) if arg.reference => {
+                        self.borrow(receiver, arg.mutable, receiver.span(), gamma)
+                    }
+                    _ => self.variable(receiver),
+                }
+                self.params(args.get(1..).unwrap_or_default(), params, gamma);
+            }
+            Term::Let { term, .. } | Term::Box { term, .. } | Term::Print { term, .. } => {
+                self.visit(term, gamma)
+            }
+            Term::Assert { cond, .. } => self.visit(cond, gamma),
+            Term::BinaryOp { lhs, rhs, .. } => {
+                self.visit(lhs, gamma);
+                self.visit(rhs, gamma);
+            }
+            Term::Assign { variable, term, .. } => {
+                self.visit(term, gamma);
+                // assigning over a box drops the value it owned
+                if let Ok((_, Type::Box(_))) = variable.clone().type_check(gamma.clone(), 0) {
+                    self.events.push(Event {
+                        span: variable.span(),
+                        kind: EventKind::AssignDrop(variable.to_string()),
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // a reference argument given a plain variable borrows it for the call
+    fn params(&mut self, args: &[Argument], params: &[Term], gamma: &TypeEnviroment) {
+        for (i, param) in params.iter().enumerate() {
+            match (args.get(i), param) {
+                (Some(arg), Term::Variable(var)) if arg.reference => {
+                    self.borrow(var, arg.mutable, var.span(), gamma)
+                }
+                _ => self.visit(param, gamma),
+            }
+        }
+    }
+
+    fn variable(&mut self, var: &LVal) {
+        let kind = match var.is_copyable() {
+            Ok(true) => EventKind::Copy(var.to_string()),
+            _ => EventKind::Move(var.to_string()),
+        };
+        self.events.push(Event {
+            span: var.span(),
+            kind,
+        });
+    }
+
+    fn borrow(&mut self, var: &LVal, mutable: bool, span: Span, gamma: &TypeEnviroment) {
+        let reborrow = matches!(var, LVal::Deref { .. })
+            && matches!(
+                gamma.get_partial(&var.get_name()),
+                Ok(Slot {
+                    value: Type::Reference { .. },
+                    ..
+                })
+            );
+        self.events.push(Event {
+            span,
+            kind: EventKind::Borrow {
+                variable: var.to_string(),
+                mutable,
+                reborrow,
+            },
+        });
+        self.borrows.push(Borrow {
+            span,
+            variable: var.to_string(),
+            holders: vec![],
+            start: span.line,
+            end: None,
+        });
+    }
+
+    /// The source with the events of each line under it, and a lane to its left for
+    /// each borrow that lives across lines.
+    pub fn text(&self) -> String {
+        let lines: Vec<&str> = self.source.lines().collect();
+        let lanes = self.lanes(lines.len());
+        let width = lines.len().to_string().len();
+        let mut out = String::new();
+        for (i, line) in lines.iter().enumerate() {
+            let number = i + 1;
+            let marks: String = lanes
+                .iter()
+                .map(
+                    |lane| match lane.iter().find(|b| b.0 <= number && number <= b.1) {
+                        Some((start, ..)) if *start == number => "+ ",
+                        Some((_, end, true, _)) if *end == number => "' ",
+                        Some(_) => "| ",
+                        None => "  ",
+                    },
+                )
+                .collect();
+            out.push_str(&format!("{:>width$} | {}{}\n", number, marks, line));
+
+            // under the line, the lanes of the borrows that carry on past it
+            let carried: String = lanes
+                .iter()
+                .map(
+                    |lane| match lane.iter().any(|b| b.0 <= number && number < b.1) {
+                        true => "| ",
+                        false => "  ",
+                    },
+                )
+                .collect();
+            for event in self
+                .events
+                .iter()
+                .filter(|e| self.line(e.span.start) == number)
+            {
+                let start = self.source[..event.span.start]
+                    .rfind('\n')
+                    .map_or(0, |i| i + 1);
+                let column = self.source[start..event.span.start].chars().count();
+                let end = event.span.end.min(start + line.len()).max(event.span.start);
+                let length = self.source[event.span.start..end].chars().count().max(1);
+                out.push_str(&format!(
+                    "{:>width$} | {}{}{} {}\n",
+                    "",
+                    carried,
+                    " ".repeat(column),
+                    "^".repeat(length),
+                    self.describe(event)
+                ));
+            }
+        }
+        return out;
+    }
+
+    /// A self-contained HTML page of the source with its events highlighted and
+    /// listed beside each line, and the borrows drawn as bars down the left.
+    pub fn html(&self, title: &str) -> String {
+        let lines: Vec<&str> = self.source.lines().collect();
+        let lanes = self.lanes(lines.len());
+        let mut out = String::new();
+        out.push_str(&format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n",
+            escape(title)
+        ));
+        out.push_str(STYLE);
+        out.push_str(&format!("</head>\n<body>\n<h1>{}</h1>\n", escape(title)));
+        out.push_str(
+            "<p class=\"legend\"><span class=\"move\">move</span> <span class=\"copy\">copy</span> \
+             <span class=\"borrow\">borrow</span> <span class=\"reborrow\">reborrow</span> \
+             <span class=\"drop\">drop</span></p>\n<table>\n",
+        );
+        let mut offset = 0;
+        for (i, line) in lines.iter().enumerate() {
+            let number = i + 1;
+            out.push_str(&format!("<tr><td class=\"number\">{}</td>", number));
+            for (l, lane) in lanes.iter().enumerate() {
+                match lane.iter().find(|b| b.0 <= number && number <= b.1) {
+                    Some((start, _, _, borrow)) => {
+                        let title = self.range(&self.borrows[*borrow]);
+                        let first = if *start == number { " start" } else { "" };
+                        out.push_str(&format!(
+                            "<td class=\"lane live lane-{}{}\" title=\"{}\"></td>",
+                            l % 6,
+                            first,
+                            escape(&title)
+                        ));
+                    }
+                    None => out.push_str("<td class=\"lane\"></td>"),
+                }
+            }
+
+            // highlight each event in the line, skipping any that overlap
+            let events: Vec<&Event> = self
+                .events
+                .iter()
+                .filter(|e| self.line(e.span.start) == number)
+                .collect();
+            let end_of_line = offset + line.len();
+            let mut code = String::new();
+            let mut at = offset;
+            for event in &events {
+                let end = event.span.end.min(end_of_line);
+                if event.span.start < at || end <= event.span.start {
+                    continue;
+                }
+                code.push_str(&escape(&self.source[at..event.span.start]));
+                code.push_str(&format!(
+                    "<span class=\"{}\" title=\"{}\">{}</span>",
+                    class(&event.kind),
+                    escape(&self.describe(event)),
+                    escape(&self.source[event.span.start..end])
+                ));
+                at = end;
+            }
+            code.push_str(&escape(&self.source[at..end_of_line]));
+            out.push_str(&format!(
+                "<td class=\"code\">{}</td><td class=\"events\">",
+                code
+            ));
+            for event in &events {
+                out.push_str(&format!(
+                    "<div class=\"{}\">{}</div>",
+                    class(&event.kind),
+                    escape(&self.describe(event))
+                ));
+            }
+            out.push_str("</td></tr>\n");
+            offset = end_of_line + 1;
+        }
+        out.push_str("</table>\n</body>\n</html>\n");
+        return out;
+    }
+
+    fn describe(&self, event: &Event) -> String {
+        match &event.kind {
+            EventKind::Move(variable) => format!("move out of {}", variable),
+            EventKind::Copy(variable) => format!("copy {}", variable),
+            EventKind::Borrow { .. } => {
+                let borrow = self
+                    .borrows
+                    .iter()
+                    .find(|b| (b.span.start, b.span.end) == (event.span.start, event.span.end));
+                return borrow.map_or(String::new(), |b| self.range(b));
+            }
+            EventKind::AssignDrop(variable) => format!("drop the old value of {}", variable),
+            EventKind::ScopeDrop {
+                function,
+                variables,
+            } => format!("end of {} drops {}", function, variables.join(", ")),
+        }
+    }
+
+    // what a borrow is of, who holds it and how long it lives
+    fn range(&self, borrow: &Borrow) -> String {
+        let kind = self.events.iter().find_map(|e| match &e.kind {
+            EventKind::Borrow {
+                mutable, reborrow, ..
+            } if (e.span.start, e.span.end) == (borrow.span.start, borrow.span.end) => {
+                Some((*mutable, *reborrow))
+            }
+            _ => None,
+        });
+        let (mutable, reborrow) = kind.unwrap_or_default();
+        let mut text = format!(
+            "{}{} {}",
+            if mutable { "mutably " } else { "" },
+            if reborrow { "reborrow" } else { "borrow" },
+            borrow.variable
+        );
+        if !borrow.holders.is_empty() {
+            text.push_str(&format!(", held by {}", borrow.holders.join(", ")));
+        }
+        match borrow.end {
+            Some(end) if end == borrow.start => text.push_str(", live on this line"),
+            Some(end) => text.push_str(&format!(", live until line {}", end)),
+            None => text.push_str(", live until the end"),
+        }
+        return text;
+    }
+
+    // give each borrow that lives across lines a lane, reusing lanes that are free
+    fn lanes(&self, lines: usize) -> Vec<Lane> {
+        let mut lanes: Vec<Lane> = vec![];
+        for (i, borrow) in self.borrows.iter().enumerate() {
+            let (end, ends) = match borrow.end {
+                Some(end) => (end, true),
+                None => (lines, false),
+            };
+            if end <= borrow.start {
+                continue;
+            }
+            let range = (borrow.start, end, ends, i);
+            match lanes
+                .iter_mut()
+                .find(|lane| lane.iter().all(|b| b.1 < borrow.start))
+            {
+                Some(lane) => lane.push(range),
+                None => lanes.push(vec![range]),
+            }
+        }
+        return lanes;
+    }
+
+    // the line a byte offset is on, counting from 1
+    fn line(&self, offset: usize) -> usize {
+        let offset = offset.min(self.source.len());
+        return self.source[..offset].matches('\n').count() + 1;
+    }
+}
+
+// the arguments of the function bound as `name`
+fn arguments(gamma: &TypeEnviroment, name: &str) -> Vec<Argument> {
+    match gamma.get(&name.to_string()) {
+        Ok(Slot {
+            value: Type::Function { args, .. },
+            ..
+        }) => args,
+        _ => vec![],
+    }
+}
+
+// the variables whose type holds the borrow made at `span`
+fn holders(gamma: &TypeEnviroment, span: Span) -> Vec<String> {
+    let mut holders = vec![];
+    for (name, slot) in gamma.bindings() {
+        let mut t = &slot.value;
+        while let Type::Box(inner) = t {
+            t = inner.as_ref();
+        }
+        if let Type::Reference { var, .. } = t {
+            let site = var.span();
+            if !name.contains('-') && (site.start, site.end) == (span.start, span.end) {
+                holders.push(name.clone());
+            }
+        }
+    }
+    return holders;
+}
+
+const STYLE: &str = "<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+td { padding: 0 0.6em; vertical-align: top; }
+td.number { color: #999; text-align: right; font-family: monospace; }
+td.code { font-family: monospace; white-space: pre; }
+td.events { font-size: 0.85em; }
+td.events div { display: inline-block; margin-right: 0.5em; padding: 0 0.3em; }
+td.lane { padding: 0; width: 0.5em; }
+td.live { border-left: 3px solid; }
+td.start { border-top: 3px solid; }
+.lane-0 { border-color: #3c78d8; } .lane-1 { border-color: #6aa84f; } .lane-2 { border-color: #e69138; }
+.lane-3 { border-color: #8e7cc3; } .lane-4 { border-color: #cc4125; } .lane-5 { border-color: #45818e; }
+.move { background: #f4cccc; } .copy { background: #eeeeee; } .borrow { background: #cfe2f3; }
+.reborrow { background: #d9d2e9; } .drop { background: #fce5cd; }
+.legend span { padding: 0 0.3em; }
+</style>
+";
+
+fn class(kind: &EventKind) -> &'static str {
+    match kind {
+        EventKind::Move(_) => "move",
+        EventKind::Copy(_) => "copy",
+        EventKind::Borrow { reborrow: true, .. } => "reborrow",
+        EventKind::Borrow { .. } => "borrow",
+        EventKind::AssignDrop(_) | EventKind::ScopeDrop { .. } => "drop",
+    }
+}
+
+fn escape(text: &str) -> String {
+    return text
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;");
+}
@@ -1,6 +1,11 @@
+use std::fmt::Display;
+
+use crate::span::Span;
+use crate::typing::IntType;
+
 #[derive(Debug, PartialEq, Clone)]
-pub enum Token {
-    NumericLiteral(i64),
+pub enum TokenKind {
+    NumericLiteral(i128, Option<IntType>),
     Identifier(String),
     Box,
     Ref,
@@ -8,12 +13,81 @@ pub enum Token {
     Let,
     Mut,
     Assign,
+    Plus,
+    Minus,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    BoolLiteral(bool),
+    Assert,
+    Print,
     Fn,
+    Trait,
+    Impl,
+    For,
+    Dot,
     LParen,
     RParen,
     LCurl,
     RCurl,
     Comma,
     Colon,
-    EOF,
+    Eof,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+// a `//` or `/* */` comment, with its delimiters
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
+
+// tokens as they are written in source, used to say what was found in parse errors
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let text = match self {
+            TokenKind::NumericLiteral(n, None) => return write!(f, "literal `{}`", n),
+            TokenKind::NumericLiteral(n, Some(ty)) => return write!(f, "literal `{}{}`", n, ty),
+            TokenKind::Identifier(s) => return write!(f, "identifier `{}`", s),
+            TokenKind::BoolLiteral(b) => return write!(f, "literal `{}`", b),
+            TokenKind::Box => "box",
+            TokenKind::Ref => "ref",
+            TokenKind::Deref => "*",
+            TokenKind::Let => "let",
+            TokenKind::Mut => "mut",
+            TokenKind::Assign => "=",
+            TokenKind::Plus => "+",
+            TokenKind::Minus => "-",
+            TokenKind::Eq => "==",
+            TokenKind::Ne => "!=",
+            TokenKind::Lt => "<",
+            TokenKind::Gt => ">",
+            TokenKind::Le => "<=",
+            TokenKind::Ge => ">=",
+            TokenKind::Assert => "assert",
+            TokenKind::Print => "print",
+            TokenKind::Fn => "fn",
+            TokenKind::Trait => "trait",
+            TokenKind::Impl => "impl",
+            TokenKind::For => "for",
+            TokenKind::Dot => ".",
+            TokenKind::LParen => "(",
+            TokenKind::RParen => ")",
+            TokenKind::LCurl => "{",
+            TokenKind::RCurl => "}",
+            TokenKind::Comma => ",",
+            TokenKind::Colon => ":",
+            TokenKind::Eof => return write!(f, "end of file"),
+        };
+        write!(f, "`{}`", text)
+    }
 }
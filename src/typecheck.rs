@@ -1,9 +1,11 @@
-use crate::constants::TypeError;
+use crate::constants::{Edit, Error, ErrorKind, RuntimeError, TypeError};
+use crate::log::{debug, trace};
 use crate::{
-    ast::{Argument, LVal, Program, Term, Value},
+    ast::{Argument, BinOp, LVal, Program, Term, Value},
     typing::{
-        dom, move_var, read_prohibited, shape_compatible, write, write_prohibited, Slot, Type,
-        TypeEnviroment, _mut,
+        _mut, borrow_sites, dom, immutable_binding, method_function_name, move_var,
+        read_prohibited, shape_compatible, write, write_prohibited, IntType, Method, Slot, Type,
+        TypeEnviroment,
     },
 };
 pub trait TypeCheck {
@@ -11,7 +13,7 @@ pub trait TypeCheck {
         &mut self,
         gamma: TypeEnviroment,
         lifetime: usize,
-    ) -> Result<(TypeEnviroment, Type), String>;
+    ) -> Result<(TypeEnviroment, Type), Error>;
 }
 
 impl TypeCheck for Value {
@@ -19,39 +21,77 @@ impl TypeCheck for Value {
         &mut self,
         gamma: TypeEnviroment,
         _lifetime: usize,
-    ) -> Result<(TypeEnviroment, Type), String> {
+    ) -> Result<(TypeEnviroment, Type), Error> {
         match self {
-            Value::NumericLiteral(_) => return Ok((gamma, Type::Numeric)),
-            _ => return Err("Invalid value".to_string()),
+            Value::NumericLiteral(n, ty) => {
+                let ty = ty.unwrap_or(IntType::I64);
+                if !ty.contains(*n) {
+                    return Err(TypeError::LiteralOutOfRange(*n, ty).into());
+                }
+                return Ok((gamma, Type::Numeric(ty)));
+            }
+            Value::Bool(_) => return Ok((gamma, Type::Bool)),
+            // values like references only exist while a program runs
+            _ => {
+                return Err(RuntimeError::InvariantViolated(format!(
+                    "Value {} cannot appear in a checked term",
+                    self
+                ))
+                .into())
+            }
         }
     }
 }
 
 impl TypeCheck for LVal {
     fn type_check(
+        &mut self,
+        gamma: TypeEnviroment,
+        lifetime: usize,
+    ) -> Result<(TypeEnviroment, Type), Error> {
+        let span = self.span();
+        return self.check(gamma, lifetime).map_err(|e| e.or_at(Some(span)));
+    }
+}
+
+impl LVal {
+    fn check(
         &mut self,
         gamma: TypeEnviroment,
         _lifetime: usize,
-    ) -> Result<(TypeEnviroment, Type), String> {
+    ) -> Result<(TypeEnviroment, Type), Error> {
         match self {
             LVal::Variable { name, .. } => {
-                let Slot { value: t, .. } = gamma.get(&name)?;
+                let Slot { value: t, .. } = match gamma.get(name) {
+                    Ok(slot) => slot,
+                    Err(e) => {
+                        let e = Error::from(e);
+                        return match gamma.moved_at(name) {
+                            Some(span) => {
+                                Err(e.with_label(span, "value moved here").with_suggestion(
+                                    format!("borrow with `ref {}` instead of moving", name),
+                                    vec![Edit {
+                                        span,
+                                        replacement: format!("ref {}", name),
+                                    }],
+                                ))
+                            }
+                            None => Err(e),
+                        };
+                    }
+                };
                 return Ok((gamma, t.clone()));
             }
-            LVal::Deref { var } => {
-                let Slot { value: t, .. } = gamma.get(&var.get_name())?;
+            LVal::Deref { var, .. } => {
+                // dereference the type of the inner lvalue, so **x is dereferenced twice
+                let (gamma, t) = var.type_check(gamma, _lifetime)?;
                 match t.clone() {
-                    Type::Reference { var: v, .. } => {
-                        let Slot { value: t3, .. } = gamma.get(&v.get_name())?;
-                        return Ok((gamma, t3));
+                    Type::Reference { var: mut v, .. } => {
+                        return v.type_check(gamma, _lifetime);
                     }
                     Type::Box(tb) => return Ok((gamma, *tb.clone())),
-                    _ => {
-                        return Err(format!(
-                            "Error type-checking dereference:  variable {:?} is not a reference",
-                            var
-                        ))
-                    }
+                    Type::Error => return Ok((gamma, Type::Error)),
+                    _ => return Err(TypeError::NotAReference(var.to_string()).into()),
                 }
             }
         }
@@ -63,7 +103,7 @@ impl TypeCheck for Program {
         &mut self,
         gamma: TypeEnviroment,
         lifetime: usize,
-    ) -> Result<(TypeEnviroment, Type), String> {
+    ) -> Result<(TypeEnviroment, Type), Error> {
         let mut gamma = gamma;
         let mut t = Type::Epsilon;
         for term in &mut self.terms {
@@ -75,46 +115,158 @@ impl TypeCheck for Program {
     }
 }
 
+impl Program {
+    // type check every statement, reporting all errors rather than the first. A
+    // statement that fails leaves the environment as it was, except that whatever
+    // it declares is bound anyway, poisoned, so later uses do not fail as well
+    pub fn check_all(
+        &mut self,
+        gamma: TypeEnviroment,
+        lifetime: usize,
+    ) -> Result<TypeEnviroment, Vec<Error>> {
+        let mut gamma = gamma;
+        let mut errors = vec![];
+        for term in &mut self.terms {
+            match term.type_check(gamma.clone(), lifetime) {
+                Ok((g, _)) => gamma = g,
+                Err(e) => {
+                    errors.push(e);
+                    poison(&mut gamma, term, lifetime);
+                }
+            }
+        }
+        if errors.is_empty() {
+            return Ok(gamma);
+        }
+        return Err(errors);
+    }
+}
+
+// bind what a failed statement declares, so it is reported once and not at every use
+fn poison(gamma: &mut TypeEnviroment, term: &Term, lifetime: usize) {
+    match term {
+        Term::Let { variable, .. } if !dom(gamma).contains(&variable.get_name()) => {
+            gamma.insert(variable.get_name(), Type::Error, lifetime);
+        }
+        Term::FunctionDeclaration { name, args, ty, .. } if !dom(gamma).contains(name) => {
+            gamma.insert(
+                name.clone(),
+                Type::Function {
+                    args: args.clone(),
+                    ret: ty.clone().map(Box::new),
+                },
+                lifetime,
+            );
+        }
+        _ => {}
+    }
+}
+
+// the environments just before and just after the innermost statement around
+// `offset`, checking the statements before it as check_all would, so tools can ask
+// what was in scope at a point in the source
+pub(crate) fn scope_at(
+    terms: &[Term],
+    gamma: TypeEnviroment,
+    offset: usize,
+    lifetime: usize,
+) -> (TypeEnviroment, TypeEnviroment) {
+    let mut gamma = gamma;
+    for term in terms {
+        let span = term.span().unwrap_or_default();
+        if offset < span.start {
+            break;
+        }
+        let before = gamma.clone();
+        match term.clone().type_check(gamma.clone(), lifetime) {
+            Ok((g, _)) => gamma = g,
+            Err(_) => poison(&mut gamma, term, lifetime),
+        }
+        if offset > span.end {
+            continue;
+        }
+        // where a declaration starts is before it, not inside its body
+        if offset == span.start {
+            return (before, gamma);
+        }
+        match term {
+            Term::FunctionDeclaration {
+                name,
+                args,
+                body,
+                ty,
+                ..
+            } => {
+                let (_, g_block) = function_scope(before, name, args, ty, lifetime);
+                return scope_at(body, g_block, offset, lifetime + 1);
+            }
+            Term::Impl {
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => {
+                let mut g = before;
+                register_methods(&mut g, trait_name, ty, methods);
+                for method in methods {
+                    let Term::FunctionDeclaration {
+                        name,
+                        args,
+                        body,
+                        ty: ret,
+                        span,
+                    } = method
+                    else {
+                        continue;
+                    };
+                    let function = method_function_name(ty, trait_name.as_deref(), name);
+                    let (g2, g_block) = function_scope(g, &function, args, ret, lifetime);
+                    if span.start <= offset && offset <= span.end {
+                        return scope_at(body, g_block, offset, lifetime + 1);
+                    }
+                    g = g2;
+                }
+                return (g.clone(), g);
+            }
+            _ => return (before, gamma),
+        }
+    }
+    return (gamma.clone(), gamma);
+}
+
 impl TypeCheck for Term {
     fn type_check(
         &mut self,
         gamma: TypeEnviroment,
         lifetime: usize,
-    ) -> Result<(TypeEnviroment, Type), String> {
+    ) -> Result<(TypeEnviroment, Type), Error> {
+        let span = self.span();
+        return self.check(gamma, lifetime).map_err(|e| e.or_at(span));
+    }
+}
+
+impl Term {
+    fn check(
+        &mut self,
+        gamma: TypeEnviroment,
+        lifetime: usize,
+    ) -> Result<(TypeEnviroment, Type), Error> {
         match self {
-            Term::FunctionCall { name, params } => {
+            Term::FunctionCall { name, params, .. } => {
                 // get the function type from the type environment
                 let Ok(Slot { value, .. }) = gamma.get(name) else {
-                    return Err(TypeError::FunctionNotDefined(name.clone()).to_string());
+                    return Err(TypeError::FunctionNotDefined(name.clone()).into());
                 };
                 match value {
                     Type::Function { args, ret } => {
-                        if args.len() != params.len() {
-                            return Err(TypeError::FunctionCallIncompatableArgumentCount(
-                                args.len(),
-                                params.len(),
-                            )
-                            .to_string());
-                        };
-                        let mut g1 = gamma;
-                        for (arg, param) in args.iter().zip(params.iter_mut()) {
-                            let (g2, t) = param.type_check(g1, lifetime)?;
-                            if !shape_compatible(&g2, &arg.clone(), &t.clone()) {
-                                return Err(TypeError::FunctionCallIncompatableArgumentType(
-                                    arg.clone(),
-                                    t.clone(),
-                                )
-                                .to_string());
-                            };
-                            g1 = g2;
-                        }
+                        let g1 = type_check_params(gamma, &args, params, lifetime)?;
 
                         match ret {
                             Some(t) => return Ok((g1, *t.clone())),
                             None => return Ok((g1, Type::Epsilon)),
                         }
                     }
-                    _ => return Err(TypeError::FunctionNotDefined(name.clone()).to_string()),
+                    _ => return Err(TypeError::FunctionNotDefined(name.clone()).into()),
                 }
             }
             Term::FunctionDeclaration {
@@ -122,206 +274,693 @@ impl TypeCheck for Term {
                 args,
                 body,
                 ty,
+                ..
             } => {
-                // add function to type environment
-                let mut g2 = gamma;
-
-                let arg_names: Vec<String> = args.iter().map(|arg| arg.name.clone()).collect();
-                // check for duplicate argument names
-                for (i, arg) in arg_names.iter().enumerate() {
-                    if arg_names[i + 1..].contains(arg) {
-                        return Err(TypeError::FunctionDeclDupArg(arg.clone()).to_string());
+                return type_check_function(gamma, fn_name, args, body, ty, lifetime);
+            }
+            Term::TraitDeclaration { name, methods, .. } => {
+                if gamma.get_trait(name).is_some() {
+                    return Err(TypeError::TraitAlreadyDefined(name.clone()).into());
+                }
+                for method in methods.iter() {
+                    if method.receiver().is_none() {
+                        return Err(TypeError::MethodMissingSelf(method.name.clone()).into());
                     }
                 }
-
-                let arg_types = args.iter().map(|arg| arg.ty.clone()).collect();
-                g2.insert(
-                    fn_name.clone(),
-                    Type::Function {
-                        args: arg_types,
-                        ret: ty.clone().map(Box::new),
-                    },
-                    lifetime,
-                );
-
-                let mut g_block = g2.clone();
-
-                // add arguments to the type environment
-                for arg in args {
-                    match arg {
-                        Argument {
-                            name,
-                            ty,
-                            mutable: false,
-                            reference: false,
-                        } => {
-                            g_block.insert(name.clone(), ty.clone(), lifetime);
-                        }
-                        Argument {
-                            name,
-                            ty,
-                            mutable: true,
-                            reference: false,
-                        } => {
-                            g_block.insert(name.clone(), ty.clone(), lifetime);
-                        }
-                        Argument {
-                            name,
-                            ty,
-                            mutable,
-                            reference: true,
-                        } => {
-                            g_block.insert(format!("{}-{}", fn_name, name), ty.clone(), lifetime);
-                            g_block.insert(
-                                name.clone(),
-                                Type::Reference {
-                                    mutable: mutable.clone(),
-                                    var: LVal::Variable {
-                                        name: format!("{}-{}", fn_name, name),
-                                        copyable: Some(ty.copyable()),
-                                    },
-                                },
-                                lifetime,
-                            );
+                let mut g = gamma;
+                g.insert_trait(name.clone(), methods.clone());
+                return Ok((g, Type::Epsilon));
+            }
+            Term::Impl {
+                trait_name,
+                ty,
+                methods,
+                ..
+            } => {
+                let mut g = gamma;
+                match trait_name {
+                    Some(trait_name) => check_trait_impl(&g, trait_name, ty, methods)?,
+                    None => {
+                        for method in methods.iter() {
+                            let Term::FunctionDeclaration { name, args, .. } = method else {
+                                panic!("Impl blocks should only contain function declarations");
+                            };
+                            if args.first().is_none_or(|arg| arg.name != "self") {
+                                return Err(TypeError::MethodMissingSelf(name.clone()).into());
+                            }
+                            let inherent = g.lookup_methods(ty, name);
+                            if inherent.iter().any(|m| m.trait_name.is_none()) {
+                                return Err(TypeError::MethodAlreadyDefined(
+                                    ty.clone(),
+                                    name.clone(),
+                                )
+                                .into());
+                            }
                         }
                     }
                 }
 
-                // type check the body of the function
-                let mut t = Type::Epsilon;
-                for term in body {
-                    let (g_block_2, t3) = term.type_check(g_block, lifetime + 1)?;
-                    g_block = g_block_2;
-                    t = t3;
-                }
+                // register before checking bodies so methods can call each other
+                register_methods(&mut g, trait_name, ty, methods);
 
-                // check that the return type of the function matches the type of the body
-                match ty {
-                    Some(ty) => {
-                        if t != *ty {
-                            return Err(TypeError::FunctionUnexpectedReturn(t.clone(), ty.clone())
-                                .to_string());
-                        }
+                for method in methods.iter_mut() {
+                    if let Term::FunctionDeclaration {
+                        name,
+                        args,
+                        body,
+                        ty: ret,
+                        ..
+                    } = method
+                    {
+                        let function = method_function_name(ty, trait_name.as_deref(), name);
+                        let (g2, _) = type_check_function(g, &function, args, body, ret, lifetime)?;
+                        g = g2;
                     }
-                    None => {}
                 }
+                return Ok((g, Type::Epsilon));
+            }
+            Term::MethodCall {
+                receiver,
+                method,
+                params,
+                function,
+                ..
+            } => {
+                // dispatch statically on the type of the receiver, inherent methods
+                // take priority over trait methods
+                let (g, t) = receiver.type_check(gamma, lifetime)?;
+                if t == Type::Error {
+                    return Ok((g, Type::Error));
+                }
+                let candidates = g.lookup_methods(&t, method);
+                let inherent: Vec<_> = candidates
+                    .iter()
+                    .filter(|m| m.trait_name.is_none())
+                    .collect();
+                let resolved = match (inherent.as_slice(), candidates.as_slice()) {
+                    ([m], _) => (**m).clone(),
+                    (_, []) => return Err(TypeError::MethodNotDefined(t, method.clone()).into()),
+                    (_, [m]) => (*m).clone(),
+                    _ => return Err(TypeError::MethodAmbiguous(t, method.clone()).into()),
+                };
+                let name = resolved.function_name();
+                *function = Some(name.clone());
+                let Ok(Slot {
+                    value: Type::Function { args, ret },
+                    ..
+                }) = g.get(&name)
+                else {
+                    return Err(TypeError::FunctionNotDefined(name).into());
+                };
+
+                // the receiver is passed as self, by value it is copied or moved like
+                // any other argument, by reference it is borrowed for the call like `ref x`
+                // or `mut ref x` would be, so conflicting borrows are rejected, in the
+                // arguments too
+                let (g1, borrow) = if resolved.receiver.reference {
+                    let mut borrow = Term::Ref {
+                        mutable: resolved.receiver.mutable,
+                        var: receiver.clone(),
+                        span: receiver.span(),
+                    };
+                    let (mut g1, t) = borrow.type_check(g, lifetime)?;
+                    let borrow = g1.fresh("borrow");
+                    g1.insert(borrow.clone(), t, lifetime);
+                    (g1, Some(borrow))
+                } else {
+                    let mut self_param = Term::Variable(receiver.clone());
+                    let (g1, _) = self_param.type_check(g, lifetime)?;
+                    if let Term::Variable(r) = self_param {
+                        *receiver = r;
+                    }
+                    (g1, None)
+                };
 
-                return Ok((g2, Type::Epsilon));
+                let mut g2 = type_check_params(g1, &args[1..], params, lifetime)?;
+                // the receiver borrow ends when the call returns
+                if let Some(borrow) = borrow {
+                    g2.remove(&borrow);
+                }
+                match ret {
+                    Some(t) => return Ok((g2, *t)),
+                    None => return Ok((g2, Type::Epsilon)),
+                }
             }
             Term::Variable(ref mut var) => {
-                let (g, t) = var.type_check(gamma, lifetime)?;
+                let (g, t) = match var.type_check(gamma, lifetime) {
+                    Ok(checked) => checked,
+                    Err(mut e) => {
+                        // borrowing where it was moved would leave it to be moved here, so
+                        // borrow here as well
+                        let moved = matches!(e.kind, ErrorKind::Type(TypeError::TypeMoved(_)));
+                        if let (true, Some(suggestion)) = (moved, e.suggestions.first_mut()) {
+                            suggestion.edits.push(Edit {
+                                span: var.span(),
+                                replacement: format!("ref {}", var),
+                            });
+                        }
+                        return Err(e);
+                    }
+                };
 
+                // borrows are recorded against the unmarked variable, so check for them first
+                let unmarked = var.clone();
                 var.set_copyable(t.copyable()); // mark the variable as copyable or not for reduction step
 
                 // if the variable is copyable, copy semmantics apply, else we use move semmantics
                 if t.copyable() {
-                    println!(
-                        "Type checking copy of variable: {:?} with type {:?}",
-                        var, t
+                    debug!(
+                        Typeck,
+                        "Type checking copy of variable: {:?} with type {:?}", var, t
                     );
-                    if read_prohibited(&g, var.clone()) {
-                        return Err(TypeError::CopyNotReadable(var.get_name()).to_string());
+                    if read_prohibited(&g, unmarked.clone())? {
+                        let e = TypeError::CopyNotReadable(var.get_name()).into();
+                        return Err(borrowed_here(e, &g, &unmarked, true));
                     };
                     return Ok((g, (t.clone())));
                 } else {
                     // let (g2, t) = var.type_check(g, lifetime)?;
-                    println!(
-                        "Type checking move of variable: {:?} with type {:?}",
-                        var, t
+                    debug!(
+                        Typeck,
+                        "Type checking move of variable: {:?} with type {:?}", var, t
                     );
-                    if write_prohibited(&g, var.clone()) {
-                        return Err(TypeError::MoveNotWritable(var.get_name()).to_string());
+                    if write_prohibited(&g, unmarked.clone())? {
+                        let e = Error::from(TypeError::MoveNotWritable(var.get_name()))
+                            .with_suggestion(
+                                format!("borrow with `ref {}` instead of moving", var),
+                                vec![Edit {
+                                    span: var.span(),
+                                    replacement: format!("ref {}", var),
+                                }],
+                            );
+                        return Err(borrowed_here(e, &g, &unmarked, false));
                     };
                     let g3 = move_var(g, var.clone(), lifetime)?;
-                    println!(
+                    trace!(
+                        Typeck,
                         "Move successfully completed, new type environment: {:?}",
                         g3
                     );
                     return Ok((g3, t));
                 }
             }
-            Term::Value(val) => {
+            Term::Value(val, _) => {
                 let (g, t) = val.type_check(gamma, lifetime)?;
                 return Ok((g, t));
             }
-            Term::Box { term } => {
+            Term::Box { term, .. } => {
                 let (g, t) = term.type_check(gamma, lifetime)?;
                 return Ok((g, Type::Box(Box::new(t))));
             }
-            Term::Ref { mutable, var } => {
+            Term::BinaryOp { op, lhs, rhs, .. } => {
+                // a literal without a suffix takes its type from the other operand
+                let (g2, t1, t2) = match (unsuffixed(lhs), unsuffixed(rhs)) {
+                    (Some(n), None) => {
+                        let (g, t2) = rhs.type_check(gamma, lifetime)?;
+                        let t1 = literal_type(n, &t2).map_err(|e| e.or_at(lhs.span()))?;
+                        (g, t1, t2)
+                    }
+                    (None, Some(n)) => {
+                        let (g, t1) = lhs.type_check(gamma, lifetime)?;
+                        let t2 = literal_type(n, &t1).map_err(|e| e.or_at(rhs.span()))?;
+                        (g, t1, t2)
+                    }
+                    _ => {
+                        let (g1, t1) = lhs.type_check(gamma, lifetime)?;
+                        let (g2, t2) = rhs.type_check(g1, lifetime)?;
+                        (g2, t1, t2)
+                    }
+                };
+                match (op, &t1, &t2) {
+                    (_, Type::Error, _) | (_, _, Type::Error) => return Ok((g2, Type::Error)),
+                    (BinOp::Eq | BinOp::Ne, Type::Bool, Type::Bool) => return Ok((g2, Type::Bool)),
+                    (op, Type::Numeric(k1), Type::Numeric(k2)) if k1 == k2 => {
+                        if op.is_comparison() {
+                            return Ok((g2, Type::Bool));
+                        }
+                        return Ok((g2, t1));
+                    }
+                    _ => return Err(TypeError::IncompatibleTypes(t1, t2).into()),
+                }
+            }
+            Term::Assert { cond, .. } => {
+                let (g, t) = cond.type_check(gamma, lifetime)?;
+                if t != Type::Bool && t != Type::Error {
+                    return Err(TypeError::IncompatibleTypes(Type::Bool, t).into());
+                }
+                return Ok((g, Type::Epsilon));
+            }
+            Term::Print { term, .. } => {
+                // print only reads a variable, so a box printed is still owned afterwards
+                let (g, t) = match term.as_mut() {
+                    Term::Variable(var) => {
+                        let (g, t) = var.type_check(gamma, lifetime)?;
+                        if read_prohibited(&g, var.clone())? {
+                            let e = TypeError::CopyNotReadable(var.get_name()).into();
+                            return Err(borrowed_here(e, &g, var, true).or_at(Some(var.span())));
+                        }
+                        var.set_copyable(true);
+                        (g, t)
+                    }
+                    term => term.type_check(gamma, lifetime)?,
+                };
+                if t == Type::Epsilon {
+                    return Err(TypeError::LetExprNoReturn(term.clone()).into());
+                }
+                return Ok((g, Type::Epsilon));
+            }
+            Term::Ref { mutable, var, span } => {
+                // the reference remembers where it was created, to point at it in errors
                 if *mutable {
-                    if write_prohibited(&gamma, var.clone()) {
-                        return Err(
-                            TypeError::MutrefAlreadyBorrowedImmut(var.get_name()).to_string()
-                        );
+                    if write_prohibited(&gamma, var.clone())? {
+                        let e = TypeError::MutrefAlreadyBorrowedImmut(var.get_name()).into();
+                        return Err(borrowed_here(e, &gamma, var, false));
                     };
-                    if !_mut(&gamma, var.clone()) {
-                        return Err(TypeError::MutrefImmut(var.get_name()).to_string());
+                    if let Some(name) = immutable_binding(&gamma, var) {
+                        return Err(TypeError::NotMutable(name).into());
+                    }
+                    if !_mut(&gamma, var.clone())? {
+                        return Err(TypeError::MutrefImmut(var.get_name()).into());
                     };
                     return Ok((
                         gamma,
                         Type::Reference {
                             mutable: *mutable,
-                            var: var.clone(),
+                            var: var.clone().with_span(*span),
                         },
                     ));
                 } else {
-                    if read_prohibited(&gamma, var.clone()) {
-                        return Err(TypeError::RefAlreadyBorrowedMut(var.get_name()).to_string());
+                    if read_prohibited(&gamma, var.clone())? {
+                        let e = TypeError::RefAlreadyBorrowedMut(var.get_name()).into();
+                        return Err(borrowed_here(e, &gamma, var, true));
                     };
                     return Ok((
                         gamma,
                         Type::Reference {
                             mutable: *mutable,
-                            var: var.clone(),
+                            var: var.clone().with_span(*span),
                         },
                     ));
                 }
             }
-            Term::Let { variable, term, .. } => {
+            Term::Let {
+                mutable,
+                variable,
+                term,
+                ..
+            } => {
                 // check if the variable is already defined
                 // x̸ ∈ dom(Γ1)
                 if dom(&gamma).contains(&variable.get_name()) {
-                    return Err(TypeError::LetAlreadyDefined(variable.get_name()).to_string());
+                    return Err(TypeError::LetAlreadyDefined(variable.get_name()).into());
                 };
 
                 // type check the term
                 // Γ1 ⊢ t : T ⊣ Γ2
                 let (mut g, t) = term.type_check(gamma, lifetime)?;
-                match t {
-                    Type::Epsilon => {
-                        return Err(TypeError::LetExprNoReturn(*term.clone()).to_string())
-                    }
-                    _ => {}
+                if t == Type::Epsilon {
+                    return Err(TypeError::LetExprNoReturn(term.clone()).into());
                 }
 
                 // insert the variable into the type environment
                 // Γ3 = Γ2[x 7 → T ]
                 g.insert(variable.get_name().clone(), t.clone(), lifetime);
+                g.set_mutable(&variable.get_name(), *mutable);
                 return Ok((g, Type::Epsilon));
             }
-            Term::Assign { variable, term } => {
-                println!("Type checking assignment: {:?} = {:?}", variable, term);
+            Term::Assign { variable, term, .. } => {
+                debug!(
+                    Typeck,
+                    "Type checking assignment: {:?} = {:?}", variable, term
+                );
 
                 let (g1, t1) = variable.type_check(gamma, lifetime)?;
+                if let Some(name) = immutable_binding(&g1, variable) {
+                    return Err(TypeError::NotMutable(name).into());
+                }
 
-                let (g2, t2) = term.type_check(g1, lifetime)?;
+                // a literal without a suffix takes the type of what it replaces
+                let (g2, t2) = match unsuffixed(term) {
+                    Some(n) => (g1, literal_type(n, &t1).map_err(|e| e.or_at(term.span()))?),
+                    None => term.type_check(g1, lifetime)?,
+                };
 
                 if !shape_compatible(&g2, &t1, &t2.clone()) {
-                    return Err(TypeError::IncompatibleTypes(t1.clone(), t2.clone()).to_string());
+                    return Err(TypeError::IncompatibleTypes(t1.clone(), t2.clone()).into());
                 };
 
-                if !t2.within(&g2, lifetime) {
-                    return Err(TypeError::NotWithinScope(t2.to_string()).to_string());
+                // the reference must not outlive the variable it is stored in
+                let Slot { lifetime: m, .. } = g2.get_partial(&variable.get_name())?;
+                if !t2.within(&g2, m)? {
+                    return Err(TypeError::NotWithinScope(t2.to_string()).into());
                 }
 
                 let g3 = write(g2, variable.clone(), t2)?;
 
-                if write_prohibited(&g3, variable.clone()) {
-                    return Err(TypeError::AssignBorrowed(variable.get_name()).to_string());
+                if write_prohibited(&g3, variable.clone())? {
+                    let e = TypeError::AssignBorrowed(variable.get_name()).into();
+                    return Err(borrowed_here(e, &g3, variable, false));
                 };
                 return Ok((g3, Type::Epsilon));
             }
         }
     }
 }
+
+// the value of an integer literal written without a suffix
+fn unsuffixed(term: &Term) -> Option<i128> {
+    match term {
+        Term::Value(Value::NumericLiteral(n, None), _) => return Some(*n),
+        _ => return None,
+    }
+}
+
+// the type of an unsuffixed literal used where a value of type `other` goes
+fn literal_type(n: i128, other: &Type) -> Result<Type, Error> {
+    let ty = match other {
+        Type::Numeric(ty) => *ty,
+        _ => IntType::I64,
+    };
+    if !ty.contains(n) {
+        return Err(TypeError::LiteralOutOfRange(n, ty).into());
+    }
+    return Ok(Type::Numeric(ty));
+}
+
+// label every live borrow of `var` that the failed access conflicts with
+fn borrowed_here(e: Error, gamma: &TypeEnviroment, var: &LVal, mutable_only: bool) -> Error {
+    let mut e = e;
+    let kind = if mutable_only { "mutably " } else { "" };
+    for span in borrow_sites(gamma, var.clone(), mutable_only) {
+        e = e.with_label(span, format!("{} is {}borrowed here", var.get_name(), kind));
+    }
+    return e;
+}
+
+// a value moved into a call while it is borrowed could be borrowed by the function
+// instead, which suggests declaring the argument as a reference
+fn declare_ref(e: Error, arg: &Argument, param: &Term) -> Error {
+    let Term::Variable(var) = param else {
+        return e;
+    };
+    if arg.reference
+        || arg.span.line == 0
+        || !matches!(e.kind, ErrorKind::Type(TypeError::MoveNotWritable(_)))
+    {
+        return e;
+    }
+    let declaration = format!(
+        "{}ref {}: {}",
+        if arg.mutable { "mut " } else { "" },
+        arg.name,
+        arg.ty.syntax()
+    );
+    return Error {
+        suggestions: vec![],
+        ..e
+    }
+    .with_suggestion(
+        format!(
+            "declare the argument as `ref {}` and borrow `{}`",
+            arg.name, var
+        ),
+        vec![
+            Edit {
+                span: arg.span,
+                replacement: declaration,
+            },
+            Edit {
+                span: var.span(),
+                replacement: format!("ref {}", var),
+            },
+        ],
+    );
+}
+
+// check that an impl block provides every method of a trait, with Self resolved to `ty`
+fn check_trait_impl(
+    gamma: &TypeEnviroment,
+    trait_name: &str,
+    ty: &Type,
+    methods: &[Term],
+) -> Result<(), Error> {
+    let Some(signatures) = gamma.get_trait(trait_name) else {
+        return Err(TypeError::TraitNotDefined(trait_name.to_string()).into());
+    };
+    if gamma.has_impl(trait_name, ty) {
+        return Err(TypeError::ImplAlreadyDefined(trait_name.to_string(), ty.clone()).into());
+    }
+
+    let mut implemented = vec![];
+    for method in methods.iter() {
+        let Term::FunctionDeclaration {
+            name,
+            args,
+            ty: ret,
+            ..
+        } = method
+        else {
+            panic!("Impl blocks should only contain function declarations");
+        };
+        let Some(signature) = signatures.iter().find(|s| s.name == *name) else {
+            return Err(TypeError::MethodNotInTrait(trait_name.to_string(), name.clone()).into());
+        };
+        let matches = args.len() == signature.args.len()
+            && args.iter().zip(signature.args.iter()).all(|(a, s)| {
+                a.same_kind(&Argument {
+                    ty: s.ty.substitute_self(ty),
+                    ..s.clone()
+                })
+            })
+            && *ret == signature.ty.as_ref().map(|r| r.substitute_self(ty));
+        if !matches {
+            return Err(
+                TypeError::TraitMethodMismatch(trait_name.to_string(), name.clone()).into(),
+            );
+        }
+        implemented.push(name.clone());
+    }
+    for signature in signatures.iter() {
+        if !implemented.contains(&signature.name) {
+            return Err(TypeError::TraitMethodMissing(
+                trait_name.to_string(),
+                signature.name.clone(),
+            )
+            .into());
+        }
+    }
+    return Ok(());
+}
+
+// bind the methods of an impl block to the type it is for
+pub(crate) fn register_methods(
+    gamma: &mut TypeEnviroment,
+    trait_name: &Option<String>,
+    ty: &Type,
+    methods: &[Term],
+) {
+    for method in methods.iter() {
+        // a method without self was already reported
+        let Term::FunctionDeclaration { name, args, .. } = method else {
+            continue;
+        };
+        if let Some(receiver) = args.first() {
+            gamma.insert_method(Method {
+                ty: ty.clone(),
+                trait_name: trait_name.clone(),
+                name: name.clone(),
+                receiver: receiver.clone(),
+            });
+        }
+    }
+}
+
+// type check the parameters of a call against the arguments of the function
+fn type_check_params(
+    gamma: TypeEnviroment,
+    args: &[Argument],
+    params: &mut [Term],
+    lifetime: usize,
+) -> Result<TypeEnviroment, Error> {
+    if args.len() != params.len() {
+        return Err(
+            TypeError::FunctionCallIncompatableArgumentCount(args.len(), params.len()).into(),
+        );
+    };
+    let mut g1 = gamma;
+    // borrows made explicitly at the call site last until every parameter is checked
+    let mut borrows = vec![];
+    for (arg, param) in args.iter().zip(params.iter_mut()) {
+        match param {
+            Term::Ref { mutable, var, .. } if arg.reference => {
+                if *mutable != arg.mutable {
+                    return Err(
+                        TypeError::ExplicitBorrowRequired(arg.name.clone(), arg.mutable).into(),
+                    );
+                }
+                let mut borrowed = var.clone();
+                let (mut g2, t) = param.type_check(g1, lifetime)?;
+                let (_, target) = borrowed.type_check(g2.clone(), lifetime)?;
+                if !shape_compatible(&g2, &arg.ty, &target) {
+                    return Err(TypeError::FunctionCallIncompatableArgumentType(
+                        arg.ty.clone(),
+                        target,
+                    )
+                    .into());
+                };
+                let borrow = g2.fresh("borrow");
+                g2.insert(borrow.clone(), t, lifetime);
+                borrows.push(borrow);
+                g1 = g2;
+            }
+            _ => {
+                if arg.reference && g1.explicit_borrows() {
+                    return Err(
+                        TypeError::ExplicitBorrowRequired(arg.name.clone(), arg.mutable).into(),
+                    );
+                }
+                // a literal without a suffix takes the type of the argument
+                let checked = match unsuffixed(param) {
+                    Some(n) => literal_type(n, &arg.ty)
+                        .map(|t| (g1, t))
+                        .map_err(|e| e.or_at(param.span())),
+                    None => param.type_check(g1, lifetime),
+                };
+                let (g2, t) = match checked {
+                    Ok(checked) => checked,
+                    Err(e) => return Err(declare_ref(e, arg, param)),
+                };
+                if !shape_compatible(&g2, &arg.ty, &t) {
+                    let e = Error::from(TypeError::FunctionCallIncompatableArgumentType(
+                        arg.ty.clone(),
+                        t.clone(),
+                    ));
+                    return Err(match (&arg.ty, param.span()) {
+                        (Type::Box(inner), Some(span)) if shape_compatible(&g2, inner, &t) => e
+                            .with_suggestion(
+                                "wrap in `box`",
+                                vec![Edit {
+                                    span: span.shrink_to_lo(),
+                                    replacement: "box ".to_string(),
+                                }],
+                            ),
+                        _ => e,
+                    });
+                };
+                g1 = g2;
+            }
+        }
+    }
+    // the borrows end when the call returns
+    for borrow in borrows {
+        g1.remove(&borrow);
+    }
+    return Ok(g1);
+}
+
+// type check a function declaration, binding its type under `fn_name`
+fn type_check_function(
+    gamma: TypeEnviroment,
+    fn_name: &String,
+    args: &[Argument],
+    body: &mut [Term],
+    ty: &Option<Type>,
+    lifetime: usize,
+) -> Result<(TypeEnviroment, Type), Error> {
+    // Self can only be resolved inside an impl block
+    if args.iter().any(|arg| arg.ty.mentions_self())
+        || ty.as_ref().is_some_and(|ty| ty.mentions_self())
+    {
+        return Err(TypeError::SelfOutsideImpl(fn_name.clone()).into());
+    }
+
+    let arg_names: Vec<String> = args.iter().map(|arg| arg.name.clone()).collect();
+    // check for duplicate argument names
+    for (i, arg) in arg_names.iter().enumerate() {
+        if arg_names[i + 1..].contains(arg) {
+            return Err(TypeError::FunctionDeclDupArg(arg.clone()).into());
+        }
+    }
+
+    let (g2, mut g_block) = function_scope(gamma, fn_name, args, ty, lifetime);
+
+    // type check the body of the function
+    let mut t = Type::Epsilon;
+    for term in body {
+        let (g_block_2, t3) = term.type_check(g_block, lifetime + 1)?;
+        g_block = g_block_2;
+        t = t3;
+    }
+
+    // check that the return type of the function matches the type of the body
+    if let Some(ty) = ty {
+        if t != *ty && t != Type::Error {
+            return Err(TypeError::FunctionUnexpectedReturn(t.clone(), ty.clone()).into());
+        }
+    }
+
+    return Ok((g2, Type::Epsilon));
+}
+
+// bind a function under `fn_name`, returning the environment after its declaration
+// and the one its body starts in, with the arguments bound
+pub(crate) fn function_scope(
+    gamma: TypeEnviroment,
+    fn_name: &String,
+    args: &[Argument],
+    ty: &Option<Type>,
+    lifetime: usize,
+) -> (TypeEnviroment, TypeEnviroment) {
+    // add function to type environment
+    let mut g2 = gamma;
+    g2.insert(
+        fn_name.clone(),
+        Type::Function {
+            args: args.to_vec(),
+            ret: ty.clone().map(Box::new),
+        },
+        lifetime,
+    );
+
+    let mut g_block = g2.clone();
+
+    // add arguments to the type environment
+    for arg in args {
+        match arg {
+            Argument {
+                name,
+                ty,
+                mutable,
+                reference: false,
+                ..
+            } => {
+                g_block.insert(name.clone(), ty.clone(), lifetime);
+                g_block.set_mutable(name, *mutable);
+            }
+            Argument {
+                name,
+                ty,
+                mutable,
+                reference: true,
+                span,
+            } => {
+                g_block.insert(format!("{}-{}", fn_name, name), ty.clone(), lifetime);
+                g_block.set_mutable(name, true);
+                g_block.insert(
+                    name.clone(),
+                    Type::Reference {
+                        mutable: *mutable,
+                        var: LVal::Variable {
+                            name: format!("{}-{}", fn_name, name),
+                            copyable: Some(ty.copyable()),
+                            span: *span,
+                        },
+                    },
+                    lifetime,
+                );
+            }
+        }
+    }
+
+    return (g2, g_block);
+}
@@ -1,8 +1,13 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
 
 use crate::{
-    ast::LVal,
+    ast::{Argument, LVal, MethodSignature},
     constants::TypeError,
+    log::trace,
+    span::Span,
 };
 
 #[derive(Debug, Clone)]
@@ -14,10 +19,68 @@ pub struct Slot<T> {
 type Lifetime = usize;
 type Variable = String;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntType {
+    I8,
+    I32,
+    I64,
+    U8,
+    U64,
+}
+
+impl IntType {
+    pub fn from_name(name: &str) -> Option<IntType> {
+        match name {
+            "i8" => Some(IntType::I8),
+            "i32" => Some(IntType::I32),
+            "i64" | "int" => Some(IntType::I64),
+            "u8" => Some(IntType::U8),
+            "u64" => Some(IntType::U64),
+            _ => None,
+        }
+    }
+
+    pub fn min(&self) -> i128 {
+        match self {
+            IntType::I8 => i8::MIN as i128,
+            IntType::I32 => i32::MIN as i128,
+            IntType::I64 => i64::MIN as i128,
+            IntType::U8 | IntType::U64 => 0,
+        }
+    }
+
+    pub fn max(&self) -> i128 {
+        match self {
+            IntType::I8 => i8::MAX as i128,
+            IntType::I32 => i32::MAX as i128,
+            IntType::I64 => i64::MAX as i128,
+            IntType::U8 => u8::MAX as i128,
+            IntType::U64 => u64::MAX as i128,
+        }
+    }
+
+    pub fn contains(&self, n: i128) -> bool {
+        self.min() <= n && n <= self.max()
+    }
+}
+
+impl Display for IntType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntType::I8 => write!(f, "i8"),
+            IntType::I32 => write!(f, "i32"),
+            IntType::I64 => write!(f, "i64"),
+            IntType::U8 => write!(f, "u8"),
+            IntType::U64 => write!(f, "u64"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Type {
     Epsilon,
-    Numeric,
+    Numeric(IntType),
+    Bool,
     Reference {
         var: LVal,
         mutable: bool,
@@ -25,9 +88,11 @@ pub enum Type {
     Box(Box<Type>),
     Undefined(Box<Type>),
     Function {
-        args: Vec<Type>,
+        args: Vec<Argument>,
         ret: Option<Box<Type>>,
     },
+    SelfAlias,
+    Error, // a binding whose definition failed to check, any use of it is accepted
 }
 
 impl Type {
@@ -39,91 +104,237 @@ impl Type {
         }
     }
 
-    pub fn within(&self, gamma: &TypeEnviroment, lifetime: Lifetime) -> bool {
+    pub fn mentions_self(&self) -> bool {
+        match self {
+            Type::SelfAlias => true,
+            Type::Box(t) | Type::Undefined(t) => t.mentions_self(),
+            _ => false,
+        }
+    }
+
+    // replace Self with the type an impl block is for
+    pub fn substitute_self(&self, ty: &Type) -> Type {
+        match self {
+            Type::SelfAlias => ty.clone(),
+            Type::Box(t) => Type::Box(Box::new(t.substitute_self(ty))),
+            Type::Undefined(t) => Type::Undefined(Box::new(t.substitute_self(ty))),
+            _ => self.clone(),
+        }
+    }
+
+    // the type as it is written in muse source, e.g. in argument annotations
+    pub fn syntax(&self) -> String {
+        match self {
+            Type::Numeric(IntType::I64) => "int".to_string(),
+            Type::Numeric(k) => k.to_string(),
+            Type::Bool => "bool".to_string(),
+            Type::Box(t) => format!("box {}", t.syntax()),
+            Type::SelfAlias => "Self".to_string(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// The type as the user would think of it, with what a reference borrows and
+    /// whether a value was moved out.
+    pub fn describe(&self) -> String {
+        match self {
+            Type::Reference { var, mutable } => {
+                format!("{}ref {}", if *mutable { "mut " } else { "" }, var)
+            }
+            Type::Box(t) => format!("box {}", t.describe()),
+            Type::Undefined(t) => format!("{} (moved)", t.describe()),
+            Type::Function { args, ret } => {
+                let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+                match ret {
+                    Some(ret) => format!("fn({}) : {}", args.join(", "), ret.describe()),
+                    None => format!("fn({})", args.join(", ")),
+                }
+            }
+            _ => self.syntax(),
+        }
+    }
+
+    pub fn within(&self, gamma: &TypeEnviroment, lifetime: Lifetime) -> Result<bool, TypeError> {
         match self {
             Type::Reference { var, .. } => {
-                let Slot { lifetime: l, .. } = gamma.get(&var.get_name()).unwrap();
-                return l <= lifetime;
+                let Slot { lifetime: l, .. } = gamma.get(&var.get_name())?;
+                return Ok(l <= lifetime);
             }
             Type::Box(t) => t.within(gamma, lifetime),
-            _ => true,
+            _ => Ok(true),
         }
     }
+}
 
-    pub fn to_string(&self) -> String {
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Type::Epsilon => "Epsilon".to_string(),
-            Type::Numeric => "Numeric".to_string(),
+            Type::Epsilon => write!(f, "Epsilon"),
+            Type::Numeric(k) => write!(f, "Numeric {}", k),
+            Type::Bool => write!(f, "Bool"),
             Type::Reference { var, mutable } => {
-                format!(
+                write!(
+                    f,
                     "{}Ref {}",
                     if *mutable { " Mut" } else { "" },
                     var.get_name()
                 )
             }
-            Type::Box(t) => {
-                format!("Box {}", t.to_string())
-            }
-            Type::Undefined(t) => {
-                format!("Undefined {}", t.to_string())
-            }
+            Type::Box(t) => write!(f, "Box {}", t),
+            Type::Undefined(t) => write!(f, "Undefined {}", t),
             Type::Function { args, ret } => {
-                let mut s = "Function (".to_string();
+                write!(f, "Function (")?;
                 for arg in args {
-                    s.push_str(&arg.to_string());
-                    s.push_str(", ");
+                    write!(f, "{}, ", arg.ty)?;
                 }
-                s.push_str(") -> ");
+                write!(f, ") -> ")?;
                 match ret {
-                    Some(t) => {
-                        s.push_str(&t.to_string());
-                    }
-                    None => {
-                        s.push_str("None");
-                    }
+                    Some(t) => write!(f, "{}", t),
+                    None => write!(f, "None"),
                 }
-                return s;
             }
+            Type::SelfAlias => write!(f, "Self"),
+            Type::Error => write!(f, "Error"),
         }
     }
 }
 
+// a method provided by an impl block, dispatched statically on `ty`
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub ty: Type,
+    pub trait_name: Option<String>, // None for inherent methods
+    pub name: String,
+    pub receiver: Argument, // the self argument, which decides how the receiver is passed
+}
+
+impl Method {
+    pub fn function_name(&self) -> String {
+        method_function_name(&self.ty, self.trait_name.as_deref(), &self.name)
+    }
+}
+
+// methods are stored as ordinary functions under a name no identifier can clash with
+pub fn method_function_name(ty: &Type, trait_name: Option<&str>, method: &str) -> String {
+    match trait_name {
+        Some(trait_name) => format!("{}::{}::{}", ty.syntax(), trait_name, method),
+        None => format!("{}::{}", ty.syntax(), method),
+    }
+}
+
+// ordered maps, so the borrow an error blames does not depend on hash order
 #[derive(Debug, Clone)]
 pub struct TypeEnviroment {
-    gamma: HashMap<Variable, Slot<Type>>,
+    gamma: BTreeMap<Variable, Slot<Type>>,
+    traits: BTreeMap<String, Vec<MethodSignature>>,
+    methods: Vec<Method>,
+    moves: BTreeMap<Variable, Span>, // where each variable was last moved out of
+    immutable: BTreeSet<Variable>,   // bindings declared without mut
+    explicit_borrows: bool, // ref arguments must be borrowed with ref/mut ref at the call site
+}
+
+impl Default for TypeEnviroment {
+    fn default() -> TypeEnviroment {
+        return TypeEnviroment::new();
+    }
 }
 
 impl TypeEnviroment {
     pub fn new() -> TypeEnviroment {
         return TypeEnviroment {
-            gamma: HashMap::new(),
+            gamma: BTreeMap::new(),
+            traits: BTreeMap::new(),
+            methods: vec![],
+            moves: BTreeMap::new(),
+            immutable: BTreeSet::new(),
+            explicit_borrows: false,
         };
     }
 
-    pub fn get_partial(&self, key: &Variable) -> Result<Slot<Type>, String> {
+    pub fn require_explicit_borrows(&mut self) {
+        self.explicit_borrows = true;
+    }
+
+    pub fn explicit_borrows(&self) -> bool {
+        return self.explicit_borrows;
+    }
+
+    /// Every variable and function in scope with its type and lifetime.
+    pub fn bindings(&self) -> impl Iterator<Item = (&Variable, &Slot<Type>)> {
+        return self.gamma.iter();
+    }
+
+    /// Every declared trait, with the methods it requires.
+    pub fn traits(&self) -> &BTreeMap<String, Vec<MethodSignature>> {
+        return &self.traits;
+    }
+
+    /// Every method from trait and inherent impls, in declaration order.
+    pub fn methods(&self) -> &[Method] {
+        return &self.methods;
+    }
+
+    pub fn moved_at(&self, key: &Variable) -> Option<Span> {
+        return self.moves.get(key).copied();
+    }
+
+    pub(crate) fn set_mutable(&mut self, key: &Variable, mutable: bool) {
+        if mutable {
+            self.immutable.remove(key);
+        } else {
+            self.immutable.insert(key.clone());
+        }
+    }
+
+    pub fn get_trait(&self, name: &str) -> Option<&Vec<MethodSignature>> {
+        return self.traits.get(name);
+    }
+
+    pub(crate) fn insert_trait(&mut self, name: String, methods: Vec<MethodSignature>) {
+        self.traits.insert(name, methods);
+    }
+
+    pub fn has_impl(&self, trait_name: &str, ty: &Type) -> bool {
+        return self
+            .methods
+            .iter()
+            .any(|m| m.trait_name.as_deref() == Some(trait_name) && m.ty == *ty);
+    }
+
+    pub(crate) fn insert_method(&mut self, method: Method) {
+        self.methods.push(method);
+    }
+
+    pub fn lookup_methods(&self, ty: &Type, name: &str) -> Vec<&Method> {
+        return self
+            .methods
+            .iter()
+            .filter(|m| m.ty == *ty && m.name == name)
+            .collect();
+    }
+
+    pub fn get_partial(&self, key: &Variable) -> Result<Slot<Type>, TypeError> {
         return match self.gamma.get(key) {
             Some(t) => Ok(t.clone()),
-            None => Err("Type not found".to_string()),
+            None => Err(TypeError::VariableNotDefined(key.clone())),
         };
     }
 
-    pub fn get_atomic(&self, partial: Slot<Type>) -> Result<Slot<Type>, String> {
+    pub fn get_atomic(&self, partial: Slot<Type>) -> Result<Slot<Type>, TypeError> {
         return match partial.value.clone() {
             Type::Undefined(t) => {
-                println!("Getting atomic type of {:?}", partial.value.clone());
-                println!("Gamma:");
-                println!("{:#?}", self.gamma);
+                trace!(Typeck, "Getting atomic type of {:?}", partial.value);
+                trace!(Typeck, "Gamma: {:#?}", self.gamma);
                 // panic!("Type of {:?} is undefined, chances are it was moved", t);
-                Err(TypeError::TypeMoved(*t.clone()).to_string())
+                Err(TypeError::TypeMoved(*t.clone()))
             }
             Type::Box(t) => {
                 match *t {
                     Type::Undefined(nt) => {
-                        println!("Getting atomic type of {:?}", partial.value.clone());
-                        println!("Gamma:");
-                        println!("{:#?}", self.gamma);
+                        trace!(Typeck, "Getting atomic type of {:?}", partial.value);
+                        trace!(Typeck, "Gamma: {:#?}", self.gamma);
                         // panic!("Type of {:?} is undefined, chances are it was moved", nt);
-                        Err(TypeError::TypeMoved(*nt.clone()).to_string())
+                        Err(TypeError::TypeMoved(*nt.clone()))
                     }
                     _ => Ok(partial.clone()),
                 }
@@ -132,139 +343,197 @@ impl TypeEnviroment {
         };
     }
 
-    pub fn get(&self, key: &Variable) -> Result<Slot<Type>, String> {
+    pub fn get(&self, key: &Variable) -> Result<Slot<Type>, TypeError> {
         let s = self.get_partial(key)?;
-        return Ok(self.get_atomic(s)?);
+        return self.get_atomic(s);
     }
 
-    pub fn insert(&mut self, key: Variable, value: Type, lifetime: Lifetime) {
+    pub(crate) fn insert(&mut self, key: Variable, value: Type, lifetime: Lifetime) {
         self.gamma.insert(key, Slot { value, lifetime });
     }
 
+    pub(crate) fn remove(&mut self, key: &Variable) {
+        self.gamma.remove(key);
+    }
+
+    // a variable name that is not bound yet, for bindings the type checker adds itself
+    pub(crate) fn fresh(&self, prefix: &str) -> Variable {
+        let mut i = 0;
+        while self.gamma.contains_key(&format!("{}-{}", prefix, i)) {
+            i += 1;
+        }
+        return format!("{}-{}", prefix, i);
+    }
+
+    /// Describe a type in this environment. A reference argument borrows a slot
+    /// named after its function, so show what the slot holds instead.
+    pub fn describe(&self, ty: &Type) -> String {
+        if let Type::Reference { var, mutable } = ty {
+            if var.get_name().contains('-') {
+                if let Ok(slot) = self.get_partial(&var.get_name()) {
+                    let prefix = if *mutable { "mut ref" } else { "ref" };
+                    return format!("{} {}", prefix, slot.value.describe());
+                }
+            }
+        }
+        return ty.describe();
+    }
+
     pub fn dom(&self) -> Vec<String> {
         return self
             .gamma
             .keys()
             .filter(|s| match self.get_partial(s) {
-                Ok(t) => match t.value {
-                    Type::Function { .. } => false,
-                    _ => true,
-                },
+                Ok(t) => !matches!(t.value, Type::Function { .. }),
                 Err(_) => false,
             })
-            .map(|s| s.clone())
+            .cloned()
             .collect();
     }
 }
 
-pub fn root(gamma: &TypeEnviroment, lval: LVal) -> Result<LVal, String> {
+pub(crate) fn root(gamma: &TypeEnviroment, lval: LVal) -> Result<LVal, TypeError> {
     match lval.clone() {
         LVal::Variable { name, .. } => {
             // check if the variable is in the type environment
             if gamma.gamma.contains_key(&name) {
                 return Ok(lval);
             } else {
-                return Err(format!("Variable {:?} not found in type environment", name));
+                return Err(TypeError::VariableNotDefined(name));
             }
         }
-        LVal::Deref { var } => {
+        LVal::Deref { var, .. } => {
             // check if the variable is in the type environment
             if gamma.gamma.contains_key(&var.get_name()) {
-                return Ok(root(gamma, *var)?);
+                return root(gamma, *var);
             } else {
-                return Err(format!(
-                    "Variable {:?} not found in type environment",
-                    var.get_name()
-                ));
+                return Err(TypeError::VariableNotDefined(var.get_name()));
             }
         }
     }
 }
 
-pub fn write_prohibited(gamma: &TypeEnviroment, variable: LVal) -> bool {
-    println!("Checking if {} is borrowed", variable.get_name());
+pub(crate) fn write_prohibited(gamma: &TypeEnviroment, variable: LVal) -> Result<bool, TypeError> {
+    trace!(Typeck, "Checking if {} is borrowed", variable.get_name());
     // for each type in the type environment
-    let v2 = root(gamma, variable.clone()).unwrap();
+    let v2 = root(gamma, variable.clone())?;
 
     for (_, Slot { value: t, .. }) in gamma.gamma.iter() {
-        if contains(
-            t.clone(),
-            Type::Reference {
-                var: v2.clone(),
-                mutable: false,
-            },
-        ) || contains(
-            t.clone(),
-            Type::Reference {
-                var: v2.clone(),
-                mutable: true,
-            },
-        ) {
-            return true;
+        if let Some((var, _)) = borrowed(t) {
+            if var.same_place(&v2) {
+                return Ok(true);
+            }
         }
     }
-    return false;
+    return Ok(false);
 }
 
-pub fn contains(t: Type, t2: Type) -> bool {
+// where the live references to a variable were created, in source order
+pub(crate) fn borrow_sites(
+    gamma: &TypeEnviroment,
+    variable: LVal,
+    mutable_only: bool,
+) -> Vec<Span> {
+    // a variable that is not defined has no borrows to point at
+    let Ok(v2) = root(gamma, variable) else {
+        return vec![];
+    };
+    let mut sites = vec![];
+    for (_, Slot { value: t, .. }) in gamma.gamma.iter() {
+        if let Some((var, mutable)) = borrowed(t) {
+            if var.same_place(&v2) && (mutable || !mutable_only) {
+                sites.push(var.span());
+            }
+        }
+    }
+    // a reference copied into several variables was still created once
+    sites.sort_by_key(|span| span.start);
+    sites.dedup();
+    return sites;
+}
+
+// the variable a type holds a reference to, looking through boxes, and whether mutably
+pub(crate) fn borrowed(t: &Type) -> Option<(&LVal, bool)> {
     match t {
-        Type::Box(t1) => contains(*t1, t2),
-        _ => t == t2,
+        Type::Box(inner) => return borrowed(inner),
+        Type::Reference { var, mutable } => return Some((var, *mutable)),
+        _ => return None,
     }
 }
 
-pub fn read_prohibited(gamma: &TypeEnviroment, variable: LVal) -> bool {
+pub(crate) fn read_prohibited(gamma: &TypeEnviroment, variable: LVal) -> Result<bool, TypeError> {
     // for each type in the type environment
-    let v2 = root(gamma, variable.clone()).unwrap();
+    let v2 = root(gamma, variable.clone())?;
     for (_, Slot { value: t, .. }) in gamma.gamma.iter() {
-        if contains(
-            t.clone(),
-            Type::Reference {
-                var: v2.clone(),
-                mutable: true,
-            },
-        ) {
-            return true;
+        if let Some((var, true)) = borrowed(t) {
+            if var.same_place(&v2) {
+                return Ok(true);
+            }
         }
     }
-    return false;
+    return Ok(false);
 }
 
-pub fn move_var(
+pub(crate) fn move_var(
     mut gamma: TypeEnviroment,
     variable: LVal,
     lifetime: Lifetime,
-) -> Result<TypeEnviroment, String> {
+) -> Result<TypeEnviroment, TypeError> {
     let t = gamma.get_partial(&variable.get_name())?.value;
-    gamma.insert(variable.get_name(), undefine(variable, t), lifetime);
+    let undefined = undefine(variable.clone(), t)?;
+    gamma.moves.insert(variable.get_name(), variable.span());
+    gamma.insert(variable.get_name(), undefined, lifetime);
     Ok(gamma)
 }
 
-pub fn undefine(lval: LVal, t: Type) -> Type {
-    match (lval.clone(), t.clone()) {
-        (LVal::Variable { .. }, _) => {
-            return Type::Undefined(Box::new(t));
+// the type once `lval` is moved out of a variable of type `t`, which can be done
+// through boxes but not through references, as the value belongs to someone else
+pub(crate) fn undefine(lval: LVal, t: Type) -> Result<Type, TypeError> {
+    match (lval.clone(), t) {
+        (LVal::Variable { .. }, t) => {
+            return Ok(Type::Undefined(Box::new(t)));
         }
-        (LVal::Deref { var }, Type::Box(t)) => {
-            return Type::Box(Box::new(undefine(*var, *t)));
+        (LVal::Deref { var, .. }, Type::Box(t)) => {
+            return Ok(Type::Box(Box::new(undefine(*var, *t)?)));
         }
-        _ => {
-            panic!(
-                "Tried to undefine something that you should not have lval: {:?}, type: {:?}",
-                lval.clone(),
-                t.clone()
-            );
+        (LVal::Deref { .. }, Type::Error) => return Ok(Type::Error),
+        (LVal::Deref { .. }, _) => return Err(TypeError::MoveOutOfReference(lval.to_string())),
+    }
+}
+
+// the binding declared without mut that assigning to `variable` would change, writes
+// through a box change the binding that owns it, writes through a reference do not
+pub(crate) fn immutable_binding(gamma: &TypeEnviroment, variable: &LVal) -> Option<Variable> {
+    let name = variable.get_name();
+    let owned = match variable {
+        LVal::Variable { .. } => true,
+        LVal::Deref { var, .. } => {
+            matches!(**var, LVal::Variable { .. })
+                && matches!(
+                    gamma.get_partial(&name),
+                    Ok(Slot {
+                        value: Type::Box(_),
+                        ..
+                    })
+                )
         }
+    };
+    if owned && gamma.immutable.contains(&name) {
+        return Some(name);
     }
+    return None;
 }
 
-pub fn dom(gamma: &TypeEnviroment) -> Vec<String> {
-    return gamma.gamma.keys().map(|s| s.clone()).collect();
+pub(crate) fn dom(gamma: &TypeEnviroment) -> Vec<String> {
+    return gamma.gamma.keys().cloned().collect();
 }
 
-pub fn shape_compatible(gamma: &TypeEnviroment, t1: &Type, t2: &Type) -> bool {
+#[allow(clippy::only_used_in_recursion)]
+pub(crate) fn shape_compatible(gamma: &TypeEnviroment, t1: &Type, t2: &Type) -> bool {
     match (t1, t2) {
-        (Type::Numeric, Type::Numeric) => true,
+        (Type::Error, _) | (_, Type::Error) => true,
+        (Type::Numeric(k1), Type::Numeric(k2)) => k1 == k2,
+        (Type::Bool, Type::Bool) => true,
         (Type::Box(bt1), Type::Box(bt2)) => shape_compatible(gamma, bt1, bt2),
         (Type::Reference { mutable: m1, .. }, Type::Reference { mutable: m2, .. }) => m1 == m2,
         (Type::Undefined(nt1), t2) => shape_compatible(gamma, nt1, t2),
@@ -273,48 +542,46 @@ pub fn shape_compatible(gamma: &TypeEnviroment, t1: &Type, t2: &Type) -> bool {
     }
 }
 
-pub fn _mut(gamma: &TypeEnviroment, variable: LVal) -> bool {
-    let t = gamma.get(&variable.get_name()).unwrap().value;
+pub(crate) fn _mut(gamma: &TypeEnviroment, variable: LVal) -> Result<bool, TypeError> {
+    let t = gamma.get(&variable.get_name())?.value;
     match (variable, t) {
-        (LVal::Deref { var }, Type::Box(_)) => _mut(gamma, *var),
+        (LVal::Deref { var, .. }, Type::Box(_)) => _mut(gamma, *var),
         (LVal::Deref { .. }, Type::Reference { mutable, var: rvar }) => {
             if mutable {
                 // TODO: check if this should be var or rvar, my brain is not working
                 return _mut(gamma, rvar);
             } else {
-                return false;
+                return Ok(false);
             }
         }
-        (LVal::Variable { .. }, Type::Reference { mutable: false, .. }) => false,
-        _ => true,
+        (LVal::Variable { .. }, Type::Reference { mutable: false, .. }) => Ok(false),
+        _ => Ok(true),
     }
 }
 
-pub fn update(
+pub(crate) fn update(
     gamma: TypeEnviroment,
     lv: &LVal,
     t1: Type,
     t2: Type,
-) -> Result<(TypeEnviroment, Type), String> {
+) -> Result<(TypeEnviroment, Type), TypeError> {
     match lv {
         LVal::Variable { .. } => {
             return Ok((gamma, t2));
         }
-        LVal::Deref { var } => match t1.clone() {
+        LVal::Deref { var, .. } => match t1.clone() {
             Type::Box(t) => {
                 let (gamma2, t3) = update(gamma, var, *t, t2)?;
                 return Ok((gamma2, Type::Box(Box::new(t3))));
             }
             Type::Reference { var: rvar, mutable } => {
                 if !mutable {
-                    return Err(format!(
-                        "Error updating reference: variable {:?} is not mutable",
-                        rvar
-                    ));
+                    return Err(TypeError::WriteThroughImmutableRef(rvar.to_string()));
                 }
                 let g3 = write(gamma, rvar, t2);
                 return Ok((g3?, t1));
             }
+            Type::Error => return Ok((gamma, t1)),
             _ => {
                 panic!("This should not happen");
             }
@@ -322,7 +589,11 @@ pub fn update(
     }
 }
 
-pub fn write(gamma: TypeEnviroment, variable: LVal, t1: Type) -> Result<TypeEnviroment, String> {
+pub(crate) fn write(
+    gamma: TypeEnviroment,
+    variable: LVal,
+    t1: Type,
+) -> Result<TypeEnviroment, TypeError> {
     let Slot {
         value: t2,
         lifetime: l,
@@ -0,0 +1,215 @@
+#![allow(clippy::needless_return)]
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn muse(args: &[&str], stdin: &str) -> (i32, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lightweight-muse"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    return (output.status.code().unwrap(), stdout);
+}
+
+#[test]
+fn exit_codes() {
+    assert_eq!(0, muse(&["run", "tests/good/print_assert.mu"], "").0);
+    assert_eq!(0, muse(&["tests/good/print_assert.mu"], "").0);
+    assert_eq!(1, muse(&[], "").0);
+    assert_eq!(1, muse(&["run", "--bogus", "x.mu"], "").0);
+    assert_eq!(1, muse(&["run", "tests/no_such_file.mu"], "").0);
+    assert_eq!(2, muse(&["parse", "tests/bad/parse_errors.mu"], "").0);
+    assert_eq!(3, muse(&["check", "tests/bad/multiple_errors.mu"], "").0);
+    assert_eq!(
+        3,
+        muse(
+            &["check", "--deny", "unused_move", "tests/good/lints.mu"],
+            ""
+        )
+        .0
+    );
+    assert_eq!(4, muse(&["run", "tests/bad/int_overflow.mu"], "").0);
+}
+
+#[test]
+fn multiple_files() {
+    // every file is processed, and the first failure decides the exit code
+    let (code, stdout) = muse(
+        &[
+            "run",
+            "tests/bad/multiple_errors.mu",
+            "tests/good/print_assert.mu",
+            "tests/bad/int_overflow.mu",
+        ],
+        "",
+    );
+    assert_eq!(3, code);
+    assert_eq!("2\n5\n5\n5\n5\n", stdout);
+}
+
+#[test]
+fn stdin() {
+    let (code, stdout) = muse(&["run", "-"], "let x = 41\nprint(x + 1)\n");
+    assert_eq!(0, code);
+    assert_eq!("42\n", stdout);
+    assert_eq!(3, muse(&["check", "-"], "let x = 1\nx = 2\n").0);
+}
+
+#[test]
+fn fmt() {
+    let (code, stdout) = muse(&["fmt", "-"], "let   x=1\n\n\nprint( x )");
+    assert_eq!(0, code);
+    assert_eq!("let x = 1\n\nprint(x)\n", stdout);
+
+    // --check only reports, and fails on anything it would change
+    assert_eq!(
+        (0, String::new()),
+        muse(&["fmt", "--check", "-"], "let x = 1\n")
+    );
+    assert_eq!(
+        (5, String::new()),
+        muse(&["fmt", "--check", "-"], "let x=1\n")
+    );
+    assert_eq!(2, muse(&["fmt", "--check", "-"], "let = 1\n").0);
+}
+
+#[test]
+fn logging() {
+    // quiet unless a target is turned on, and then only that target
+    let (_, stdout) = muse(&["run", "-"], "let x = box 1\n");
+    assert_eq!("", stdout);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lightweight-muse"))
+        .args(["run", "--log", "eval=debug", "-"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"let x = box 1\n")
+        .unwrap();
+    let stderr = String::from_utf8(child.wait_with_output().unwrap().stderr).unwrap();
+    assert!(stderr.contains("[debug eval] Reducing box"), "{}", stderr);
+    assert!(!stderr.contains("typeck"), "{}", stderr);
+}
+
+#[test]
+fn debug() {
+    let dir = std::env::temp_dir().join(format!("muse-debug-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("program.mu");
+    std::fs::write(
+        &file,
+        "fn f(a: int) : int {\n    a + 1\n}\nprint(f(1))\nassert(false)\n",
+    )
+    .unwrap();
+    let file = file.to_str().unwrap();
+
+    // paused before the first statement, then at the breakpoint, then at the error
+    let (code, stdout) = muse(
+        &["debug", "--break", "f", file],
+        "continue\nnext\ncontinue\n",
+    );
+    assert_eq!(4, code);
+    assert!(
+        stdout.starts_with("Paused at line 1 in <main>\n"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Paused at line 2 in f\n   2 |     a + 1\nStack:\n    f at line 2: a -> l-1\n    <main> at line 4\n"), "{}", stdout);
+    assert!(
+        stdout.contains("2\nPaused at line 5 in <main>"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("Assertion failed"), "{}", stdout);
+
+    // quitting early is not a failure
+    assert_eq!(0, muse(&["debug", file], "step\nquit\n").0);
+    assert_eq!(1, muse(&["debug", "-"], "").0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dot() {
+    let dir = std::env::temp_dir().join(format!("muse-dot-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("program.mu");
+    std::fs::write(
+        &file,
+        "fn f(a: int) {\n    print(a)\n}\nlet x = box 1\nf(*x)\n",
+    )
+    .unwrap();
+    let file = file.to_str().unwrap();
+    let graphs = dir.join("program.dot");
+    let graphs = graphs.to_str().unwrap();
+
+    // one graph after each statement
+    assert_eq!(
+        (0, "1\n".to_string()),
+        muse(&["run", "--dot", graphs, file], "")
+    );
+    let written = std::fs::read_to_string(graphs).unwrap();
+    assert_eq!(3, written.matches("digraph").count(), "{}", written);
+    assert!(written.contains("digraph \"after line 4\""), "{}", written);
+
+    // or only at the points asked for, inside calls too, in the same single run
+    assert_eq!(
+        (0, "1\n".to_string()),
+        muse(&["run", "--dot", graphs, "--dot-at", "f", file], "")
+    );
+    let written = std::fs::read_to_string(graphs).unwrap();
+    assert_eq!(1, written.matches("digraph").count(), "{}", written);
+    assert!(
+        written.contains("digraph \"before line 2 in f\""),
+        "{}",
+        written
+    );
+    assert!(
+        written.contains("subgraph cluster_frame_1 {\n        label=\"f\""),
+        "{}",
+        written
+    );
+
+    assert_eq!(1, muse(&["run", "--dot-at", "f", file], "").0);
+    assert_eq!(1, muse(&["run", "--dot", graphs, file, file], "").0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn timeline() {
+    let dir = std::env::temp_dir().join(format!("muse-timeline-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let page = dir.join("program.html");
+    let page = page.to_str().unwrap();
+
+    let (code, stdout) = muse(
+        &["timeline", "--html", page, "-"],
+        "let x = box 1\nlet y = x\n",
+    );
+    assert_eq!(0, code);
+    assert_eq!(
+        "1 | let x = box 1\n2 | let y = x\n  |         ^ move out of x\n",
+        stdout
+    );
+    let written = std::fs::read_to_string(page).unwrap();
+    assert!(written.starts_with("<!DOCTYPE html>"), "{}", written);
+    assert!(written.contains("move out of x"), "{}", written);
+
+    assert_eq!(3, muse(&["timeline", "-"], "let x = 1\nx = 2\n").0);
+    assert_eq!(1, muse(&["timeline", "--html", page, "-", "-"], "").0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,59 @@
+#![allow(clippy::needless_return)]
+
+// replay recorded DAP sessions against the debug adapter binary. In a transcript,
+// `-> ` lines are sent by the client and `<- ` lines are what the adapter must send
+// back, in order. Paths in them are relative to the crate root, where tests run.
+
+use std::io::BufReader;
+use std::process::{Command, Stdio};
+
+use lightweight_muse::json::{self, Json};
+
+fn replay(transcript: &str) {
+    let mut sent = vec![];
+    let mut expected = vec![];
+    for line in transcript.lines() {
+        if let Some(message) = line.strip_prefix("-> ") {
+            sent.push(Json::parse(message).unwrap());
+        } else if let Some(message) = line.strip_prefix("<- ") {
+            expected.push(Json::parse(message).unwrap());
+        }
+    }
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_muse-dap"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for message in &sent {
+        json::write_frame(&mut stdin, message).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+
+    let mut stdout = BufReader::new(output.stdout.as_slice());
+    let mut received = vec![];
+    while let Some(body) = json::read_frame(&mut stdout).unwrap() {
+        received.push(Json::parse(&body).unwrap());
+    }
+    for (i, (expected, received)) in expected.iter().zip(&received).enumerate() {
+        assert_eq!(expected, received, "message {} differs", i + 1);
+    }
+    assert_eq!(expected.len(), received.len());
+}
+
+#[test]
+fn transcripts() {
+    let mut replayed = 0;
+    for entry in std::fs::read_dir("tests/dap").unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|e| e == "txt") {
+            println!("replaying {}", path.display());
+            replay(&std::fs::read_to_string(&path).unwrap());
+            replayed += 1;
+        }
+    }
+    assert!(replayed > 0);
+}
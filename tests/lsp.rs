@@ -0,0 +1,320 @@
+#![allow(clippy::needless_return)]
+
+// drive the language server binary with JSON-RPC messages, as an editor would
+
+use std::io::BufReader;
+use std::process::{Command, Stdio};
+
+use lightweight_muse::json::{self, object, Json};
+
+const URI: &str = "file:///program.mu";
+
+const PROGRAM: &str = "\
+fn add_one(ref n: int) : int {
+    *n + 1
+}
+let a = 1
+let b = add_one(a)
+let mut x = box 1
+let r = ref a
+let y = x
+x = box 3
+impl int {
+    fn double(self) : int {
+        self + self
+    }
+}
+";
+
+// send every message, then return the exit code and everything the server sent
+fn session(messages: &[Json]) -> (i32, Vec<Json>) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_muse-lsp"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    for message in messages {
+        json::write_frame(&mut stdin, message).unwrap();
+    }
+    drop(stdin);
+    let output = child.wait_with_output().unwrap();
+    let mut stdout = BufReader::new(output.stdout.as_slice());
+    let mut replies = vec![];
+    while let Some(body) = json::read_frame(&mut stdout).unwrap() {
+        replies.push(Json::parse(&body).unwrap());
+    }
+    return (output.status.code().unwrap(), replies);
+}
+
+fn request(id: usize, method: &str, params: Json) -> Json {
+    return object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ]);
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    return object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ]);
+}
+
+fn open(uri: &str, text: &str) -> Json {
+    return notification(
+        "textDocument/didOpen",
+        object([(
+            "textDocument",
+            object([
+                ("uri", uri.into()),
+                ("languageId", "muse".into()),
+                ("version", 1usize.into()),
+                ("text", text.into()),
+            ]),
+        )]),
+    );
+}
+
+fn at(id: usize, method: &str, line: usize, character: usize) -> Json {
+    return request(
+        id,
+        method,
+        object([
+            ("textDocument", object([("uri", URI.into())])),
+            (
+                "position",
+                object([("line", line.into()), ("character", character.into())]),
+            ),
+        ]),
+    );
+}
+
+fn result(replies: &[Json], id: usize) -> &Json {
+    let reply = replies
+        .iter()
+        .find(|r| r.get("id").as_u64() == Some(id as u64))
+        .unwrap_or_else(|| panic!("no reply to {}", id));
+    return reply.get("result");
+}
+
+fn hover_text(replies: &[Json], id: usize) -> &str {
+    return result(replies, id).at("contents.value").as_str().unwrap();
+}
+
+fn range(line: usize, start: usize, end: usize) -> Json {
+    let position =
+        |character: usize| object([("line", line.into()), ("character", character.into())]);
+    return object([("start", position(start)), ("end", position(end))]);
+}
+
+fn start() -> Vec<Json> {
+    return vec![
+        request(0, "initialize", object([("capabilities", object([]))])),
+        notification("initialized", object([])),
+    ];
+}
+
+fn stop(mut messages: Vec<Json>) -> Vec<Json> {
+    messages.push(request(99, "shutdown", Json::Null));
+    messages.push(notification("exit", Json::Null));
+    return messages;
+}
+
+#[test]
+fn diagnostics() {
+    let source = "let x = box 1\nlet y = x\nprint(*x)\n";
+    let mut messages = start();
+    messages.push(open(URI, source));
+    messages.push(notification(
+        "textDocument/didChange",
+        object([
+            (
+                "textDocument",
+                object([("uri", URI.into()), ("version", 2usize.into())]),
+            ),
+            (
+                "contentChanges",
+                vec![object([("text", "let x = box 1\nprint(*x)\n".into())])].into(),
+            ),
+        ]),
+    ));
+    let (code, replies) = session(&stop(messages));
+    assert_eq!(0, code);
+    assert_eq!(
+        Some(true),
+        result(&replies, 0)
+            .at("capabilities.hoverProvider")
+            .as_bool()
+    );
+
+    let published: Vec<&Json> = replies
+        .iter()
+        .filter(|r| r.get("method").as_str() == Some("textDocument/publishDiagnostics"))
+        .map(|r| r.at("params.diagnostics"))
+        .collect();
+    assert_eq!(2, published.len());
+
+    // the use after the move is an error, which points back at the move
+    let [moved] = published[0].as_array() else {
+        panic!("expected one diagnostic: {}", published[0]);
+    };
+    assert_eq!(&range(2, 7, 8), moved.get("range"));
+    assert_eq!(Some(1), moved.get("severity").as_u64());
+    assert!(moved.get("code").as_str().unwrap().starts_with('M'));
+    let related = &moved.get("relatedInformation").as_array()[0];
+    assert_eq!(&range(1, 8, 9), related.at("location.range"));
+    assert_eq!(Some("value moved here"), related.get("message").as_str());
+
+    // fixing it clears the diagnostics
+    assert_eq!(&Json::Array(vec![]), published[1]);
+}
+
+#[test]
+fn hover() {
+    let mut messages = start();
+    messages.push(open(URI, PROGRAM));
+    messages.push(at(1, "textDocument/hover", 7, 8)); // y = x, before the move
+    messages.push(at(2, "textDocument/hover", 8, 0)); // x after the move
+    messages.push(at(3, "textDocument/hover", 6, 4)); // r borrows a
+    messages.push(at(4, "textDocument/hover", 1, 5)); // a ref argument
+    messages.push(at(5, "textDocument/hover", 4, 10)); // a function
+    messages.push(at(6, "textDocument/hover", 11, 9)); // self in a method
+    messages.push(at(7, "textDocument/hover", 4, 3)); // not an identifier
+    let (_, replies) = session(&stop(messages));
+
+    assert_eq!("```muse\nx: box int\n```", hover_text(&replies, 1));
+    assert_eq!(
+        "```muse\nx: box int (moved)\n```\n\nMoved out at line 8",
+        hover_text(&replies, 2)
+    );
+    assert_eq!(&range(8, 0, 1), result(&replies, 2).get("range"));
+    assert_eq!("```muse\nr: ref a\n```", hover_text(&replies, 3));
+    assert_eq!("```muse\nn: ref int\n```", hover_text(&replies, 4));
+    assert_eq!(
+        "```muse\nfn add_one(ref n : int) : int\n```",
+        hover_text(&replies, 5)
+    );
+    assert_eq!("```muse\nself: int\n```", hover_text(&replies, 6));
+    assert!(result(&replies, 7).is_null());
+}
+
+#[test]
+fn definition() {
+    let mut messages = start();
+    messages.push(open(URI, PROGRAM));
+    messages.push(at(1, "textDocument/definition", 4, 16)); // a
+    messages.push(at(2, "textDocument/definition", 4, 8)); // add_one
+    messages.push(at(3, "textDocument/definition", 1, 5)); // n
+    messages.push(at(4, "textDocument/definition", 11, 16)); // self
+    let (_, replies) = session(&stop(messages));
+
+    let location =
+        |line, start, end| object([("uri", URI.into()), ("range", range(line, start, end))]);
+    assert_eq!(&location(3, 4, 5), result(&replies, 1));
+    assert_eq!(&location(0, 3, 10), result(&replies, 2));
+    assert_eq!(&location(0, 15, 16), result(&replies, 3));
+    assert_eq!(&location(10, 14, 18), result(&replies, 4));
+}
+
+#[test]
+fn document_symbols() {
+    let mut messages = start();
+    messages.push(open(URI, PROGRAM));
+    messages.push(request(
+        1,
+        "textDocument/documentSymbol",
+        object([("textDocument", object([("uri", URI.into())]))]),
+    ));
+    let (_, replies) = session(&stop(messages));
+
+    let symbols = result(&replies, 1).as_array();
+    let names: Vec<(&str, u64)> = symbols
+        .iter()
+        .map(|s| {
+            (
+                s.get("name").as_str().unwrap(),
+                s.get("kind").as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(vec![("add_one", 12), ("impl int", 5)], names);
+    assert_eq!(&range(0, 3, 10), symbols[0].get("selectionRange"));
+    assert_eq!(
+        Some("fn add_one(ref n : int) : int"),
+        symbols[0].get("detail").as_str()
+    );
+    let method = &symbols[1].get("children").as_array()[0];
+    assert_eq!(Some("double"), method.get("name").as_str());
+    assert_eq!(Some(6), method.get("kind").as_u64());
+}
+
+#[test]
+fn protocol() {
+    // unknown requests get an error, and exiting without shutdown is an error
+    let mut messages = start();
+    messages.push(request(1, "textDocument/rename", object([])));
+    messages.push(notification("exit", Json::Null));
+    let (code, replies) = session(&messages);
+    assert_eq!(1, code);
+    let error = replies
+        .iter()
+        .find(|r| r.get("id").as_u64() == Some(1))
+        .unwrap()
+        .get("error");
+    assert_eq!(Some(-32601.0), error.get("code").as_f64());
+}
+
+#[test]
+fn syntax_errors() {
+    // a broken line is reported, and the statements around it still answer
+    let source = PROGRAM.replacen("let a = 1\n", "let = (\nlet a = 1\n", 1);
+    let mut messages = start();
+    messages.push(open(URI, &source));
+    messages.push(at(1, "textDocument/hover", 8, 4)); // y = x
+    messages.push(at(2, "textDocument/definition", 5, 16)); // a
+    messages.push(request(
+        3,
+        "textDocument/documentSymbol",
+        object([("textDocument", object([("uri", URI.into())]))]),
+    ));
+    let (_, replies) = session(&stop(messages));
+
+    let published = replies
+        .iter()
+        .find(|r| r.get("method").as_str() == Some("textDocument/publishDiagnostics"))
+        .unwrap();
+    let [error] = published.at("params.diagnostics").as_array() else {
+        panic!("expected one diagnostic: {}", published);
+    };
+    assert_eq!(Some(3), error.at("range.start.line").as_u64());
+
+    assert_eq!("```muse\ny: box int\n```", hover_text(&replies, 1));
+    let location = object([("uri", URI.into()), ("range", range(4, 4, 5))]);
+    assert_eq!(&location, result(&replies, 2));
+    assert_eq!(2, result(&replies, 3).as_array().len());
+}
+
+#[test]
+fn undefined_borrow() {
+    // a typo in a borrowed name is a diagnostic, and the server keeps answering
+    let mut messages = start();
+    messages.push(open(URI, "let x = 1\nlet y = ref z\n"));
+    messages.push(at(1, "textDocument/hover", 0, 4));
+    let (code, replies) = session(&stop(messages));
+    assert_eq!(0, code);
+
+    let published = replies
+        .iter()
+        .find(|r| r.get("method").as_str() == Some("textDocument/publishDiagnostics"))
+        .unwrap();
+    let [error] = published.at("params.diagnostics").as_array() else {
+        panic!("expected one diagnostic: {}", published);
+    };
+    assert_eq!(Some("M0015"), error.get("code").as_str());
+    assert_eq!(&range(1, 8, 13), error.get("range"));
+    assert_eq!("```muse\nx: int\n```", hover_text(&replies, 1));
+}